@@ -1,19 +1,34 @@
-use nalgebra::{DMatrix, DVector};
-use rand_distr::{Distribution, Normal};
+use nalgebra::{Complex, DMatrix, DVector};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Normal, StandardNormal};
 use finitediff::FiniteDiff;
-use liblbfgs::lbfgs;
-use super::utils::{pacf, residuals, mean};
+use super::error::NefeleError;
+use super::summary::Summary;
+use super::utils::{acf, aic, bic, diff, differenced_length, undiff_forecast, ar_dl_path, ar_dl_rho_cov, ar_from_optimizer, lag_matrix, ar_to_optimizer, ar_roots, forecast, normal_quantile, initial_ar_from_pacf, residuals, r_squared, simulate_stationary, css_objective, mean, input_quality, winsorize, OptimizerConfig, OrderCriterion};
 
 /// AutoRegressive struct represents an autoregressive model.
 #[derive(Debug, Clone)]
 pub struct AutoRegressive {
-    pub phi: Vec<f64>,           // AR coefficients
-    sigma_squared: f64,          // Variance of the model
-    aic: f64,                    // AIC (Akaike Information Criterion) value
-    bic: f64                     // BIC (Bayesian Information Criterion) value
+    pub phi: Vec<f64>,                     // AR coefficients
+    pub intercept: f64,                    // Intercept, estimated only by Yule-Walker and CSS
+    pub diff: usize,                       // Differencing order, set only by fit_differenced
+    pub reflection_coefficients: Vec<f64>, // Reflection (PARCOR) coefficients, set only by Yule-Walker and Burg
+    standard_errors: Option<Vec<f64>>,     // Standard errors of the AR coefficients, set only by OLS
+    phi_covariance: Option<DMatrix<f64>>,  // Covariance matrix of the AR coefficients, set only by OLS
+    sigma_squared: f64,                    // Variance of the model
+    aic: f64,                              // AIC (Akaike Information Criterion) value
+    bic: f64,                              // BIC (Bayesian Information Criterion) value
+    n_params: usize,                       // Number of free parameters of the last fit
+    rls_p: Option<DMatrix<f64>>,           // Inverse information matrix of the recursive least squares updates
+    pub enforce_stationarity: bool,        // Whether the CSS fit searches only stationary AR coefficients
+    pub optimizer: OptimizerConfig,        // Settings of the L-BFGS minimizer of the CSS fit
+    pub winsorize: Option<(f64, f64)>,     // Lower and upper percentiles the data are winsorized to before fitting, if any
+    ols_solver: Option<OLSSolver>,         // Solver used by the last OLS fit
+    fitted: bool                           // Whether `fit` has been called
 }
 
 /// ARMethod represents different methods for fitting an autoregressive model.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ARMethod {
     OLS,        // Ordinary Least Squares
     YWALKER,    // Yule-Walker Method
@@ -21,29 +36,318 @@ pub enum ARMethod {
     CSS         // Conditional Sum of Squares
 }
 
+/// OLSSolver represents the ways the OLS normal equations are solved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OLSSolver {
+    Cholesky,   // Cholesky decomposition of X'X
+    QR          // QR decomposition of X, used when X'X is numerically singular
+}
+
 /// ARCriterion represents criteria for selecting the order of the autoregressive model.
 pub enum ARCriterion {
     AIC,    // Akaike Information Criterion
     BIC     // Bayesian Information Criterion
 }
 
+impl OrderCriterion for ARCriterion {
+    fn evaluate(&self, n: usize, rss: f64, k: usize) -> f64 {
+        match self {
+            ARCriterion::AIC => aic(n, rss, k),
+            ARCriterion::BIC => bic(n, rss, k),
+        }
+    }
+}
+
 impl AutoRegressive {
     /// Creates a new AutoRegressive struct with default values.
     pub fn new() -> AutoRegressive {
         AutoRegressive {
             phi: vec![0.0; 1],
+            intercept: 0.0,
+            diff: 0,
+            reflection_coefficients: Vec::new(),
+            standard_errors: None,
+            phi_covariance: None,
             sigma_squared: 0.0,
             aic: 0.0,
-            bic: 0.0
+            bic: 0.0,
+            n_params: 0,
+            rls_p: None,
+            enforce_stationarity: false,
+            optimizer: OptimizerConfig::default(),
+            winsorize: None,
+            ols_solver: None,
+            fitted: false
+        }
+    }
+
+    /// Builds an AR(`order`) model from autocorrelations instead of data, solving the
+    /// Yule-Walker equations by the Levinson-Durbin recursion. `rho` holds the autocorrelations
+    /// from lag 0 on and `cov0` the variance of the process; the order is capped at
+    /// `rho.len() - 1`. The innovation variance and the reflection coefficients are those of
+    /// the recursion.
+    pub fn from_acf(rho: &[f64], cov0: f64, order: usize) -> AutoRegressive {
+        let mut model = AutoRegressive::new();
+        model.fit_yule_walker_from_acf(rho, cov0, order);
+        model
+    }
+
+    /// Builds an AR model from known coefficients `phi` and innovation variance `sigma2`, e.g.
+    /// estimated elsewhere, with a zero intercept. Returns an `InvalidValue` error if a value
+    /// is not finite, `sigma2` is not positive or the coefficients are not stationary, i.e.
+    /// an inverse root of the AR polynomial lies on or outside the unit circle.
+    pub fn try_from_coefficients(phi: Vec<f64>, sigma2: f64) -> Result<AutoRegressive, NefeleError> {
+        if phi.iter().any(|x| !x.is_finite()) || !(sigma2.is_finite() && sigma2 > 0.0) {
+            return Err(NefeleError::InvalidValue(format!(
+                "coefficients must be finite and the variance positive, found phi {:?} and sigma2 {}", phi, sigma2
+            )));
         }
+        if ar_roots(&phi).iter().any(|root| root.norm_sqr() >= 1.0) {
+            return Err(NefeleError::InvalidValue(format!("AR coefficients {:?} are not stationary", phi)));
+        }
+
+        let mut model = AutoRegressive::new();
+        model.n_params = phi.len() + 1;
+        model.phi = phi;
+        model.sigma_squared = sigma2;
+        model.fitted = true;
+        Ok(model)
+    }
+
+    /// Fits an AR(`order`) by Yule-Walker from pre-computed autocorrelations, so that the
+    /// sample ACF can be computed once and reused across several orders. `rho` holds the
+    /// autocorrelations from lag 0 on and `cov0` the variance of the process; the order is
+    /// capped at `rho.len() - 1`. The intercept is zero, and the information criteria are
+    /// left at zero since the number of observations is not known.
+    pub fn fit_yule_walker_from_acf(&mut self, rho: &[f64], cov0: f64, order: usize) {
+        let (phi, sigma_squared, reflections) = ar_dl_rho_cov(&rho.to_vec(), cov0, Some(order));
+
+        self.intercept = 0.0;
+        self.standard_errors = None;
+        self.phi_covariance = None;
+        self.diff = 0;
+        self.ols_solver = None;
+        self.rls_p = None;
+        self.aic = 0.0;
+        self.bic = 0.0;
+        self.n_params = phi.len() + 1;
+        self.phi = phi;
+        self.sigma_squared = sigma_squared;
+        self.reflection_coefficients = reflections;
+        self.fitted = true;
     }
 
     /// Prints a summary of the autoregressive model.
     pub fn summary(&self) {
-        println!(
-            "coefficients: {:?} \nsigma^2: {}",
-            self.phi, self.sigma_squared
-        )
+        println!("{}", self.to_summary())
+    }
+
+    /// Collects the estimates of the autoregressive model in a Summary struct.
+    pub fn to_summary(&self) -> Summary {
+        Summary {
+            model: format!("AR({})", self.phi.len()),
+            fitted: self.fitted,
+            phi: self.phi.clone(),
+            phi_std_errors: self.standard_errors.clone(),
+            diff: None,
+            theta: Vec::new(),
+            intercept: self.intercept,
+            sigma_squared: self.sigma_squared,
+            aic: Some(self.aic),
+            bic: Some(self.bic)
+        }
+    }
+
+    /// Returns the number of free parameters, intercept and innovation variance included.
+    pub fn n_params(&self) -> usize {
+        self.n_params
+    }
+
+    /// Returns the standard errors of the AR coefficients, `None` if the last fit was not
+    /// by OLS, the only method which provides them.
+    pub fn standard_errors(&self) -> Option<&[f64]> {
+        self.standard_errors.as_deref()
+    }
+
+    /// Returns the estimated covariance matrix of the AR coefficients, `sigma^2 (X'X)^-1`,
+    /// available only after an OLS fit.
+    pub fn phi_covariance(&self) -> Option<&DMatrix<f64>> {
+        self.phi_covariance.as_ref()
+    }
+
+    /// Returns the solver used by the last OLS fit, `None` if the last fit used another method.
+    pub fn ols_solver(&self) -> Option<OLSSolver> {
+        self.ols_solver
+    }
+
+    /// Returns a copy of the model holding only its estimates and settings, without the
+    /// recursive least squares state of `update_weighted`, for cheap copies of a fitted
+    /// template. The copy forecasts like the original; its first weighted update rebuilds the
+    /// state from the history, as after a fresh fit.
+    pub fn clone_parameters(&self) -> Self {
+        AutoRegressive {
+            phi: self.phi.clone(),
+            intercept: self.intercept,
+            diff: self.diff,
+            reflection_coefficients: self.reflection_coefficients.clone(),
+            standard_errors: self.standard_errors.clone(),
+            phi_covariance: self.phi_covariance.clone(),
+            sigma_squared: self.sigma_squared,
+            aic: self.aic,
+            bic: self.bic,
+            n_params: self.n_params,
+            rls_p: None,
+            enforce_stationarity: self.enforce_stationarity,
+            optimizer: self.optimizer,
+            winsorize: self.winsorize,
+            ols_solver: self.ols_solver,
+            fitted: self.fitted
+        }
+    }
+
+    /// Returns whether the model holds recursive least squares state from `update_weighted`.
+    pub fn has_update_state(&self) -> bool {
+        self.rls_p.is_some()
+    }
+
+    /// Returns the AR coefficient at the 1-based `lag`, or 0.0 for lag 0 and for lags beyond
+    /// the AR order, so that models of different orders can be queried uniformly.
+    pub fn phi_at(&self, lag: usize) -> f64 {
+        lag.checked_sub(1).and_then(|i| self.phi.get(i)).copied().unwrap_or(0.0)
+    }
+
+    /// Computes the in-sample residuals of the fitted model on the provided data.
+    pub fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(residuals(data, self.intercept, &self.phi, &[]))
+    }
+
+    /// Computes the R^2 of the one-step predictions of the fitted model on the provided data,
+    /// the share of the variance explained relative to a mean-only model, after the warm-up.
+    /// It can be negative for a model fitted without intercept on data with a non-zero mean.
+    pub fn r_squared(&self, data: &[f64]) -> Result<f64, NefeleError> {
+        let residuals = self.residuals(data)?;
+        Ok(r_squared(data, &residuals, self.phi.len(), self.phi.len()).0)
+    }
+
+    /// Computes the R^2 of `r_squared` adjusted for the number of estimated coefficients.
+    pub fn adjusted_r_squared(&self, data: &[f64]) -> Result<f64, NefeleError> {
+        let residuals = self.residuals(data)?;
+        Ok(r_squared(data, &residuals, self.phi.len(), self.phi.len()).1)
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data. After `fit_differenced`, the
+    /// data are differenced `diff` times and the forecast is integrated back to their scale.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        if self.diff > 0 {
+            let diff_forecast = forecast(&diff(data, self.diff), self.intercept, &self.phi, &[], horizon);
+            return Ok(undiff_forecast(data, &diff_forecast, self.diff));
+        }
+        Ok(forecast(data, self.intercept, &self.phi, &[], horizon))
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data around a known `mean` instead
+    /// of the estimated intercept: the data are centered on `mean`, forecast with a zero
+    /// intercept and shifted back. The two forecasts agree for a stationary model when
+    /// `mean = intercept / (1 - phi_1 - ... - phi_p)`.
+    pub fn forecast_with_mean(&self, data: &[f64], horizon: usize, mean: f64) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let centered: Vec<f64> = data.iter().map(|x| x - mean).collect();
+        let forecast = forecast(&centered, 0.0, &self.phi, &[], horizon);
+        Ok(forecast.iter().map(|f| f + mean).collect())
+    }
+
+    /// Draws `n_samples` paths `horizon` steps ahead of the provided data from an
+    /// approximate posterior predictive distribution, from a generator seeded with `seed`.
+    /// Each path first draws its AR coefficients from the normal approximation
+    /// `N(phi, phi_covariance)` and then simulates forward with normal innovations of the
+    /// fitted variance, so that quantiles across the paths reflect both parameter and
+    /// innovation uncertainty. Returns `InvalidValue` unless the last fit was by OLS, the
+    /// only method estimating the coefficient covariance.
+    pub fn posterior_predictive_sample(
+        &self,
+        data: &[f64],
+        horizon: usize,
+        n_samples: usize,
+        seed: u64,
+    ) -> Result<Vec<Vec<f64>>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let covariance = self.phi_covariance.as_ref().ok_or_else(|| {
+            NefeleError::InvalidValue("the coefficient covariance is estimated only by OLS fits".to_string())
+        })?;
+        let order = self.phi.len();
+        if data.len() < order {
+            return Err(NefeleError::InvalidLength(format!(
+                "{} observations, at least {} needed to forecast an AR({})", data.len(), order, order
+            )));
+        }
+        let factor = covariance
+            .clone()
+            .cholesky()
+            .ok_or_else(|| NefeleError::DegenerateInput("coefficient covariance is not positive definite".to_string()))?
+            .l();
+        let innovations = Normal::new(0.0, self.sigma_squared.sqrt())
+            .map_err(|e| NefeleError::InvalidValue(e.to_string()))?;
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let paths = (0..n_samples)
+            .map(|_| {
+                // Coefficients drawn around the estimates
+                let z = DVector::from_fn(order, |_, _| rng.sample::<f64, _>(StandardNormal));
+                let phi = DVector::from_column_slice(&self.phi) + &factor * z;
+
+                let mut history: Vec<f64> = data[data.len() - order..].to_vec();
+                for _ in 0..horizon {
+                    let t = history.len();
+                    let value = self.intercept
+                        + (0..order).map(|j| phi[j] * history[t - j - 1]).sum::<f64>()
+                        + innovations.sample(&mut rng);
+                    history.push(value);
+                }
+                history.split_off(order)
+            })
+            .collect();
+        Ok(paths)
+    }
+
+    /// Computes the half-life of mean reversion, i.e. the number of periods after which a
+    /// shock has decayed by half: `ln(0.5) / ln(|phi|)` for an AR(1) and, in general, the
+    /// same expression on the modulus of the dominant inverse root. Returns infinity for
+    /// non-stationary coefficients.
+    pub fn half_life(&self) -> f64 {
+        let modulus = ar_roots(&self.phi)
+            .iter()
+            .map(|root| root.norm_sqr().sqrt())
+            .fold(0.0, f64::max);
+
+        if modulus >= 1.0 {
+            f64::INFINITY
+        } else {
+            0.5_f64.ln() / modulus.ln()
+        }
+    }
+
+    /// Returns each inverse root of the AR polynomial with its modulus, the damping factor of
+    /// its component per period, and its period `2 pi / |arg|`. Complex conjugate roots give a
+    /// damped cycle of that period, a negative real root an alternation of period 2, and a
+    /// positive real root no cycle at all, with an infinite period.
+    pub fn roots_and_periods(&self) -> Vec<(Complex<f64>, f64, f64)> {
+        ar_roots(&self.phi)
+            .into_iter()
+            .map(|root| {
+                let period = 2.0 * std::f64::consts::PI / root.im.atan2(root.re).abs();
+                (root, root.norm_sqr().sqrt(), period)
+            })
+            .collect()
     }
 
     /// Simulates an autoregressive process.
@@ -54,112 +358,492 @@ impl AutoRegressive {
         error_mean: f64,
         error_variance: f64,
     ) -> Vec<f64> {
-        let mut output: Vec<f64> = Vec::with_capacity(length);
         let normal: Normal<f64> = Normal::new(error_mean, error_variance.sqrt()).unwrap();
+        let mut rng = rand::thread_rng();
 
-        let ar_order = param.len();
+        simulate_path(length, &param, &normal, &mut rng)
+    }
 
-        // Initialization
-        let init = ar_order;
-        for _ in 0..(init + length) {
-            let mut rng = rand::thread_rng();
-            let err = normal.sample(&mut rng);
-            output.push(err);
-        }
+    /// Simulates an autoregressive process starting from a draw of its stationary distribution
+    /// instead of a short burn-in, from a generator seeded with `seed`: every value, the first
+    /// included, has the stationary distribution, however close the roots are to the unit
+    /// circle. Returns an `InvalidValue` error for non-stationary coefficients.
+    pub fn simulate_stationary_init(
+        &self,
+        length: usize,
+        param: Vec<f64>,
+        error_mean: f64,
+        error_variance: f64,
+        seed: u64,
+    ) -> Result<Vec<f64>, NefeleError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        simulate_stationary(length, &param, &[], error_mean, error_variance, &mut rng)
+    }
 
-        // AR(phi)
-        if ar_order > 0 {
-            let ar = &param;
+    /// Simulates an autoregressive process as `simulate` does and adds the deterministic linear trend
+    /// `trend_intercept + trend_slope * t`, for `t = 0, ..., length - 1`, on top of it.
+    pub fn simulate_with_trend(
+        &mut self,
+        length: usize,
+        param: Vec<f64>,
+        error_mean: f64,
+        error_variance: f64,
+        trend_slope: f64,
+        trend_intercept: f64,
+    ) -> Vec<f64> {
+        self.simulate(length, param, error_mean, error_variance)
+            .into_iter()
+            .enumerate()
+            .map(|(t, x)| x + trend_intercept + trend_slope * t as f64)
+            .collect()
+    }
 
-            for i in (ar_order)..(init + length) {
-                for j in 0..ar_order {
-                    output[i] += ar[j] * output[i - j - 1];
-                }
+    /// Simulates `n_paths` autoregressive processes drawing all the errors from a
+    /// single generator seeded with `seed`, so that the whole ensemble is reproducible.
+    pub fn simulate_ensemble(
+        &self,
+        length: usize,
+        param: Vec<f64>,
+        error_mean: f64,
+        error_variance: f64,
+        n_paths: usize,
+        seed: u64,
+    ) -> Vec<Vec<f64>> {
+        let normal: Normal<f64> = Normal::new(error_mean, error_variance.sqrt()).unwrap();
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        (0..n_paths)
+            .map(|_| simulate_path(length, &param, &normal, &mut rng))
+            .collect()
+    }
+
+    /// Simulates an autoregressive process lazily, yielding one observation at a time without
+    /// end, from a generator seeded with `seed`. The `param.len()` initial values are drawn
+    /// and discarded before the first one is yielded, so `take(n)` gives the path
+    /// `simulate_ensemble(n, param, error_mean, error_variance, 1, seed)` does.
+    pub fn simulate_iter(
+        &self,
+        param: Vec<f64>,
+        error_mean: f64,
+        error_variance: f64,
+        seed: u64,
+    ) -> impl Iterator<Item = f64> {
+        let normal: Normal<f64> = Normal::new(error_mean, error_variance.sqrt()).unwrap();
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        // Burn-in: the last param.len() values, most recent last
+        let mut history: Vec<f64> = (0..param.len()).map(|_| normal.sample(&mut rng)).collect();
+
+        std::iter::from_fn(move || {
+            let mut value = normal.sample(&mut rng);
+            for (j, coefficient) in param.iter().enumerate() {
+                value += coefficient * history[history.len() - j - 1];
             }
-        }
+            if !history.is_empty() {
+                history.remove(0);
+                history.push(value);
+            }
+            Some(value)
+        })
+    }
+
+    /// Simulates `n` observations of an AR process with coefficients `phi` and standard
+    /// normal errors from a generator seeded with `seed`, then fits an AR(`order`) to them.
+    pub fn roundtrip(order: usize, phi: Vec<f64>, n: usize, seed: u64, method: ARMethod) -> AutoRegressive {
+        let normal: Normal<f64> = Normal::new(0.0, 1.0).unwrap();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let data = simulate_path(n, &phi, &normal, &mut rng);
 
-        output[ar_order..].to_vec()
+        let mut model = AutoRegressive::new();
+        model.fit(&data, order, method);
+        model
     }
 
     /// Fits the autoregressive model to the provided data according to the selected method.
+    ///
+    /// When `winsorize` is set, the model is fitted to the winsorized data: robust to isolated
+    /// outliers, but estimated on a lighter-tailed series than the one observed.
     pub fn fit(&mut self, data: &Vec<f64>, order: usize, method: ARMethod) {
+        let Ok(winsorized) = self.prepare_fit(data) else { return };
+        let data = winsorized.as_ref().unwrap_or(data);
+        // coefficients, innovation variance and, for Yule-Walker and CSS, the intercept
+        let k = order + 1 + usize::from(matches!(method, ARMethod::YWALKER | ARMethod::CSS));
         match method {
-            ARMethod::OLS => Self::fit_ols(self, data, order),
+            ARMethod::OLS => {
+                if let Err(error) = Self::fit_ols(self, data, order) {
+                    tracing::warn!("{}, the model is left unfitted", error);
+                    self.phi = Vec::new();
+                    self.ols_solver = None;
+                    self.mark_unfitted();
+                    return;
+                }
+            }
             ARMethod::YWALKER => Self::fit_yule_walker(self, data, order),
             ARMethod::BURG => Self::fit_burg(self, data, order),
-            ARMethod::CSS => Self::fit_css(self, data, order)
+            ARMethod::CSS => Self::fit_css(self, data, order, None)
         }
 
-        self.sigma_squared = compute_variance(&data, &self.phi);
-        self.aic = compute_aic(data.len(), self.sigma_squared, order);
-        self.bic = compute_bic(data.len(), self.sigma_squared, order);
+        // Burg fits keep the prediction error variance of their recursion
+        let sigma_squared = match method {
+            ARMethod::BURG => self.sigma_squared,
+            _ => compute_variance(data, &self.phi)
+        };
+        self.set_fit_statistics(data, sigma_squared, k);
     }
 
-    /// Automatically fits the autoregressive model by selecting the order based on a criterion (AIC or BIC).
-    pub fn autofit(&mut self, data: &Vec<f64>, max_order: usize, method: ARCriterion) {
-        match method {
-            ARCriterion::AIC => Self::autofit_aic(self, data, max_order),
-            ARCriterion::BIC => Self::autofit_bic(self, data, max_order),
+    /// Checks the data before a fit and clears the estimates of any earlier one. On a
+    /// degenerate series the model is left unfitted and the problem returned as an error;
+    /// otherwise returns the winsorized data when `winsorize` is set.
+    fn prepare_fit(&mut self, data: &[f64]) -> Result<Option<Vec<f64>>, NefeleError> {
+        let quality = input_quality(data);
+        quality.warn();
+        if quality.is_degenerate() {
+            self.mark_unfitted();
+            quality.check()?;
         }
+        self.intercept = 0.0;
+        self.reflection_coefficients = Vec::new();
+        self.standard_errors = None;
+        self.phi_covariance = None;
+        self.diff = 0;
+        self.ols_solver = None;
+        Ok(self.winsorize.map(|(lower_pct, upper_pct)| winsorize(data, lower_pct, upper_pct)))
     }
 
-    fn fit_ols(&mut self, data: &Vec<f64>, order: usize) {
-        let n = data.len();
+    /// Leaves the model unfitted, with undefined variance and information criteria.
+    fn mark_unfitted(&mut self) {
+        self.fitted = false;
+        self.sigma_squared = f64::NAN;
+        self.aic = f64::NAN;
+        self.bic = f64::NAN;
+    }
 
+    /// Fits the autoregressive model to the data differenced `d` times, an ARIMA(`order`, `d`, 0)
+    /// without leaving the AR type, and stores `d` so that `forecast` integrates back to the
+    /// scale of the data. The other methods (residuals, R^2, simulation) work on the
+    /// differenced scale. Returns `InsufficientData` if fewer than `order + 1` differenced
+    /// observations remain.
+    pub fn fit_differenced(&mut self, data: &[f64], order: usize, d: usize, method: ARMethod) -> Result<(), NefeleError> {
+        let n = differenced_length(data.len(), d);
         if n <= order {
-            panic!("Not enough data for the given order");
+            return Err(NefeleError::InsufficientData(format!(
+                "{} observations leave {} after differencing {} times, at least {} needed for an AR({})",
+                data.len(), n, d, order + 1, order
+            )));
         }
+        self.fit(&diff(data, d), order, method);
+        self.diff = d;
+        Ok(())
+    }
 
-        // Construct the matrix of regressors
-        let mut x = DMatrix::zeros(n - order, order);
-        for i in order..n {
-            for j in 0..order {
-                x[(i - order, j)] = data[i - j - 1];
+    /// Fits the autoregressive model with a method chosen from the length of the data, and
+    /// returns it: Yule-Walker below 500 observations, whose estimates on the demeaned data
+    /// are always stationary, and OLS from 500 on, where it is the most efficient. The
+    /// threshold is heuristic.
+    pub fn fit_auto_method(&mut self, data: &Vec<f64>, order: usize) -> ARMethod {
+        let method = match data.len() {
+            0..=499 => ARMethod::YWALKER,
+            _ => ARMethod::OLS
+        };
+        self.fit(data, order, method);
+        method
+    }
+
+    /// Fits an AR(`max_order`) by OLS, then drops the highest lag while its coefficient is
+    /// not significant at level `significance` and refits. Returns the selected order, or
+    /// `InsufficientData` unless there are more than `2 * max_order` observations.
+    ///
+    /// The test is a two-sided Wald test with normal critical values: it is the t-test on
+    /// `n - 2p` degrees of freedom only asymptotically, and rejects slightly too often on
+    /// short series.
+    pub fn fit_backward(&mut self, data: &Vec<f64>, max_order: usize, significance: f64) -> Result<usize, NefeleError> {
+        if data.len() <= 2 * max_order {
+            return Err(NefeleError::InsufficientData(format!(
+                "{} observations for an AR({}), more than {} needed", data.len(), max_order, 2 * max_order
+            )));
+        }
+        let critical = normal_quantile(1.0 - significance / 2.0);
+
+        let mut order = max_order;
+        while order > 0 {
+            let (coefficients, standard_errors) = ols_with_standard_errors(data, order);
+            let t_stat = coefficients[order - 1] / standard_errors[order - 1];
+            if t_stat.abs() >= critical {
+                break;
             }
+            order -= 1;
         }
 
-        let y = DVector::from_iterator(n - order, data.iter().skip(order).cloned());
+        self.fit(data, order, ARMethod::OLS);
+        Ok(order)
+    }
 
-        // OLS
-        let xtx = x.transpose() * &x;
-        let xty = x.transpose() * &y;
+    /// Fits the model by CSS starting the optimizer from `initial` instead of the default
+    /// guesses. `initial` holds the intercept followed by the AR coefficients, so it must
+    /// have length `1 + order`. Trying several starting points helps escaping bad local minima.
+    /// The data are checked as in `fit`, and a degenerate series returns its problem as an error.
+    pub fn fit_css_with_initial(&mut self, data: &Vec<f64>, order: usize, initial: Vec<f64>) -> Result<(), NefeleError> {
+        if initial.len() != 1 + order {
+            return Err(NefeleError::InvalidLength(format!(
+                "initial guess has {} values, expected {}", initial.len(), 1 + order
+            )));
+        }
+        let winsorized = self.prepare_fit(data)?;
+        let data = winsorized.as_ref().unwrap_or(data);
+        Self::fit_css(self, data, order, Some(initial));
+        self.set_fit_statistics(data, compute_variance(data, &self.phi), order + 2);
+        Ok(())
+    }
 
-        // Cholesky decomposition
-        let chol = xtx.cholesky().expect("Cholesky decomposition failed");
-        let coefficients = chol.solve(&xty);
+    /// Fits the model by ridge regression: the OLS regression of `fit_ols` with an L2 penalty
+    /// `lambda |phi|^2`, solving `(X'X + lambda I) phi = X'y`. Larger values of `lambda`
+    /// shrink the coefficients towards zero, stabilizing long-lag fits on short data, and
+    /// `lambda = 0` gives back OLS. The estimate is the posterior mode under independent
+    /// `N(0, sigma^2 / lambda)` priors on the coefficients, so `lambda` is the ratio of the
+    /// innovation variance to the prior variance.
+    pub fn fit_ridge(&mut self, data: &[f64], order: usize, lambda: f64) -> Result<(), NefeleError> {
+        if lambda < 0.0 || lambda.is_nan() {
+            return Err(NefeleError::InvalidValue(format!("ridge penalty must be non-negative, found {}", lambda)));
+        }
+        if data.len() <= order {
+            return Err(NefeleError::InsufficientData(format!(
+                "{} observations for an AR({})", data.len(), order
+            )));
+        }
+
+        // Construct the matrix of regressors
+        let lags: Vec<usize> = (1..=order).collect();
+        let x = lag_matrix(data, &lags);
+        let y = DVector::from_iterator(data.len() - order, data.iter().skip(order).cloned());
+
+        let penalized = x.transpose() * &x + DMatrix::<f64>::identity(order, order) * lambda;
+        let coefficients = penalized
+            .cholesky()
+            .ok_or_else(|| NefeleError::DegenerateInput("lagged regressors are collinear".to_string()))?
+            .solve(&(x.transpose() * &y));
 
+        self.intercept = 0.0;
+        self.reflection_coefficients = Vec::new();
+        self.standard_errors = None;
+        self.phi_covariance = None;
+        self.diff = 0;
+        self.ols_solver = None;
         self.phi = coefficients.data.into();
+        self.set_fit_statistics(data, compute_variance(data, &self.phi), order + 1);
+        Ok(())
     }
 
-    fn fit_yule_walker(&mut self, data: &Vec<f64>, order: usize) {
-        let n = data.len();
+    /// Stores the variance and the information criteria of a fit with `k` free parameters to
+    /// `data`. The criteria use the residual sum of squares `n sigma^2` of the `n` residuals
+    /// following the first `phi.len()` observations.
+    fn set_fit_statistics(&mut self, data: &[f64], sigma_squared: f64, k: usize) {
+        let n = data.len().saturating_sub(self.phi.len());
+        let rss = sigma_squared * n as f64;
+        self.sigma_squared = sigma_squared;
+        self.aic = aic(n, rss, k);
+        self.bic = bic(n, rss, k);
+        self.n_params = k;
+        self.rls_p = None;
+        self.fitted = true;
+    }
+
+    /// Updates the intercept and the AR coefficients with a new observation by recursive least
+    /// squares. `history` holds the observations preceding `new_value`, at least `phi.len()`.
+    pub fn update(&mut self, new_value: f64, history: &[f64]) -> Result<(), NefeleError> {
+        self.update_weighted(new_value, history, 1.0)
+    }
 
-        // Autocorrelation matrix rho
-        let mut rho = DMatrix::<f64>::zeros(order, order);
+    /// Updates the intercept and the AR coefficients with a new observation by recursive least
+    /// squares with exponential forgetting: past observations are down-weighted by
+    /// `forgetting_factor` in (0, 1] at every update, so that the estimates track slowly varying
+    /// dynamics. A factor of 1 gives ordinary recursive least squares, and one outside (0, 1]
+    /// an `InvalidValue` error.
+    ///
+    /// `history` holds the observations preceding `new_value`, at least `phi.len()`. On the first
+    /// update after a fit, the inverse information matrix is initialized from the whole `history`.
+    pub fn update_weighted(&mut self, new_value: f64, history: &[f64], forgetting_factor: f64) -> Result<(), NefeleError> {
+        if !(forgetting_factor > 0.0 && forgetting_factor <= 1.0) {
+            return Err(NefeleError::InvalidValue(format!(
+                "forgetting factor must be in (0, 1], found {}", forgetting_factor
+            )));
+        }
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let p = self.phi.len();
+        if history.len() < p {
+            return Err(NefeleError::InvalidLength(format!(
+                "history has {} values, expected at least {}", history.len(), p
+            )));
+        }
+
+        // regressors: a constant followed by the last p observations, most recent first
+        let regressors = |t: usize| {
+            let mut x = DVector::from_element(p + 1, 1.0);
+            for j in 0..p {
+                x[j + 1] = history[t - j - 1];
+            }
+            x
+        };
 
-        for i in 0..order {
-            for j in 0..order {
-                let mut sum = 0.0;
-                for k in 0..(n - order) {
-                    sum += data[k + i] * data[k + j];
+        let mut p_matrix = match self.rls_p.take() {
+            Some(p_matrix) => p_matrix,
+            None => {
+                let mut information = DMatrix::<f64>::zeros(p + 1, p + 1);
+                for t in p..history.len() {
+                    let x = regressors(t);
+                    information += &x * x.transpose();
                 }
-                rho[(i, j)] = sum / (n - order) as f64;
+                information.try_inverse().unwrap_or_else(|| DMatrix::identity(p + 1, p + 1) * 1e6)
             }
-        }
+        };
 
-        let mut r = DVector::<f64>::zeros(order);
+        let x = regressors(history.len());
+        let mut theta = DVector::from_iterator(p + 1, std::iter::once(self.intercept).chain(self.phi.iter().cloned()));
 
-        for i in 0..order {
-            let mut sum = 0.0;
-            for k in 0..(n - order) {
-                sum += data[k + i] * data[k + order];
+        // gain, prediction error and updates of the coefficients and of the inverse information
+        let px = &p_matrix * &x;
+        let gain = &px / (forgetting_factor + x.dot(&px));
+        let error = new_value - x.dot(&theta);
+        theta += &gain * error;
+        p_matrix = (&p_matrix - &gain * px.transpose()) / forgetting_factor;
+
+        self.intercept = theta[0];
+        self.phi = theta.iter().skip(1).cloned().collect();
+        self.standard_errors = None;
+        self.phi_covariance = None;
+        self.rls_p = Some(p_matrix);
+        Ok(())
+    }
+
+    /// Automatically fits the autoregressive model by selecting the order based on a criterion (AIC or BIC).
+    /// `max_order` is capped at half the length of the data, with a warning, since higher
+    /// orders leave too few observations to estimate the coefficients.
+    ///
+    /// The candidates are Yule-Walker fits: the sample autocorrelations are computed once and
+    /// a single Levinson-Durbin sweep yields the coefficients of every order up to
+    /// `max_order`, each scored on its residual variance like `fit`. The selected order is
+    /// then refitted with `fit_yule_walker_from_acf` on the same autocorrelations and the
+    /// intercept set to match the sample mean, so the result equals `fit` with
+    /// `ARMethod::YWALKER` at that order.
+    pub fn autofit(&mut self, data: &Vec<f64>, max_order: usize, criterion: &dyn OrderCriterion) {
+        let limit = data.len() / 2;
+        let max_order = if max_order > limit {
+            tracing::warn!("max_order {} capped at {} for {} observations", max_order, limit, data.len());
+            limit
+        } else {
+            max_order
+        };
+        let winsorized;
+        let data = match self.winsorize {
+            Some((lower_pct, upper_pct)) => {
+                winsorized = winsorize(data, lower_pct, upper_pct);
+                &winsorized
             }
-            r[i] = sum / (n - order) as f64;
+            None => data
+        };
+
+        let autocovariances = acf(data, Some(max_order), true);
+        let cov0 = autocovariances[0];
+        if cov0 <= 0.0 {
+            tracing::warn!("constant series, no autoregressive order to select");
+            return;
         }
+        let rho: Vec<f64> = autocovariances.iter().map(|c| c / cov0).collect();
 
-        if let Some(solution) = rho.clone().qr().solve(&r) {
-            self.phi = solution.iter().rev().cloned().collect();
+        // coefficients of every order from one Levinson-Durbin sweep
+        let (path, _) = ar_dl_path(&rho, cov0, Some(max_order));
+        let criterion: Vec<f64> = path
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(order, phi)| {
+                let n = data.len() - order;
+                criterion.evaluate(n, compute_variance(data, phi) * n as f64, order + 2)
+            })
+            .collect();
+
+        let min_order = criterion
+            .iter()
+            .enumerate()
+            .min_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(index, _)| index + 1) // Adding 1 to get position
+            .unwrap_or(0);
+
+        self.fit_yule_walker_from_acf(&rho, cov0, min_order);
+        self.intercept = mean(data) * (1.0 - self.phi.iter().sum::<f64>());
+        let sigma_squared = compute_variance(data, &self.phi);
+        self.set_fit_statistics(data, sigma_squared, min_order + 2);
+    }
+
+    /// Returns `DegenerateInput` if the lagged regressors are exactly collinear, in which case
+    /// the coefficients are not identified.
+    fn fit_ols(&mut self, data: &Vec<f64>, order: usize) -> Result<(), NefeleError> {
+        let n = data.len();
+
+        if n <= order {
+            panic!("Not enough data for the given order");
         }
+
+        // Construct the matrix of regressors
+        let lags: Vec<usize> = (1..=order).collect();
+        let x = lag_matrix(data, &lags);
+
+        let y = DVector::from_iterator(n - order, data.iter().skip(order).cloned());
+
+        // OLS
+        let xtx = x.transpose() * &x;
+        let xty = x.transpose() * &y;
+        let xtx_inv = xtx.clone().try_inverse();
+
+        // Cholesky decomposition, falling back on the QR decomposition of X, which does not
+        // square its condition number, when X'X is not numerically positive definite
+        let coefficients = match xtx.cholesky() {
+            Some(chol) => {
+                self.ols_solver = Some(OLSSolver::Cholesky);
+                chol.solve(&xty)
+            }
+            None => {
+                self.ols_solver = Some(OLSSolver::QR);
+                let qr = x.clone().qr();
+                match qr.r().solve_upper_triangular(&(qr.q().transpose() * &y)) {
+                    Some(coefficients) => coefficients,
+                    None => {
+                        return Err(NefeleError::DegenerateInput(
+                            "the lagged regressors are exactly collinear".to_string()
+                        ));
+                    }
+                }
+            }
+        };
+
+        // Standard errors sqrt(sigma^2 (X'X)^-1_jj), with sigma^2 corrected for the lost
+        // observations and the estimated coefficients, hence left unset without more than
+        // 2 * order observations
+        self.phi_covariance = match xtx_inv {
+            Some(xtx_inv) if n > 2 * order => {
+                let sigma_squared = (&y - &x * &coefficients).norm_squared() / (n - 2 * order) as f64;
+                Some(xtx_inv * sigma_squared)
+            }
+            _ => None,
+        };
+        self.standard_errors = self.phi_covariance
+            .as_ref()
+            .map(|covariance| (0..order).map(|j| covariance[(j, j)].sqrt()).collect());
+
+        self.phi = coefficients.data.into();
+        Ok(())
+    }
+
+    fn fit_yule_walker(&mut self, data: &Vec<f64>, order: usize) {
+        // Levinson-Durbin recursion on the sample autocorrelations of the demeaned data
+        let autocovariances = acf(data, Some(order), true);
+        let cov0 = autocovariances[0];
+        let rho: Vec<f64> = autocovariances.iter().map(|c| c / cov0).collect();
+        self.fit_yule_walker_from_acf(&rho, cov0, order);
+
+        // Intercept matching the sample mean of the data
+        self.intercept = mean(data) * (1.0 - self.phi.iter().sum::<f64>());
     }
 
     fn fit_burg(&mut self, data: &Vec<f64>, order: usize) {
@@ -185,18 +869,23 @@ impl AutoRegressive {
             }
             lambda = (r[i] - sum) / e[i - 1];
 
+            // The update reads the coefficients of the previous stage, not the ones being updated
+            let previous = a.clone();
             a[i] = lambda;
             for j in 1..=i - 1 {
-                a[j] = a[j] - lambda * a[i - j];
+                a[j] = previous[j] - lambda * previous[i - j];
             }
 
             e[i] = (1.0 - lambda * lambda) * e[i - 1];
+            self.reflection_coefficients.push(lambda);
         }
 
         self.phi = a[1..].to_vec();
+        self.sigma_squared = e[order];
     }
 
-    fn fit_css(&mut self, data: &Vec<f64>, ar: usize) {
+    fn fit_css(&mut self, data: &Vec<f64>, ar: usize, initial: Option<Vec<f64>>) {
+        let enforce = self.enforce_stationarity;
 
         let total_size = 1 + ar;
 
@@ -206,32 +895,34 @@ impl AutoRegressive {
             assert_eq!(coef.len(), total_size);
 
             let intercept = coef[0];
-            let phi = &coef[1..ar + 1];
+            let phi = &ar_from_optimizer(&coef[1..ar + 1], enforce);
             let theta = &coef[ar + 1..];
 
-            let residuals = residuals(&data, intercept, &phi.to_vec(), &theta.to_vec());
-
-            let mut css: f64 = 0.0;
-            for residual in &residuals {
-                css += residual * residual;
-            }
-            css
+            css_objective(data, intercept, phi, theta)
         };
         let g = |coef: &Vec<f64>| coef.forward_diff(&f);
 
-        // Initial coefficients
-        let mut coef: Vec<f64> = Vec::new();
+        // Initial coefficients, unless supplied by the caller
+        let mut coef: Vec<f64> = match initial {
+            Some(initial) => initial,
+            None => {
+                let mut coef: Vec<f64> = Vec::new();
+
+                // Initial guess for the intercept: First value of data
+                coef.push(mean(&data));
 
-        // Initial guess for the intercept: First value of data
-        coef.push(mean(&data));
+                // Initial guess for the AR coefficients: Values of the PACF
+                if ar > 0 {
+                    coef.extend(initial_ar_from_pacf(data, ar));
+                }
 
-        // Initial guess for the AR coefficients: Values of the PACF
-        if ar > 0 {
-            let pacf = pacf(&data, Some(ar));
-            for p in pacf {
-                coef.push(p);
+                coef
             }
-        }
+        };
+
+        // With enforced stationarity the AR part is searched in the unconstrained space
+        let start = ar_to_optimizer(&coef[1..=ar], enforce);
+        coef[1..=ar].copy_from_slice(&start);
 
         let evaluate = |x: &[f64], gx: &mut [f64]| {
             let x = x.to_vec();
@@ -242,8 +933,7 @@ impl AutoRegressive {
             Ok(fx)
         };
 
-        let fmin = lbfgs().with_max_iterations(200);
-        if let Err(e) = fmin.minimize(
+        if let Err(e) = self.optimizer.minimize(
             &mut coef, // input variables
             evaluate,  // define how to evaluate function
             |_prng| {
@@ -253,42 +943,80 @@ impl AutoRegressive {
             tracing::warn!("{}", e);
         }
         
-        self.phi = coef[1..=ar].to_vec();
+        self.intercept = coef[0];
+        self.phi = ar_from_optimizer(&coef[1..=ar], enforce);
     }
+}
 
-    fn autofit_aic(&mut self, data: &Vec<f64>, max_order: usize) {
-        let mut aic: Vec<f64> = Vec::with_capacity(max_order);
-        for order in 1..(max_order + 1) {
-            Self::fit(self, data, order, ARMethod::YWALKER);
-            aic.push(self.aic);
+/// Fits an AR(`order`) model to each of the independent `series` with `method`, in parallel
+/// when the `rayon` feature is enabled, returning the results in the order of the input.
+/// Each series is validated before fitting, so that a series which is too short (fewer than
+/// `2 * (order + 1)` observations) or fails [`input_quality`] yields an error for that
+/// series only instead of aborting the whole batch.
+pub fn fit_many(series: &[Vec<f64>], order: usize, method: ARMethod) -> Vec<Result<AutoRegressive, NefeleError>> {
+    let fit_one = |data: &Vec<f64>| -> Result<AutoRegressive, NefeleError> {
+        if data.len() < 2 * (order + 1) {
+            return Err(NefeleError::InsufficientData(format!(
+                "{} observations for an AR({}), at least {} needed", data.len(), order, 2 * (order + 1)
+            )));
         }
+        input_quality(data).check()?;
 
-        let min_order = aic
-            .iter()
-            .enumerate()
-            .min_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(index, _)| index + 1) // Adding 1 to get position
-            .unwrap_or(0);
+        let mut model = AutoRegressive::new();
+        model.fit(data, order, method);
+        Ok(model)
+    };
 
-        Self::fit(self, data, min_order, ARMethod::YWALKER);
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        series.par_iter().map(fit_one).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        series.iter().map(fit_one).collect()
     }
+}
 
-    fn autofit_bic(&mut self, data: &Vec<f64>, max_order: usize) {
-        let mut bic: Vec<f64> = Vec::with_capacity(max_order);
-        for order in 1..(max_order + 1) {
-            Self::fit(self, data, order, ARMethod::YWALKER);
-            bic.push(self.bic);
-        }
+/// Regresses the data on its first `order` lags by OLS, as `fit_ols` does, returning the
+/// coefficients and their standard errors `sqrt(sigma^2 (X'X)^-1_jj)`. Requires more than
+/// `2 * order` observations, so that `sigma^2` has positive degrees of freedom.
+fn ols_with_standard_errors(data: &[f64], order: usize) -> (Vec<f64>, Vec<f64>) {
+    let n = data.len();
 
-        let min_order = bic
-            .iter()
-            .enumerate()
-            .min_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(index, _)| index + 1) // Adding 1 to get position
-            .unwrap_or(0);
+    // Construct the matrix of regressors
+    let lags: Vec<usize> = (1..=order).collect();
+    let x = lag_matrix(data, &lags);
+    let y = DVector::from_iterator(n - order, data.iter().skip(order).cloned());
+
+    let xtx_inv = match (x.transpose() * &x).try_inverse() {
+        Some(xtx_inv) => xtx_inv,
+        None => return (vec![0.0; order], vec![f64::INFINITY; order]),
+    };
+    let coefficients = &xtx_inv * (x.transpose() * &y);
+
+    let residuals = &y - &x * &coefficients;
+    let sigma_squared = residuals.norm_squared() / (n - 2 * order) as f64;
+    let standard_errors = (0..order).map(|j| (sigma_squared * xtx_inv[(j, j)]).sqrt()).collect();
 
-        Self::fit(self, data, min_order, ARMethod::YWALKER);
+    (coefficients.iter().cloned().collect(), standard_errors)
+}
+
+/// Simulates a single AR path of the given length, discarding `param.len()` initial values.
+fn simulate_path<R: Rng>(length: usize, param: &[f64], normal: &Normal<f64>, rng: &mut R) -> Vec<f64> {
+    let ar_order = param.len();
+
+    // Initialization
+    let mut output: Vec<f64> = (0..(ar_order + length)).map(|_| normal.sample(rng)).collect();
+
+    // AR(phi)
+    for i in ar_order..(ar_order + length) {
+        for j in 0..ar_order {
+            output[i] += param[j] * output[i - j - 1];
+        }
     }
+
+    output.split_off(ar_order)
 }
 
 /// Computes the variance of the residuals.
@@ -311,16 +1039,521 @@ fn compute_variance(data: &[f64], coefficients: &[f64]) -> f64 {
     variance
 }
 
-/// Computes the Akaike Information Criterion.
-fn compute_aic(n: usize, residual_sum_of_squares: f64, p: usize) -> f64 {
-    let k = p; // Number of parameters (p autoregressive parameters)
-    let aic = 2.0 * k as f64 + n as f64 * (residual_sum_of_squares / n as f64).ln();
-    aic
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensemble_mean_approaches_the_conditional_forecast() {
+        let model = AutoRegressive::new();
+        let paths = model.simulate_ensemble(10, vec![0.5], 1.0, 1.0, 20_000, 7);
+
+        // every path starts from a single draw of the errors, whose mean is 1
+        let expected = forecast(&[1.0], 1.0, &[0.5], &[], 10);
+        for (h, &target) in expected.iter().enumerate() {
+            let mean = paths.iter().map(|path| path[h]).sum::<f64>() / paths.len() as f64;
+            assert!((mean - target).abs() < 0.05, "horizon {}: {} vs {}", h + 1, mean, target);
+        }
+    }
+
+    #[test]
+    fn unfitted_model_reports_not_fitted() {
+        let model = AutoRegressive::new();
+        let data = vec![1.0, 2.0, 1.5, 0.5, 1.0];
+
+        assert_eq!(model.forecast(&data, 3), Err(NefeleError::NotFitted));
+        assert_eq!(model.residuals(&data), Err(NefeleError::NotFitted));
+        assert!(model.to_summary().to_string().contains("not yet fitted"));
+    }
+
+    #[test]
+    fn roundtrip_recovers_the_coefficients() {
+        let phi = vec![0.5, -0.3];
+        for method in [ARMethod::OLS, ARMethod::YWALKER, ARMethod::BURG, ARMethod::CSS] {
+            let model = AutoRegressive::roundtrip(2, phi.clone(), 5000, 3, method);
+            for (estimate, truth) in model.phi.iter().zip(&phi) {
+                assert!((estimate - truth).abs() < 0.05, "{:?}: {:?}", method, model.phi);
+            }
+        }
+    }
+
+    #[test]
+    fn forecast_with_mean_shifts_by_the_mean() {
+        let model = AutoRegressive::roundtrip(2, vec![0.5, -0.3], 500, 4, ARMethod::OLS);
+        let data = AutoRegressive::new().simulate_ensemble(100, vec![0.5, -0.3], 0.0, 1.0, 1, 5).remove(0);
+        let shifted: Vec<f64> = data.iter().map(|x| x + 10.0).collect();
+
+        let base = model.forecast_with_mean(&data, 5, 0.0).unwrap();
+        let moved = model.forecast_with_mean(&shifted, 5, 10.0).unwrap();
+        for (b, m) in base.iter().zip(&moved) {
+            assert!((m - b - 10.0).abs() < 1e-12, "{} vs {}", m, b);
+        }
+    }
+
+    #[test]
+    fn half_life_of_ar1() {
+        let model = AutoRegressive::try_from_coefficients(vec![0.5], 1.0).unwrap();
+        assert!((model.half_life() - 1.0).abs() < 1e-12);
+
+        // dominant inverse root 0.5 of (1 - 0.5 z) (1 - 0.25 z)
+        let model = AutoRegressive::try_from_coefficients(vec![0.75, -0.125], 1.0).unwrap();
+        assert!((model.half_life() - 1.0).abs() < 1e-9);
+
+        let mut random_walk = AutoRegressive::new();
+        random_walk.phi = vec![1.0];
+        assert_eq!(random_walk.half_life(), f64::INFINITY);
+    }
+
+    #[test]
+    fn forgetting_tracks_a_changing_coefficient() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        use rand_distr::StandardNormal;
+
+        // AR(1) whose coefficient jumps from 0.2 to 0.8 after the first 300 observations
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut data = vec![0.0];
+        for t in 1..600 {
+            let phi = if t < 300 { 0.2 } else { 0.8 };
+            let shock: f64 = rng.sample(StandardNormal);
+            data.push(phi * data[t - 1] + shock);
+        }
+
+        let mut plain = AutoRegressive::new();
+        plain.fit(&data[..300].to_vec(), 1, ARMethod::OLS);
+        let mut forgetting = plain.clone();
+        let mut unit = plain.clone();
+        for t in 300..600 {
+            plain.update(data[t], &data[..t]).unwrap();
+            forgetting.update_weighted(data[t], &data[..t], 0.98).unwrap();
+            unit.update_weighted(data[t], &data[..t], 1.0).unwrap();
+        }
+
+        assert!((forgetting.phi[0] - 0.8).abs() < 0.15, "{}", forgetting.phi[0]);
+        assert!((forgetting.phi[0] - 0.8).abs() < (plain.phi[0] - 0.8).abs(), "{} vs {}", forgetting.phi[0], plain.phi[0]);
+        assert_eq!(unit.phi, plain.phi);
+
+        for factor in [0.0, 1.5, f64::NAN] {
+            assert!(matches!(unit.update_weighted(data[0], &data, factor), Err(NefeleError::InvalidValue(_))));
+        }
+    }
+
+    #[test]
+    fn detrending_recovers_the_simulated_trend() {
+        use crate::arma::ARMA;
+
+        // least squares line through the points (t, x_t)
+        let line = |x: &[f64]| {
+            let n = x.len() as f64;
+            let t_mean = (n - 1.0) / 2.0;
+            let x_mean = x.iter().sum::<f64>() / n;
+            let sxy: f64 = x.iter().enumerate().map(|(t, v)| (t as f64 - t_mean) * (v - x_mean)).sum();
+            let sxx: f64 = (0..x.len()).map(|t| (t as f64 - t_mean).powi(2)).sum();
+            let slope = sxy / sxx;
+            (slope, x_mean - slope * t_mean)
+        };
+
+        let ar = AutoRegressive::new().simulate_with_trend(5000, vec![0.5], 0.0, 1.0, 0.02, 3.0);
+        let arma = ARMA::new().simulate_with_trend(5000, vec![0.5], vec![0.3], 0.0, 1.0, 0.02, 3.0);
+        for path in [ar, arma] {
+            let (slope, intercept) = line(&path);
+            assert!((slope - 0.02).abs() < 1e-3, "slope {}", slope);
+            assert!((intercept - 3.0).abs() < 0.4, "intercept {}", intercept);
+        }
+    }
+
+    #[test]
+    fn auto_method_depends_on_the_length() {
+        for (length, expected, tolerance) in [(40, ARMethod::YWALKER, 0.3), (300, ARMethod::YWALKER, 0.15), (3000, ARMethod::OLS, 0.05)] {
+            let data = AutoRegressive::new().simulate_stationary_init(length, vec![0.6], 0.0, 1.0, 8).unwrap();
+            let mut model = AutoRegressive::new();
+
+            assert_eq!(model.fit_auto_method(&data, 1), expected);
+            assert!((model.phi[0] - 0.6).abs() < tolerance, "{}: {}", length, model.phi[0]);
+        }
+    }
+
+    #[test]
+    fn reflection_coefficients_are_the_partial_autocorrelations() {
+        let data = AutoRegressive::new().simulate_stationary_init(500, vec![0.5, -0.3], 0.0, 1.0, 9).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data, 3, ARMethod::YWALKER);
+
+        // the partial autocorrelations from lag 1 on
+        let partial = crate::utils::pacf(&data, Some(3));
+        assert_eq!(model.reflection_coefficients.len(), 3);
+        for (k, r) in model.reflection_coefficients.iter().enumerate() {
+            assert!((r - partial[k]).abs() < 1e-10, "lag {}: {} vs {}", k + 1, r, partial[k]);
+        }
+        assert!((model.reflection_coefficients[2] - model.phi[2]).abs() < 1e-10);
+    }
+
+    #[test]
+    fn fit_many_isolates_degenerate_series() {
+        let good = AutoRegressive::new().simulate_stationary_init(300, vec![0.5], 0.0, 1.0, 4).unwrap();
+        let other = AutoRegressive::new().simulate_stationary_init(300, vec![-0.4], 0.0, 1.0, 5).unwrap();
+        let series = vec![good.clone(), vec![1.0, 2.0], other, vec![3.0; 50], good];
+
+        let results = fit_many(&series, 1, ARMethod::OLS);
+        assert_eq!(results.len(), 5);
+        assert!(matches!(results[1], Err(NefeleError::InsufficientData(_))));
+        assert!(matches!(results[3], Err(NefeleError::DegenerateInput(_))));
+
+        // the results keep the order of the input
+        let phi: Vec<f64> = [0, 2, 4].iter().map(|&i| results[i].as_ref().unwrap().phi[0]).collect();
+        assert!((phi[0] - 0.5).abs() < 0.15 && (phi[1] + 0.4).abs() < 0.15, "{:?}", phi);
+        assert_eq!(phi[0], phi[2]);
+    }
 
-/// Computes the Bayesian Information Criterion.
-fn compute_bic(n: usize, residual_sum_of_squares: f64, p: usize) -> f64 {
-    let k = p; // Number of parameters (p autoregressive parameters)
-    let bic = n as f64 * (residual_sum_of_squares / n as f64).ln() + k as f64 * (n as f64).ln();
-    bic
+    #[test]
+    fn enforced_stationarity_keeps_the_css_fit_stationary() {
+        let mut rng = StdRng::seed_from_u64(12);
+        let mut data = vec![1.0];
+        for t in 1..80 {
+            let shock: f64 = rng.sample(StandardNormal);
+            data.push(1.05 * data[t - 1] + shock);
+        }
+
+        let mut free = AutoRegressive::new();
+        free.fit(&data, 1, ARMethod::CSS);
+        assert!(free.phi[0] > 1.0, "{:?}", free.phi);
+
+        let mut constrained = AutoRegressive::new();
+        constrained.enforce_stationarity = true;
+        constrained.fit(&data, 1, ARMethod::CSS);
+        assert!(constrained.phi[0].abs() < 1.0, "{:?}", constrained.phi);
+
+        // the reparameterization is one-to-one on stationary coefficients
+        let phi = [0.5, -0.3, 0.1];
+        let back = ar_from_optimizer(&ar_to_optimizer(&phi, true), true);
+        for (a, b) in phi.iter().zip(&back) {
+            assert!((a - b).abs() < 1e-10, "{:?}", back);
+        }
+    }
+
+    #[test]
+    fn backward_elimination_drops_spurious_lags() {
+        let data = AutoRegressive::new().simulate_stationary_init(1000, vec![0.5, -0.3], 0.0, 1.0, 10).unwrap();
+        let mut model = AutoRegressive::new();
+
+        assert_eq!(model.fit_backward(&data, 6, 0.01), Ok(2));
+        assert_eq!(model.phi.len(), 2);
+        assert!((model.phi[0] - 0.5).abs() < 0.1 && (model.phi[1] + 0.3).abs() < 0.1, "{:?}", model.phi);
+
+        assert!(matches!(model.fit_backward(&data[..12].to_vec(), 6, 0.01), Err(NefeleError::InsufficientData(_))));
+    }
+
+    #[test]
+    fn winsorizing_resists_a_single_outlier() {
+        let clean = AutoRegressive::new().simulate_stationary_init(300, vec![0.5], 0.0, 1.0, 13).unwrap();
+        let mut spiky = clean.clone();
+        spiky[150] = 80.0;
+
+        let fit = |data: &Vec<f64>, winsorize: Option<(f64, f64)>| {
+            let mut model = AutoRegressive::new();
+            model.winsorize = winsorize;
+            model.fit(data, 1, ARMethod::OLS);
+            model.phi[0]
+        };
+        let reference = fit(&clean, None);
+        let plain_shift = (fit(&spiky, None) - reference).abs();
+        let robust_shift = (fit(&spiky, Some((1.0, 99.0))) - reference).abs();
+        assert!(plain_shift > 5.0 * robust_shift, "{} vs {}", plain_shift, robust_shift);
+
+        let capped = winsorize(&[1.0, 2.0, 3.0, 4.0, 100.0], 0.0, 75.0);
+        assert_eq!(capped, [1.0, 2.0, 3.0, 4.0, 4.0]);
+    }
+
+    #[test]
+    fn ols_falls_back_on_qr_for_collinear_lags() {
+        // the lags of a quadratic trend are nearly collinear: x_t = 3 x_{t-1} - 3 x_{t-2} + x_{t-3}
+        let data: Vec<f64> = (0..200).map(|t| (t as f64).powi(2)).collect();
+        let mut model = AutoRegressive::new();
+        model.fit(&data, 4, ARMethod::OLS);
+        assert_eq!(model.ols_solver(), Some(OLSSolver::QR));
+
+        // the coefficients still reproduce the series one step ahead
+        let residuals = model.residuals(&data).unwrap();
+        let largest = residuals[4..].iter().fold(0.0f64, |m, e| m.max(e.abs()));
+        assert!(largest < 1e-6 * data[199], "{}", largest);
+
+        let noisy = AutoRegressive::new().simulate_stationary_init(200, vec![0.5], 0.0, 1.0, 14).unwrap();
+        model.fit(&noisy, 4, ARMethod::OLS);
+        assert_eq!(model.ols_solver(), Some(OLSSolver::Cholesky));
+    }
+
+    #[test]
+    fn lazy_simulation_matches_the_seeded_path() {
+        let model = AutoRegressive::new();
+        let lazy: Vec<f64> = model.simulate_iter(vec![0.5, -0.2], 1.0, 2.0, 15).take(100).collect();
+        let path = model.simulate_ensemble(100, vec![0.5, -0.2], 1.0, 2.0, 1, 15).remove(0);
+        assert_eq!(lazy, path);
+
+        let white: Vec<f64> = model.simulate_iter(vec![], 0.0, 1.0, 15).take(3).collect();
+        assert_eq!(white, model.simulate_ensemble(3, vec![], 0.0, 1.0, 1, 15)[0]);
+    }
+
+    #[test]
+    fn stored_criteria_use_the_effective_sample() {
+        let data = AutoRegressive::new().simulate_stationary_init(200, vec![0.5, -0.2], 0.0, 1.0, 16).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data, 2, ARMethod::OLS);
+
+        // 198 regression rows, two coefficients and the innovation variance
+        let rss = model.sigma_squared * 198.0;
+        assert!((model.aic - aic(198, rss, 3)).abs() < 1e-9, "{} vs {}", model.aic, aic(198, rss, 3));
+        assert!((model.bic - bic(198, rss, 3)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn burg_keeps_the_prediction_error_variance() {
+        let data = AutoRegressive::new().simulate_stationary_init(5000, vec![0.5, -0.3], 0.0, 1.0, 21).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data, 2, ARMethod::BURG);
+
+        assert!(model.sigma_squared > 0.0);
+        assert!((model.sigma_squared - 1.0).abs() < 0.1, "{}", model.sigma_squared);
+        assert_eq!(model.reflection_coefficients.len(), 2);
+        assert!((model.reflection_coefficients[1] - model.phi[1]).abs() < 1e-10);
+    }
+
+    #[test]
+    fn burg_agrees_with_yule_walker_beyond_order_two() {
+        let data = AutoRegressive::new().simulate_stationary_init(3000, vec![0.3, 0.3, -0.6], 0.0, 1.0, 22).unwrap();
+        let mut burg = AutoRegressive::new();
+        burg.fit(&data, 3, ARMethod::BURG);
+        let mut yule_walker = AutoRegressive::new();
+        yule_walker.fit(&data, 3, ARMethod::YWALKER);
+
+        for (b, y) in burg.phi.iter().zip(&yule_walker.phi) {
+            assert!((b - y).abs() < 0.01, "{:?} vs {:?}", burg.phi, yule_walker.phi);
+        }
+    }
+
+    #[test]
+    fn from_acf_recovers_an_ar1() {
+        // AR(1) with phi 0.6 and unit innovations: rho_k = 0.6^k, gamma_0 = 1 / (1 - 0.36)
+        let rho: Vec<f64> = (0..4).map(|k| 0.6_f64.powi(k)).collect();
+        let model = AutoRegressive::from_acf(&rho, 1.0 / 0.64, 2);
+
+        assert_eq!(model.phi.len(), 2);
+        assert!((model.phi[0] - 0.6).abs() < 1e-10 && model.phi[1].abs() < 1e-10);
+        assert!((model.sigma_squared - 1.0).abs() < 1e-10);
+
+        // the order is capped by the autocorrelations available
+        assert_eq!(AutoRegressive::from_acf(&rho[..2], 1.0 / 0.64, 3).phi.len(), 1);
+    }
+
+    #[test]
+    fn autofit_caps_an_oversized_max_order() {
+        let data = AutoRegressive::new().simulate_stationary_init(30, vec![0.7], 0.0, 1.0, 23).unwrap();
+        let mut model = AutoRegressive::new();
+        model.autofit(&data, 100, &ARCriterion::AIC);
+
+        assert!(!model.phi.is_empty() && model.phi.len() <= 3, "{:?}", model.phi);
+        assert!(model.phi.iter().all(|p| p.is_finite()));
+    }
+
+    #[test]
+    fn complex_roots_report_their_period() {
+        // inverse roots 0.9 exp(+-i pi / 6): phi_1 = 2 r cos(w), phi_2 = -r^2, a 12 period cycle
+        let omega = std::f64::consts::PI / 6.0;
+        let model = AutoRegressive::try_from_coefficients(vec![1.8 * omega.cos(), -0.81], 1.0).unwrap();
+        let roots = model.roots_and_periods();
+        assert_eq!(roots.len(), 2);
+        for (root, modulus, period) in roots {
+            assert!((modulus - 0.9).abs() < 1e-10 && (root.im.abs() - 0.9 * omega.sin()).abs() < 1e-10);
+            assert!((period - 12.0).abs() < 1e-8, "{}", period);
+        }
+
+        let alternating = AutoRegressive::try_from_coefficients(vec![-0.5], 1.0).unwrap();
+        let (_, modulus, period) = alternating.roots_and_periods()[0];
+        assert!((modulus - 0.5).abs() < 1e-10 && (period - 2.0).abs() < 1e-10);
+        let (_, _, period) = AutoRegressive::try_from_coefficients(vec![0.5], 1.0).unwrap().roots_and_periods()[0];
+        assert!(period.is_infinite());
+    }
+
+    #[test]
+    fn ridge_penalty_shrinks_the_coefficients() {
+        let data = AutoRegressive::new().simulate_stationary_init(80, vec![0.5, 0.2], 0.0, 1.0, 26).unwrap();
+        let mut ols = AutoRegressive::new();
+        ols.fit(&data, 6, ARMethod::OLS);
+        let mut ridge = AutoRegressive::new();
+        ridge.fit_ridge(&data, 6, 0.0).unwrap();
+        for (r, o) in ridge.phi.iter().zip(&ols.phi) {
+            assert!((r - o).abs() < 1e-8, "{:?} vs {:?}", ridge.phi, ols.phi);
+        }
+
+        let mut previous = f64::INFINITY;
+        for lambda in [0.0, 1.0, 10.0, 100.0, 1000.0] {
+            ridge.fit_ridge(&data, 6, lambda).unwrap();
+            let norm = ridge.phi.iter().map(|p| p * p).sum::<f64>();
+            assert!(norm < previous, "lambda {}: {} after {}", lambda, norm, previous);
+            previous = norm;
+        }
+        assert!(matches!(ridge.fit_ridge(&data, 6, -1.0), Err(NefeleError::InvalidValue(_))));
+    }
+
+    #[test]
+    fn r_squared_separates_dependence_from_noise() {
+        // an AR(1) explains phi^2 of the variance of the process
+        let data = AutoRegressive::new().simulate_stationary_init(1000, vec![0.9], 0.0, 1.0, 27).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data, 1, ARMethod::OLS);
+        let r2 = model.r_squared(&data).unwrap();
+        assert!((r2 - 0.81).abs() < 0.05, "{}", r2);
+        assert!(model.adjusted_r_squared(&data).unwrap() < r2);
+
+        let noise = AutoRegressive::new().simulate_stationary_init(1000, vec![0.0], 0.0, 1.0, 28).unwrap();
+        model.fit(&noise, 1, ARMethod::OLS);
+        assert!(model.r_squared(&noise).unwrap().abs() < 0.02);
+    }
+
+    #[test]
+    fn autofit_matches_the_per_order_refit() {
+        let data = AutoRegressive::new().simulate_stationary_init(400, vec![0.5, -0.3, 0.2], 0.0, 1.0, 32).unwrap();
+        let check = |criterion: &dyn OrderCriterion, value: fn(&AutoRegressive) -> f64| {
+            let mut selected = AutoRegressive::new();
+            selected.autofit(&data, 8, criterion);
+
+            // refit Yule-Walker at every order and keep the best criterion
+            let best = (1..=8)
+                .map(|order| {
+                    let mut model = AutoRegressive::new();
+                    model.fit(&data, order, ARMethod::YWALKER);
+                    model
+                })
+                .min_by(|a, b| value(a).total_cmp(&value(b)))
+                .unwrap();
+
+            assert_eq!(selected.phi.len(), best.phi.len());
+            for (s, b) in selected.phi.iter().zip(&best.phi) {
+                assert!((s - b).abs() < 1e-10, "{:?} vs {:?}", selected.phi, best.phi);
+            }
+            assert!((selected.intercept - best.intercept).abs() < 1e-10);
+            assert!((value(&selected) - value(&best)).abs() < 1e-8);
+        };
+        check(&ARCriterion::AIC, |model| model.aic);
+        check(&ARCriterion::BIC, |model| model.bic);
+    }
+
+    #[test]
+    fn parameter_uncertainty_widens_the_predictive_spread() {
+        let data = AutoRegressive::new().simulate_stationary_init(60, vec![0.8], 0.0, 1.0, 33).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data, 1, ARMethod::OLS);
+        let phi = model.phi[0];
+
+        let horizon = 10;
+        let paths = model.posterior_predictive_sample(&data, horizon, 4000, 1).unwrap();
+        assert!(paths.len() == 4000 && paths.iter().all(|path| path.len() == horizon));
+        let last: Vec<f64> = paths.iter().map(|path| path[horizon - 1]).collect();
+        let mean = last.iter().sum::<f64>() / last.len() as f64;
+        let spread = last.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / last.len() as f64;
+
+        // variance of the forecast error with the coefficient held fixed
+        let fixed: f64 = (0..horizon).map(|j| model.sigma_squared * phi.powi(2 * j as i32)).sum();
+        assert!(spread > fixed, "{} vs {}", spread, fixed);
+
+        model.fit(&data, 1, ARMethod::YWALKER);
+        assert!(matches!(model.posterior_predictive_sample(&data, horizon, 10, 1), Err(NefeleError::InvalidValue(_))));
+    }
+
+    #[test]
+    fn differenced_fit_of_a_random_walk_is_white_noise() {
+        let steps = AutoRegressive::new().simulate_stationary_init(1000, vec![0.0], 0.0, 1.0, 34).unwrap();
+        let walk: Vec<f64> = steps.iter().scan(0.0, |level, step| { *level += step; Some(*level) }).collect();
+
+        let mut model = AutoRegressive::new();
+        model.fit_differenced(&walk, 1, 1, ARMethod::OLS).unwrap();
+        assert!(model.phi[0].abs() < 0.1, "{:?}", model.phi);
+
+        // the forecasts are integrated back to the level of the walk
+        let last = walk[walk.len() - 1];
+        let forecast = model.forecast(&walk, 5).unwrap();
+        assert!(forecast.iter().all(|f| (f - last).abs() < 0.5), "{} {:?}", last, forecast);
+
+        assert!(matches!(model.fit_differenced(&walk[..3], 2, 1, ARMethod::OLS), Err(NefeleError::InsufficientData(_))));
+    }
+
+    #[test]
+    fn parameter_clone_drops_the_update_state() {
+        let data = AutoRegressive::new().simulate_stationary_init(300, vec![0.6, -0.2], 0.0, 1.0, 36).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data[..299].to_vec(), 2, ARMethod::OLS);
+        model.update_weighted(data[299], &data[..299], 0.99).unwrap();
+        assert!(model.has_update_state());
+
+        let copy = model.clone_parameters();
+        assert!(!copy.has_update_state());
+        assert_eq!(copy.phi, model.phi);
+        assert_eq!(copy.forecast(&data, 5).unwrap(), model.forecast(&data, 5).unwrap());
+    }
+
+    #[test]
+    fn custom_criterion_selects_its_own_order() {
+        /// AIC with a penalty so heavy that only one lag is ever worth it.
+        struct Parsimonious;
+        impl OrderCriterion for Parsimonious {
+            fn evaluate(&self, n: usize, rss: f64, k: usize) -> f64 {
+                aic(n, rss, k) + 1000.0 * k as f64
+            }
+        }
+
+        let data = AutoRegressive::new().simulate_stationary_init(500, vec![0.3, 0.2, 0.4], 0.0, 1.0, 37).unwrap();
+        let mut model = AutoRegressive::new();
+        model.autofit(&data, 6, &ARCriterion::AIC);
+        assert_eq!(model.phi.len(), 3);
+        model.autofit(&data, 6, &Parsimonious);
+        assert_eq!(model.phi.len(), 1);
+    }
+
+    #[test]
+    fn coefficients_are_validated_on_construction() {
+        let model = AutoRegressive::try_from_coefficients(vec![0.5, -0.3], 2.0).unwrap();
+        assert_eq!(model.phi, [0.5, -0.3]);
+        assert_eq!(model.sigma_squared, 2.0);
+        assert!(model.forecast(&[1.0, 2.0, 3.0], 2).is_ok());
+        // the coefficients and the variance, but no intercept
+        assert_eq!(model.n_params(), 3);
+
+        for (phi, sigma2) in [(vec![1.5], 1.0), (vec![0.5, 0.6], 1.0), (vec![0.5], 0.0), (vec![f64::NAN], 1.0)] {
+            assert!(matches!(AutoRegressive::try_from_coefficients(phi, sigma2), Err(NefeleError::InvalidValue(_))));
+        }
+    }
+
+    #[test]
+    fn ols_leaves_exactly_collinear_lags_unfitted() {
+        // an alternating series makes the second lag the negative of the first
+        let data: Vec<f64> = (0..40).map(|t| if t % 2 == 0 { 1.0 } else { -1.0 }).collect();
+        let mut model = AutoRegressive::new();
+        model.fit(&(0..40).map(|t| (t as f64 * 0.7).sin()).collect(), 1, ARMethod::OLS);
+        assert!(model.fitted);
+
+        model.fit(&data, 2, ARMethod::OLS);
+        assert!(!model.fitted);
+        assert!(model.phi.is_empty());
+        assert_eq!(model.ols_solver, None);
+        assert!(model.aic.is_nan() && model.bic.is_nan());
+        assert_eq!(model.forecast(&data, 1), Err(NefeleError::NotFitted));
+    }
+
+    #[test]
+    fn css_with_initial_checks_and_resets_like_fit() {
+        let data = AutoRegressive::new().simulate_stationary_init(300, vec![0.5], 0.0, 1.0, 8).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data, 1, ARMethod::BURG);
+        assert!(!model.reflection_coefficients.is_empty());
+        model.fit_css_with_initial(&data, 1, vec![0.0, 0.1]).unwrap();
+        assert!(model.fitted && model.reflection_coefficients.is_empty());
+
+        model.fit(&data, 1, ARMethod::OLS);
+        assert!(model.ols_solver.is_some() && model.standard_errors.is_some());
+        model.fit_css_with_initial(&data, 1, vec![0.0, 0.1]).unwrap();
+        assert_eq!(model.ols_solver, None);
+        assert!(model.standard_errors.is_none());
+
+        let constant = vec![2.0; 50];
+        assert!(matches!(model.fit_css_with_initial(&constant, 1, vec![0.0, 0.1]), Err(NefeleError::DegenerateInput(_))));
+        assert!(!model.fitted && model.aic.is_nan());
+    }
 }
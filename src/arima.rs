@@ -1,7 +1,9 @@
 use rand_distr::{Distribution, Normal};
-use liblbfgs::lbfgs;
 use finitediff::FiniteDiff;
-use super::utils::{compute_variance, diff, inverse_diff, residuals, mean, pacf, compute_aic, compute_bic};
+use super::error::NefeleError;
+use super::forecaster::ForecastInterval;
+use super::summary::Summary;
+use super::utils::{ar_from_optimizer, ar_to_optimizer, diff, differenced_length, inverse_diff, forecast, undiff_forecast, normal_quantile, poly_mul, psi_weights, residuals, warm_up, css_objective, gaussian_loglik, mean, input_quality, initial_ar_from_pacf, aic, bic, OptimizerConfig, VarianceEstimator, innovation_variance, initial_ma_from_acf, OrderCriterion};
 
 /// ARIMA struct represents an autoregressive integrated moving average model.
 #[derive(Debug, Clone)]
@@ -9,9 +11,15 @@ pub struct ARIMA {
     pub phi: Vec<f64>,              // AR coefficients
     pub diff: usize,                // Differencing order
     pub theta: Vec<f64>,            // MA coefficients
+    pub intercept: f64,             // Intercept of the differenced series
     pub sigma_squared: f64,         // Variance of the model
     pub aic: f64,                   // AIC (Akaike Information Criterion) value
-    pub bic: f64                    // BIC (Bayesian Information Criterion) value
+    pub bic: f64,                   // BIC (Bayesian Information Criterion) value
+    pub enforce_stationarity: bool, // Whether the CSS and ML fits search only stationary AR coefficients
+    pub optimizer: OptimizerConfig, // Settings of the L-BFGS minimizer of the CSS and ML fits
+    pub variance_estimator: VarianceEstimator, // How sigma_squared is estimated; the AIC and BIC use the ARMA residuals either way
+    pub include_intercept: bool,    // Whether the fits estimate the intercept, held at zero otherwise
+    fitted: bool                    // Whether `fit` has been called
 }
 
 /// ARIMAMethod represents different methods for fitting an ARIMA model.
@@ -26,24 +34,171 @@ pub enum ARIMACriterion{
     BIC     // Bayesian Information Criterion
 }
 
+impl OrderCriterion for ARIMACriterion {
+    fn evaluate(&self, n: usize, rss: f64, k: usize) -> f64 {
+        match self {
+            ARIMACriterion::AIC => aic(n, rss, k),
+            ARIMACriterion::BIC => bic(n, rss, k),
+        }
+    }
+}
+
 impl ARIMA {
     /// Creates a new ARIMA struct with default values.
     pub fn new() -> ARIMA {
-        ARIMA { phi: vec![0.0;1], diff:0, theta:vec![0.0;1], sigma_squared: 0.0, aic: 0.0, bic: 0.0 }
+        ARIMA { phi: vec![0.0;1], diff:0, theta:vec![0.0;1], intercept: 0.0, sigma_squared: 0.0, aic: 0.0, bic: 0.0, enforce_stationarity: false, optimizer: OptimizerConfig::default(), variance_estimator: VarianceEstimator::PlugIn, include_intercept: true, fitted: false }
     }
 
     /// Prints a summary of the ARIMA model.
     pub fn summary(&self) {
-        println!(
-            "phi: {:?}\nd: {}\ntheta: {:?} \nsigma^2: {:?}",
-            self.phi, self.diff, self.theta, self.sigma_squared
-        )
+        println!("{}", self.to_summary())
+    }
+
+    /// Collects the estimates of the ARIMA model in a Summary struct.
+    pub fn to_summary(&self) -> Summary {
+        Summary {
+            model: format!("ARIMA({}, {}, {})", self.phi.len(), self.diff, self.theta.len()),
+            fitted: self.fitted,
+            phi: self.phi.clone(),
+            phi_std_errors: None,
+            diff: Some(self.diff as f64),
+            theta: self.theta.clone(),
+            intercept: self.intercept,
+            sigma_squared: self.sigma_squared,
+            aic: Some(self.aic),
+            bic: Some(self.bic)
+        }
+    }
+
+    /// Returns the number of free parameters, intercept and innovation variance included.
+    pub fn n_params(&self) -> usize {
+        self.phi.len() + self.theta.len() + 1 + usize::from(self.include_intercept)
+    }
+
+    /// Computes the in-sample residuals of the fitted model on the differenced data.
+    pub fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(residuals(&diff(data, self.diff), self.intercept, &self.phi, &self.theta))
+    }
+
+    /// Returns the series the ARMA part of the model is fitted to: the data differenced `diff`
+    /// times by `x_t - x_{t-1}`, losing the first `diff` observations. With `diff = 0` this is
+    /// the data itself.
+    pub fn differenced_data(&self, data: &[f64]) -> Vec<f64> {
+        diff(data, self.diff)
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data, on the original (undifferenced) scale.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let diff_forecast = forecast(&diff(data, self.diff), self.intercept, &self.phi, &self.theta, horizon);
+        Ok(undiff_forecast(data, &diff_forecast, self.diff))
     }
 
-    /// Simulates an ARIMA process.
+    /// Forecasts `horizon` steps ahead of the provided data together with the lower and upper
+    /// bounds of the `confidence` (e.g. 0.95) prediction intervals, on the original scale.
+    ///
+    /// The forecast error variance `sigma^2 * sum_{j<h} psi_j^2` uses the psi weights of the
+    /// integrated AR polynomial `phi(L) (1 - L)^d`, so that it grows polynomially with the
+    /// horizon when `d >= 1` (linearly for a random walk). `sigma^2` is the variance of the
+    /// in-sample residuals.
+    pub fn forecast_interval(&self, data: &[f64], horizon: usize, confidence: f64) -> Result<ForecastInterval, NefeleError> {
+        let forecast = self.forecast(data, horizon)?;
+
+        let residuals = self.residuals(data)?;
+        let errors = &residuals[warm_up(self.phi.len(), self.theta.len())..];
+        let sigma_squared = errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64;
+
+        // 1 - phi_1 L - ... - phi_p L^p, times (1 - L)^d
+        let mut ar_poly: Vec<f64> = vec![1.0];
+        ar_poly.extend(self.phi.iter().map(|p| -p));
+        for _ in 0..self.diff {
+            ar_poly = poly_mul(&ar_poly, &[1.0, -1.0]);
+        }
+        let phi_star: Vec<f64> = ar_poly[1..].iter().map(|p| -p).collect();
+        let psi = psi_weights(&phi_star, &self.theta, horizon);
+
+        let z = normal_quantile(0.5 + confidence / 2.0);
+        let mut variance = 0.0;
+        let mut lower: Vec<f64> = Vec::with_capacity(horizon);
+        let mut upper: Vec<f64> = Vec::with_capacity(horizon);
+        for h in 0..horizon {
+            variance += sigma_squared * psi[h] * psi[h];
+            lower.push(forecast[h] - z * variance.sqrt());
+            upper.push(forecast[h] + z * variance.sqrt());
+        }
+
+        Ok((forecast, lower, upper))
+    }
+
+    /// Forecasts `horizon` steps ahead with `confidence` prediction intervals, as
+    /// `forecast_interval` does, in a data frame with one row per step and the columns
+    /// `step` (from 1), `forecast`, `lower` and `upper`.
+    #[cfg(feature = "polars")]
+    pub fn forecast_frame(&self, data: &[f64], horizon: usize, confidence: f64) -> Result<polars::prelude::DataFrame, NefeleError> {
+        let (forecast, lower, upper) = self.forecast_interval(data, horizon, confidence)?;
+        let step: Vec<u32> = (1..=horizon as u32).collect();
+
+        Ok(polars::df!(
+            "step" => step,
+            "forecast" => forecast,
+            "lower" => lower,
+            "upper" => upper
+        ).expect("the columns all have `horizon` rows"))
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data around a known `mean` of the
+    /// original series instead of the estimated intercept: the data are centered on `mean`,
+    /// forecast with a zero intercept and shifted back. For `d > 0` differencing removes
+    /// the mean, so the result does not depend on it and equals a driftless forecast.
+    pub fn forecast_with_mean(&self, data: &[f64], horizon: usize, mean: f64) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let centered: Vec<f64> = data.iter().map(|x| x - mean).collect();
+        let diff_forecast = forecast(&diff(&centered, self.diff), 0.0, &self.phi, &self.theta, horizon);
+        let forecast = undiff_forecast(&centered, &diff_forecast, self.diff);
+        Ok(forecast.iter().map(|f| f + mean).collect())
+    }
+
+    /// Simulates an ARIMA process, as [`ARIMA::try_simulate`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length` is shorter than the differencing order `diff`.
     pub fn simulate(&self, length: usize, phi: Vec<f64>,
         diff: usize,
         theta: Vec<f64>, error_mean: f64, error_variance: f64) -> Vec<f64> {
+        self.try_simulate(length, phi, diff, theta, error_mean, error_variance)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Simulates an ARIMA process of the given length, returning an `InvalidLength` error when
+    /// `length` is shorter than the differencing order `diff`, which needs as many initial values.
+    pub fn try_simulate(&self, length: usize, phi: Vec<f64>,
+        diff: usize,
+        theta: Vec<f64>, error_mean: f64, error_variance: f64) -> Result<Vec<f64>, NefeleError> {
+        self.simulate_full(length, phi, diff, theta, error_mean, error_variance)
+            .map(|(integrated, _)| integrated)
+    }
+
+    /// Simulates an ARIMA process as [`ARIMA::try_simulate`], returning both the integrated
+    /// series of `length` values and the stationary ARMA series it integrates, of
+    /// `differenced_length(length, diff)` values: differencing the first `diff` times gives
+    /// back the second.
+    pub fn simulate_full(&self, length: usize, phi: Vec<f64>,
+        diff: usize,
+        theta: Vec<f64>, error_mean: f64, error_variance: f64) -> Result<(Vec<f64>, Vec<f64>), NefeleError> {
+        if length < diff {
+            return Err(NefeleError::InvalidLength(format!(
+                "cannot simulate {} values of an ARIMA({}, {}, {}), at least {} needed",
+                length, phi.len(), diff, theta.len(), diff
+            )));
+        }
         let mut output: Vec<f64> = Vec::with_capacity(length);
 
         let ar_order = phi.len();
@@ -82,51 +237,142 @@ impl ARIMA {
             }
         }
 
-        if diff > 0 {
-            output = inverse_diff(&output[init..output.len() - diff].to_vec(), diff);
+        let mut stationary = output.split_off(init);
+        stationary.truncate(differenced_length(length, diff));
+        let integrated = if diff > 0 {
+            inverse_diff(&stationary, diff)
         } else {
-            output.drain(0..init);
-        }
+            stationary.clone()
+        };
 
-        output
+        Ok((integrated, stationary))
     }
 
     /// Fits the ARIMA model to the provided data.
+    ///
+    /// The AIC and BIC are those of the ARMA fit on the `d` times differenced series, whose
+    /// length is `differenced_length(data.len(), d)`, so for a fixed `d` they match the ARMA module's values on
+    /// the differenced data. Models with different `d` are fit to different series and their
+    /// criteria are not comparable: choose `d` beforehand, e.g. with a unit-root test, or
+    /// compare them with [`ARIMA::aic_comparable`].
     pub fn fit(&mut self, data: &Vec<f64>, p: usize, d: usize, q: usize, method: ARIMAMethod) {
-        if d > 0 {
-            let diff_data = diff(data, d);
+        if self.prepare_fit(data).is_err() {
+            return;
+        }
+        let diff_data = diff(data, d);
 
-            match method {
-                ARIMAMethod::CSS => Self::fit_css(self, &diff_data, p, q),
-                ARIMAMethod::ML => Self::fit_ml(self, &diff_data, p, q)
-            }
+        match method {
+            ARIMAMethod::CSS => Self::fit_css(self, &diff_data, p, q, None),
+            ARIMAMethod::ML => Self::fit_ml(self, &diff_data, p, q)
+        }
 
-            self.diff = d;
-            self.sigma_squared = compute_variance(&diff_data, &self.phi);
-            self.aic = compute_aic(data.len(), self.sigma_squared, p + q);
-            self.bic = compute_bic(data.len(), self.sigma_squared, p + q);
-        } else {
-            match method {
-                ARIMAMethod::CSS => Self::fit_css(self, &data, p, q),
-                ARIMAMethod::ML => Self::fit_ml(self, &data, p, q)
-            }
-            self.sigma_squared = compute_variance(&data, &self.phi);
-            self.aic = compute_aic(data.len(), self.sigma_squared, p + q);
-            self.bic = compute_bic(data.len(), self.sigma_squared, p + q);
+        // coefficients, intercept and innovation variance
+        self.set_fit_statistics(&diff_data, d, p + q + 1 + usize::from(self.include_intercept));
+    }
+
+    /// Fits the model by CSS starting the optimizer from `initial` instead of the default
+    /// guesses. `initial` holds the intercept followed by the AR and MA coefficients, so it must
+    /// have length `1 + p + q`. Trying several starting points helps escaping bad local minima.
+    /// The intercept is ignored, and held at zero, when `include_intercept` is false.
+    /// The data are checked as in `fit`, and a degenerate series returns its problem as an error.
+    pub fn fit_css_with_initial(&mut self, data: &Vec<f64>, p: usize, d: usize, q: usize, initial: Vec<f64>) -> Result<(), NefeleError> {
+        if initial.len() != 1 + p + q {
+            return Err(NefeleError::InvalidLength(format!(
+                "initial guess has {} values, expected {}", initial.len(), 1 + p + q
+            )));
+        }
+        self.prepare_fit(data)?;
+        let diff_data = diff(data, d);
+
+        Self::fit_css(self, &diff_data, p, q, Some(initial));
+        self.set_fit_statistics(&diff_data, d, p + q + 1 + usize::from(self.include_intercept));
+        Ok(())
+    }
+
+    /// Computes the AIC of the fitted model on a sample of the levels which models of any
+    /// differencing order can share, so that their criteria are comparable across `d`.
+    ///
+    /// Differencing given the first `d` observations is a triangular transform with unit
+    /// Jacobian, so the likelihood of the levels equals that of the differenced series and the
+    /// residuals are the one-step errors of the levels: no scale correction is needed. What
+    /// differs across `d` is the sample, since the stored `aic` conditions on the first
+    /// `d + max(p, q)` observations. Here the criterion uses only the observations from `skip`
+    /// on; pass every compared model the same `skip`, at least the largest `d + max(p, q)`
+    /// among them. Returns an `InvalidValue` error if `skip` is below this model's own.
+    pub fn aic_comparable(&self, data: &[f64], skip: usize) -> Result<f64, NefeleError> {
+        let residuals = self.residuals(data)?;
+        let own_skip = self.diff + warm_up(self.phi.len(), self.theta.len());
+        if skip < own_skip {
+            return Err(NefeleError::InvalidValue(format!(
+                "skip {} below the {} observations an ARIMA({}, {}, {}) conditions on",
+                skip, own_skip, self.phi.len(), self.diff, self.theta.len()
+            )));
         }
+
+        // residuals[i] is the one-step error of data[i + d]
+        let errors = &residuals[differenced_length(skip, self.diff).min(residuals.len())..];
+        let rss: f64 = errors.iter().map(|e| e * e).sum();
+        Ok(aic(errors.len(), rss, self.phi.len() + self.theta.len() + 1 + usize::from(self.include_intercept)))
     }
 
-    /// Automatically fits the ARIMA model by selecting the order based on a criterion.
-    pub fn autofit(&mut self, data: &Vec<f64>, d: usize, max_ar_order: usize, max_ma_order: usize, criterion: ARIMACriterion) {     
+    /// Checks the data before a fit. On a degenerate series the model is left unfitted with
+    /// NaN statistics and the problem returned as an error.
+    fn prepare_fit(&mut self, data: &[f64]) -> Result<(), NefeleError> {
+        let quality = input_quality(data);
+        quality.warn();
+        if quality.is_degenerate() {
+            self.fitted = false;
+            self.sigma_squared = f64::NAN;
+            self.aic = f64::NAN;
+            self.bic = f64::NAN;
+            return quality.check();
+        }
+        Ok(())
+    }
+
+    /// Stores the differencing order, the variance and the information criteria of a fit
+    /// with `k` free parameters, all on the differenced series.
+    fn set_fit_statistics(&mut self, diff_data: &Vec<f64>, d: usize, k: usize) {
+        self.diff = d;
+        let (sigma_squared, rss, n) = innovation_variance(diff_data, self.intercept, &self.phi, &self.theta, self.variance_estimator);
+        self.sigma_squared = sigma_squared;
+        self.aic = aic(n, rss, k);
+        self.bic = bic(n, rss, k);
+        self.fitted = true;
+    }
+
+    /// Automatically fits the ARIMA model by selecting the order based on a criterion. All the
+    /// candidates share `d`, so their criteria are comparable.
+    pub fn autofit(&mut self, data: &Vec<f64>, d: usize, max_ar_order: usize, max_ma_order: usize, criterion: &dyn OrderCriterion) {
+        self.autofit_verbose(data, d, max_ar_order, max_ma_order, criterion);
+    }
+
+    /// Fits an ARIMA(p, `d`, q) by CSS for every `p <= max_ar_order` and `q <= max_ma_order`,
+    /// keeps the one minimizing the criterion, as `autofit` does, and returns the whole search
+    /// path: for every candidate `(p, q)`, in order, its AR then MA coefficients and its
+    /// criterion value.
+    pub fn autofit_verbose(&mut self, data: &Vec<f64>, d: usize, max_ar_order: usize, max_ma_order: usize, criterion: &dyn OrderCriterion) -> Vec<((usize, usize), Vec<f64>, f64)> {
+        let mut path = Vec::with_capacity((max_ar_order + 1) * (max_ma_order + 1));
         let diff_data = diff(data, d);
-        
-        match criterion {
-            ARIMACriterion::AIC => Self::autofit_aic(self, &diff_data, max_ar_order, max_ma_order),
-            ARIMACriterion::BIC => Self::autofit_bic(self, &diff_data, max_ar_order, max_ma_order),
+        for ar_order in 0..=max_ar_order {
+            for ma_order in 0..=max_ma_order {
+                self.fit(data, ar_order, d, ma_order, ARIMAMethod::CSS);
+                let (_, rss, n) = innovation_variance(&diff_data, self.intercept, &self.phi, &self.theta, self.variance_estimator);
+                let value = criterion.evaluate(n, rss, ar_order + ma_order + 1 + usize::from(self.include_intercept));
+                let coefficients = self.phi.iter().chain(&self.theta).copied().collect();
+                path.push(((ar_order, ma_order), coefficients, value));
+            }
         }
+
+        let ((ar_order, ma_order), _, _) = path.iter()
+            .min_by(|(_, _, a), (_, _, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .expect("the search grid is never empty");
+        self.fit(data, *ar_order, d, *ma_order, ARIMAMethod::CSS);
+        path
     }
 
-    fn fit_css(&mut self, data: &Vec<f64>, ar: usize, ma: usize) {
+    fn fit_css(&mut self, data: &Vec<f64>, ar: usize, ma: usize, initial: Option<Vec<f64>>) {
+        let enforce = self.enforce_stationarity;
 
         let total_size = 1 + ar + ma;
 
@@ -136,50 +382,58 @@ impl ARIMA {
             assert_eq!(coef.len(), total_size);
 
             let intercept = coef[0];
-            let phi = &coef[1..ar + 1];
+            let phi = &ar_from_optimizer(&coef[1..ar + 1], enforce);
             let theta = &coef[ar + 1..];
 
-            let residuals = residuals(&data, intercept, &phi.to_vec(), &theta.to_vec());
-
-            let mut css: f64 = 0.0;
-            for residual in &residuals {
-                css += residual * residual;
-            }
-            css
+            css_objective(data, intercept, phi, theta)
         };
         let g = |coef: &Vec<f64>| coef.forward_diff(&f);
 
-        // Initial coefficients
-        let mut coef: Vec<f64> = Vec::new();
+        // Initial coefficients, unless supplied by the caller
+        let mut coef: Vec<f64> = match initial {
+            Some(initial) => initial,
+            None => {
+                let mut coef: Vec<f64> = Vec::new();
 
-        // Initial guess for the intercept: First value of data
-        coef.push(mean(&data));
+                // Initial guess for the intercept: First value of data
+                coef.push(mean(&data));
 
-        // Initial guess for the AR coefficients: Values of the PACF
-        if ar > 0 {
-            let pacf = pacf(&data, Some(ar));
-            for p in pacf {
-                coef.push(p);
+                // Initial guess for the AR coefficients: Values of the PACF
+                if ar > 0 {
+                    coef.extend(initial_ar_from_pacf(data, ar));
+                }
+
+                // Initial guess for the MA coefficients: matched to the ACF of the AR-filtered data
+                if ma > 0 {
+                    let filtered = residuals(data, 0.0, &coef[1..], &[]);
+                    coef.extend(initial_ma_from_acf(&filtered[ar.min(filtered.len())..], ma));
+                }
+
+                coef
             }
-        }
+        };
+
+        // With enforced stationarity the AR part is searched in the unconstrained space
+        let start = ar_to_optimizer(&coef[1..=ar], enforce);
+        coef[1..=ar].copy_from_slice(&start);
 
-        // Initial guess for the MA coefficients: 1.0
-        if ma > 0 {
-            coef.resize(coef.len() + ma, 1.0);
+        // Without intercept it is held at zero and only the coefficients are searched
+        let skip = usize::from(!self.include_intercept);
+        if skip == 1 {
+            coef[0] = 0.0;
         }
 
         let evaluate = |x: &[f64], gx: &mut [f64]| {
-            let x = x.to_vec();
+            let x: Vec<f64> = std::iter::repeat_n(0.0, skip).chain(x.iter().cloned()).collect();
             let fx = f(&x);
             let gx_eval = g(&x);
             // copy values from gx_eval into gx
-            gx[..gx_eval.len()].copy_from_slice(&gx_eval[..]);
+            gx.copy_from_slice(&gx_eval[skip..]);
             Ok(fx)
         };
 
-        let fmin = lbfgs().with_max_iterations(200);
-        if let Err(e) = fmin.minimize(
-            &mut coef, // input variables
+        if let Err(e) = self.optimizer.minimize(
+            &mut coef[skip..], // input variables
             evaluate,  // define how to evaluate function
             |_prng| {
                 false 
@@ -188,106 +442,255 @@ impl ARIMA {
             tracing::warn!("{}", e);
         }
         
-        self.phi = coef[1..=ar].to_vec();
+        self.intercept = coef[0];
+        self.phi = ar_from_optimizer(&coef[1..=ar], enforce);
         self.theta = coef[ar+1..].to_vec();
     }
 
     fn fit_ml(&mut self, data: &Vec<f64>, ar: usize, ma: usize) {
-        // Initial guess for parameters
-        let initial_guess: Vec<f64> = vec![0.0; ar + ma + 1];
-
-        // Objective function for MLE estimation
-        let f = |params: &[f64]| -> f64 {
-            let phi = &params[1..ar + 1];
-            let theta = &params[ar + 1..];
-            let mut log_likelihood = 0.0;
-
-            for t in (ar + ma)..data.len() {
-                let mut prediction = params[0];
-                for i in 0..ar {
-                    prediction += phi[i] * data[t - i - 1];
-                }
-                for j in 0..ma {
-                    prediction += theta[j] * data[t - j - 1];
-                }
-                let residual = data[t] - prediction;
-                log_likelihood -= 0.5 * (residual * residual).ln();
-            }
+        let enforce = self.enforce_stationarity;
+        let total_size = 1 + ar + ma;
 
-            // println!("LL: {:?}, {:?}, {}",phi, theta, -log_likelihood);
-            -log_likelihood // negative log likelihood
+        // The objective is the negative Gaussian log-likelihood, with the
+        // innovation variance profiled out
+        let f = |coef: &Vec<f64>| {
+            assert_eq!(coef.len(), total_size);
+
+            let intercept = coef[0];
+            let phi = &ar_from_optimizer(&coef[1..ar + 1], enforce);
+            let theta = &coef[ar + 1..];
+
+            -gaussian_loglik(data, intercept, phi, theta)
         };
+        let g = |coef: &Vec<f64>| coef.forward_diff(&f);
 
-        // Compute gradient using finite differences
-        let mut gradient = vec![0.0; ar + ma + 1];
-        let epsilon = 1e-6;
-        for i in 0..(ar + ma + 1) {
-            let mut params_plus = initial_guess.clone();
-            params_plus[i] += epsilon;
-            let fx_plus = f(&params_plus);
+        // Initial coefficients
+        let mut coef: Vec<f64> = Vec::new();
 
-            let mut params_minus = initial_guess.clone();
-            params_minus[i] -= epsilon;
-            let fx_minus = f(&params_minus);
+        // Initial guess for the intercept: Mean of data
+        coef.push(mean(data));
 
-            gradient[i] = (fx_plus - fx_minus) / (2.0 * epsilon);
+        // Initial guess for the AR coefficients: Values of the PACF
+        if ar > 0 {
+            coef.extend(initial_ar_from_pacf(data, ar));
+        }
+
+        // Initial guess for the MA coefficients: 0.0
+        coef.resize(total_size, 0.0);
+
+        // With enforced stationarity the AR part is searched in the unconstrained space
+        let start = ar_to_optimizer(&coef[1..=ar], enforce);
+        coef[1..=ar].copy_from_slice(&start);
+
+        // Without intercept it is held at zero and only the coefficients are searched
+        let skip = usize::from(!self.include_intercept);
+        if skip == 1 {
+            coef[0] = 0.0;
         }
 
-        let mut optimized_params = initial_guess.clone();
-        
         let evaluate = |x: &[f64], gx: &mut [f64]| {
-            let fx = f(x);
-            gx.copy_from_slice(&gradient);
+            let x: Vec<f64> = std::iter::repeat_n(0.0, skip).chain(x.iter().cloned()).collect();
+            let fx = f(&x);
+            let gx_eval = g(&x);
+            // copy values from gx_eval into gx
+            gx.copy_from_slice(&gx_eval[skip..]);
             Ok(fx)
         };
 
-        let fmin = lbfgs().with_max_iterations(200);
-        if let Err(e) = fmin.minimize(&mut optimized_params, evaluate, |_prng| { false }) {
+        if let Err(e) = self.optimizer.minimize(&mut coef[skip..], evaluate, |_prng| { false }) {
             tracing::warn!("{}", e);
         }
 
         // Extract estimated parameters
-        self.phi = optimized_params[1..=ar].to_vec();
-        self.theta = optimized_params[ar + 1..].to_vec();
+        self.intercept = coef[0];
+        self.phi = ar_from_optimizer(&coef[1..=ar], enforce);
+        self.theta = coef[ar + 1..].to_vec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rand_distr::StandardNormal;
+
+    /// Random walk of standard normal steps.
+    fn random_walk(length: usize, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let steps: Vec<f64> = (0..length).map(|_| rng.sample(StandardNormal)).collect();
+        steps.iter().scan(0.0, |level, step| { *level += step; Some(*level) }).collect()
     }
-    
 
-    fn autofit_aic(&mut self, data: &Vec<f64>, max_ar_order: usize, max_ma_order: usize) {
-        let mut aic: Vec<f64> = Vec::with_capacity((max_ar_order + 1) * (max_ma_order + 1));
-    
-        for ar_order in 0..=max_ar_order {
-            for ma_order in 0..=max_ma_order {
-                Self::fit(self, data, ar_order,0, ma_order, ARIMAMethod::CSS);
-                aic.push(self.aic);
+    #[test]
+    fn unfitted_model_reports_not_fitted() {
+        let model = ARIMA::new();
+        let data = vec![1.0, 2.0, 1.5, 0.5, 1.0];
 
-                println!("ar: {}, ma: {}, aic: {}\n", ar_order, ma_order, self.aic);
-            }
+        assert_eq!(model.forecast(&data, 3), Err(NefeleError::NotFitted));
+        assert_eq!(model.residuals(&data), Err(NefeleError::NotFitted));
+        assert!(model.to_summary().to_string().contains("not yet fitted"));
+    }
+
+    #[test]
+    fn random_walk_interval_variance_grows_linearly() {
+        let data = random_walk(500, 1);
+        let mut model = ARIMA::new();
+        model.fit(&data, 0, 1, 0, ARIMAMethod::CSS);
+
+        let (forecast, _, upper) = model.forecast_interval(&data, 10, 0.95).unwrap();
+        let half_width: Vec<f64> = upper.iter().zip(&forecast).map(|(u, f)| u - f).collect();
+        for (h, width) in half_width.iter().enumerate() {
+            let ratio = (width / half_width[0]).powi(2);
+            assert!((ratio - (h + 1) as f64).abs() < 1e-9, "h = {}: {}", h + 1, ratio);
         }
-    
-        let min_order = aic
-            .iter()
-            .enumerate()
-            .min_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(index, _)| index)
-            .unwrap_or(0);
-    
-        let ar_order = min_order / (max_ma_order + 1); // Integer division for ar_order
-        let ma_order = min_order % (max_ma_order + 1); // Using modulo for ma_order
-    
-        Self::fit(self, data, ar_order, 0, ma_order, ARIMAMethod::CSS);
-    }  
-
-    fn autofit_bic(&mut self, data: &Vec<f64>, max_ar_order: usize, max_ma_order: usize){
-        let mut bic:Vec<f64> = Vec::with_capacity(max_ar_order * max_ma_order);
-            for ar_order in 1..(max_ar_order+1){
-                for ma_order in 1..(max_ma_order+1){
-                Self::fit(self, data, ar_order,0, ma_order, ARIMAMethod::CSS);
-                bic.push(self.bic);}
-            
-            let ar_order =1;
-            let ma_order =1;
-
-            Self::fit(self, data, ar_order, 0, ma_order, ARIMAMethod::CSS);
+    }
+
+    #[test]
+    fn criteria_match_the_arma_fit_of_the_differences() {
+        use crate::arma::{ARMAMethod, ARMA};
+
+        let data = random_walk(400, 5);
+        let mut arima = ARIMA::new();
+        arima.fit(&data, 1, 1, 1, ARIMAMethod::CSS);
+        let mut arma = ARMA::new();
+        arma.fit(&diff(&data, 1), 1, 1, ARMAMethod::CSS);
+
+        assert!((arima.sigma_squared - arma.sigma_squared).abs() < 1e-6 * arma.sigma_squared);
+        assert!((arima.aic - arma.aic).abs() < 1e-4, "{} vs {}", arima.aic, arma.aic);
+        assert!((arima.bic - arma.bic).abs() < 1e-4, "{} vs {}", arima.bic, arma.bic);
+    }
+
+    #[test]
+    fn differenced_data_is_what_the_model_saw() {
+        let data = random_walk(100, 3);
+        let mut model = ARIMA::new();
+        model.fit(&data, 1, 0, 0, ARIMAMethod::CSS);
+        assert_eq!(model.differenced_data(&data), data);
+
+        model.fit(&data, 1, 2, 0, ARIMAMethod::CSS);
+        let differenced = model.differenced_data(&data);
+        assert_eq!(differenced.len(), 98);
+        assert!((differenced[0] - (data[2] - 2.0 * data[1] + data[0])).abs() < 1e-12);
+    }
+
+    #[test]
+    fn verbose_autofit_reports_every_candidate() {
+        let data = random_walk(300, 8);
+        let mut model = ARIMA::new();
+        let path = model.autofit_verbose(&data, 1, 2, 1, &ARIMACriterion::BIC);
+
+        assert_eq!(path.len(), 6);
+        for ((p, q), coefficients, value) in &path {
+            assert_eq!(coefficients.len(), p + q);
+            assert!(value.is_finite());
         }
+        let ((p, q), coefficients, _) = path.iter()
+            .min_by(|(_, _, a), (_, _, b)| a.partial_cmp(b).unwrap())
+            .unwrap();
+        assert_eq!((model.phi.len(), model.theta.len()), (*p, *q));
+        let selected: Vec<f64> = model.phi.iter().chain(&model.theta).copied().collect();
+        assert_eq!(&selected, coefficients);
+    }
+
+    #[cfg(feature = "polars")]
+    #[test]
+    fn forecast_frame_has_a_row_per_step() {
+        let data = random_walk(300, 4);
+        let mut model = ARIMA::new();
+        model.fit(&data, 1, 1, 0, ARIMAMethod::CSS);
+
+        let frame = model.forecast_frame(&data, 7, 0.9).unwrap();
+        assert_eq!(frame.height(), 7);
+        let columns: Vec<&str> = frame.get_column_names().iter().map(|name| name.as_str()).collect();
+        assert_eq!(columns, ["step", "forecast", "lower", "upper"]);
+
+        let (forecast, _, _) = model.forecast_interval(&data, 7, 0.9).unwrap();
+        let column: Vec<f64> = frame.column("forecast").unwrap().f64().unwrap().into_no_null_iter().collect();
+        assert_eq!(column, forecast);
+    }
+
+    #[test]
+    fn too_short_simulation_is_a_descriptive_error() {
+        let model = ARIMA::new();
+        match model.try_simulate(1, vec![0.5], 2, vec![0.3], 0.0, 1.0) {
+            Err(NefeleError::InvalidLength(message)) => assert!(message.contains("ARIMA(1, 2, 1)"), "{}", message),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(model.try_simulate(50, vec![0.5], 1, vec![], 0.0, 1.0).unwrap().len(), 50);
+    }
+
+    #[test]
+    fn comparable_criteria_share_the_sample_across_d() {
+        let data = random_walk(400, 5);
+        let mut levels = ARIMA::new();
+        levels.fit(&data, 1, 0, 0, ARIMAMethod::CSS);
+        let mut differences = ARIMA::new();
+        differences.fit(&data, 0, 1, 0, ARIMAMethod::CSS);
+
+        // on the same sample the two models of a random walk fit about equally well
+        let aic_levels = levels.aic_comparable(&data, 1).unwrap();
+        let aic_differences = differences.aic_comparable(&data, 1).unwrap();
+        assert!((aic_levels - aic_differences).abs() < 10.0, "{} vs {}", aic_levels, aic_differences);
+
+        let mut second = ARIMA::new();
+        second.fit(&data, 0, 2, 0, ARIMAMethod::CSS);
+        assert!(matches!(second.aic_comparable(&data, 1), Err(NefeleError::InvalidValue(_))));
+
+        // over-differencing is penalized on the shared sample
+        assert!(second.aic_comparable(&data, 2).unwrap() > differences.aic_comparable(&data, 2).unwrap());
+    }
+
+    #[test]
+    fn criteria_count_the_differenced_observations() {
+        let data = random_walk(200, 6);
+        let mut model = ARIMA::new();
+        model.variance_estimator = VarianceEstimator::ML;
+        model.fit(&data, 1, 1, 0, ARIMAMethod::CSS);
+
+        // d = 1 and one warm-up value: 198 of the 200 observations enter the criteria
+        let residuals = model.residuals(&data).unwrap();
+        assert_eq!(residuals.len(), differenced_length(data.len(), 1));
+        let rss: f64 = residuals[1..].iter().map(|e| e * e).sum();
+        assert!((model.aic - aic(198, rss, 3)).abs() < 1e-8, "{} vs {}", model.aic, aic(198, rss, 3));
+        assert!((model.bic - bic(198, rss, 3)).abs() < 1e-8);
+
+        // from skip 5 on, the residuals of observations 5 to 199
+        let shared: f64 = residuals[4..].iter().map(|e| e * e).sum();
+        assert!((model.aic_comparable(&data, 5).unwrap() - aic(195, shared, 3)).abs() < 1e-8);
+    }
+
+    #[test]
+    fn differencing_the_simulation_recovers_the_stationary_series() {
+        let model = ARIMA::new();
+        for d in 0..=2 {
+            let (integrated, stationary) = model.simulate_full(300, vec![0.5], d, vec![0.3], 0.0, 1.0).unwrap();
+            assert_eq!(integrated.len(), 300);
+            assert_eq!(stationary.len(), differenced_length(300, d));
+            for (x, y) in diff(&integrated, d).iter().zip(&stationary) {
+                assert!((x - y).abs() < 1e-8 * (1.0 + y.abs()), "d = {}: {} vs {}", d, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn driftless_fit_holds_the_intercept_at_zero() {
+        let data = random_walk(500, 7);
+        let mut drift = ARIMA::new();
+        drift.fit(&data, 1, 1, 0, ARIMAMethod::CSS);
+        let mut driftless = ARIMA::new();
+        driftless.include_intercept = false;
+        driftless.fit(&data, 1, 1, 0, ARIMAMethod::CSS);
+
+        assert_eq!(driftless.intercept, 0.0);
+        assert!((drift.phi[0] - driftless.phi[0]).abs() < 0.02, "{:?} vs {:?}", drift.phi, driftless.phi);
+        assert_eq!(drift.n_params(), driftless.n_params() + 1);
+    }
+
+    #[test]
+    fn css_with_initial_rejects_degenerate_input() {
+        let mut model = ARIMA::new();
+        let data = vec![1.0, f64::NAN, 2.0, 3.0, 4.0, 5.0];
+        assert!(matches!(model.fit_css_with_initial(&data, 1, 1, 0, vec![0.0, 0.1]), Err(NefeleError::InvalidValue(_))));
+        assert!(!model.fitted && model.sigma_squared.is_nan());
     }
 }
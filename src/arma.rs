@@ -1,22 +1,33 @@
+use nalgebra::{DMatrix, DVector};
+use rand::{rngs::StdRng, SeedableRng};
 use rand_distr::{Distribution, Normal};
-use liblbfgs::lbfgs;
 use finitediff::FiniteDiff;
-use super::utils::{pacf, residuals, compute_aic, compute_bic, compute_variance, mean};
+use super::error::NefeleError;
+use super::forecaster::{ForecastState, Prediction};
+use super::summary::Summary;
+use super::utils::{arma_acf, arma_pacf, ar_from_optimizer, ar_roots, ar_to_optimizer, forecast, initial_ar_from_pacf, residuals, r_squared, simulate_stationary, warm_up, css_objective, gaussian_loglik, aic, bic, mean, input_quality, OptimizerConfig, VarianceEstimator, innovation_variance, initial_ma_from_acf, periodogram, spectral_density, residual_jacobian, OrderCriterion};
 
 /// ARMA struct represents an autoregressive moving average model.
 #[derive(Debug, Clone)]
 pub struct ARMA {
     pub phi: Vec<f64>,              // AR coefficients
     pub theta: Vec<f64>,            // MA coefficients
+    pub intercept: f64,             // Intercept
     pub sigma_squared: f64,         // Variance of the model
     pub aic: f64,                   // AIC (Akaike Information Criterion) value
-    pub bic: f64                    // BIC (Bayesian Information Criterion) value
+    pub bic: f64,                   // BIC (Bayesian Information Criterion) value
+    pub enforce_stationarity: bool, // Whether the CSS and ML fits search only stationary AR coefficients
+    pub optimizer: OptimizerConfig, // Settings of the L-BFGS minimizer of the CSS and ML fits
+    pub variance_estimator: VarianceEstimator, // How sigma_squared is estimated; the AIC and BIC use the ARMA residuals either way
+    pub include_intercept: bool,    // Whether the fits estimate the intercept, held at zero otherwise
+    fitted: bool                    // Whether `fit` has been called
 }
 
 /// ARMAMethod represents different methods for fitting an ARMA model.
 pub enum ARMAMethod {
     CSS,    // Conditional Sum of Squares
-    ML      // Maximum Likelihood
+    ML,     // Maximum Likelihood
+    WHITTLE // Whittle approximation of the likelihood in the frequency domain, from an O(n^2) periodogram
 }
 
 /// ARMACriterion represents criteria for selecting the order of the ARMA model.
@@ -25,17 +36,276 @@ pub enum ARMACriterion{
     BIC     // Bayesian Information Criterion
 }
 
+impl OrderCriterion for ARMACriterion {
+    fn evaluate(&self, n: usize, rss: f64, k: usize) -> f64 {
+        match self {
+            ARMACriterion::AIC => aic(n, rss, k),
+            ARMACriterion::BIC => bic(n, rss, k),
+        }
+    }
+}
+
 impl ARMA {
     /// Creates a new ARMA struct with default values.
     pub fn new() -> ARMA {
         let phi: Vec<f64> = vec![0.0; 1];
         let theta: Vec<f64> = vec![0.0; 1];
-        ARMA { phi, theta, sigma_squared: 0.0, aic: 0.0, bic: 0.0 }
+        ARMA { phi, theta, intercept: 0.0, sigma_squared: 0.0, aic: 0.0, bic: 0.0, enforce_stationarity: false, optimizer: OptimizerConfig::default(), variance_estimator: VarianceEstimator::PlugIn, include_intercept: true, fitted: false }
+    }
+
+    /// Builds an ARMA model from known coefficients `phi` and `theta` and innovation variance
+    /// `sigma2`, e.g. estimated elsewhere, with a zero intercept. The intercept is not a free
+    /// parameter, so `include_intercept` is false and `n_params` counts only the coefficients
+    /// and the variance; set it back before refitting the model to estimate one. Returns an
+    /// `InvalidValue` error if a value is not finite, `sigma2` is not positive, the AR
+    /// coefficients are not stationary or the MA coefficients are not invertible, i.e. an
+    /// inverse root of either polynomial lies on or outside the unit circle.
+    pub fn try_from_coefficients(phi: Vec<f64>, theta: Vec<f64>, sigma2: f64) -> Result<ARMA, NefeleError> {
+        if phi.iter().chain(&theta).any(|x| !x.is_finite()) || !(sigma2.is_finite() && sigma2 > 0.0) {
+            return Err(NefeleError::InvalidValue(format!(
+                "coefficients must be finite and the variance positive, found phi {:?}, theta {:?} and sigma2 {}",
+                phi, theta, sigma2
+            )));
+        }
+        if ar_roots(&phi).iter().any(|root| root.norm_sqr() >= 1.0) {
+            return Err(NefeleError::InvalidValue(format!("AR coefficients {:?} are not stationary", phi)));
+        }
+        let negated: Vec<f64> = theta.iter().map(|t| -t).collect();
+        if ar_roots(&negated).iter().any(|root| root.norm_sqr() >= 1.0) {
+            return Err(NefeleError::InvalidValue(format!("MA coefficients {:?} are not invertible", theta)));
+        }
+
+        let mut model = ARMA::new();
+        model.phi = phi;
+        model.theta = theta;
+        model.sigma_squared = sigma2;
+        model.include_intercept = false;
+        model.fitted = true;
+        Ok(model)
     }
 
     /// Prints a summary of the ARMA model.
     pub fn summary(&self) {
-        println!("phi: {:?} \ntheta: {:?} \nsigma^2: {:?}", self.phi, self.theta, self.sigma_squared);
+        println!("{}", self.to_summary());
+    }
+
+    /// Collects the estimates of the ARMA model in a Summary struct.
+    pub fn to_summary(&self) -> Summary {
+        Summary {
+            model: format!("ARMA({}, {})", self.phi.len(), self.theta.len()),
+            fitted: self.fitted,
+            phi: self.phi.clone(),
+            phi_std_errors: None,
+            diff: None,
+            theta: self.theta.clone(),
+            intercept: self.intercept,
+            sigma_squared: self.sigma_squared,
+            aic: Some(self.aic),
+            bic: Some(self.bic)
+        }
+    }
+
+    /// Returns the number of free parameters, intercept and innovation variance included.
+    pub fn n_params(&self) -> usize {
+        self.phi.len() + self.theta.len() + 1 + usize::from(self.include_intercept)
+    }
+
+    /// Returns the AR coefficient at the 1-based `lag`, or 0.0 for lag 0 and for lags beyond
+    /// the AR order, so that models of different orders can be queried uniformly.
+    pub fn phi_at(&self, lag: usize) -> f64 {
+        lag.checked_sub(1).and_then(|i| self.phi.get(i)).copied().unwrap_or(0.0)
+    }
+
+    /// Returns the MA coefficient at the 1-based `lag`, or 0.0 for lag 0 and for lags beyond
+    /// the MA order, so that models of different orders can be queried uniformly.
+    pub fn theta_at(&self, lag: usize) -> f64 {
+        lag.checked_sub(1).and_then(|i| self.theta.get(i)).copied().unwrap_or(0.0)
+    }
+
+    /// Computes the in-sample residuals of the fitted model on the provided data.
+    pub fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(residuals(data, self.intercept, &self.phi, &self.theta))
+    }
+
+    /// Computes the conventional standard errors of the intercept, AR and MA coefficients,
+    /// in this order, from the Gauss-Newton approximation `sigma^2 (J' J)^-1` of their
+    /// covariance, where `J` is the Jacobian of the residuals on the provided data and
+    /// `sigma^2 = rss / (n - k)`. They assume homoskedastic, correctly specified innovations.
+    pub fn std_errors(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        let (jacobian, errors) = self.residual_jacobian(data)?;
+        let information_inv = (jacobian.transpose() * &jacobian)
+            .try_inverse()
+            .ok_or_else(|| NefeleError::DegenerateInput("the residual Jacobian is rank deficient".to_string()))?;
+        let k = jacobian.ncols();
+        let n = data.len().saturating_sub(warm_up(self.phi.len(), self.theta.len()));
+        if n <= k {
+            return Err(NefeleError::InsufficientData(format!("{} residuals for {} parameters", n, k)));
+        }
+        let sigma_squared = errors.norm_squared() / (n - k) as f64;
+        Ok((0..k).map(|j| (sigma_squared * information_inv[(j, j)]).sqrt()).collect())
+    }
+
+    /// Computes heteroskedasticity-robust (Huber-White) standard errors of the intercept, AR
+    /// and MA coefficients, in this order, from the sandwich covariance `H^-1 G H^-1` of the
+    /// CSS objective at the fitted parameters: `H = 2 J' J` is the Gauss-Newton Hessian and
+    /// `G = sum_t 4 e_t^2 J_t J_t'` the outer product of the per-observation gradients. They
+    /// remain valid when the innovation variance changes over time, where [`ARMA::std_errors`]
+    /// understates the uncertainty.
+    pub fn robust_std_errors(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        let (jacobian, errors) = self.residual_jacobian(data)?;
+        let information_inv = (jacobian.transpose() * &jacobian)
+            .try_inverse()
+            .ok_or_else(|| NefeleError::DegenerateInput("the residual Jacobian is rank deficient".to_string()))?;
+        let k = jacobian.ncols();
+
+        // meat of the sandwich, with the factors 2 of H and 4 of G cancelling out
+        let mut meat = DMatrix::<f64>::zeros(k, k);
+        for t in 0..jacobian.nrows() {
+            let row = jacobian.row(t);
+            meat += errors[t] * errors[t] * row.transpose() * row;
+        }
+        let covariance = &information_inv * meat * &information_inv;
+        Ok((0..k).map(|j| covariance[(j, j)].sqrt()).collect())
+    }
+
+    /// Jacobian of the residuals on `data`, with the residuals.
+    fn residual_jacobian(&self, data: &[f64]) -> Result<(DMatrix<f64>, DVector<f64>), NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let jacobian = residual_jacobian(data, self.intercept, &self.phi, &self.theta);
+        let errors = DVector::from_vec(residuals(data, self.intercept, &self.phi, &self.theta));
+        Ok((jacobian, errors))
+    }
+
+    /// Computes the R^2 of the one-step predictions of the fitted model on the provided data,
+    /// the share of the variance explained relative to a mean-only model, after the warm-up.
+    /// It can be negative for a model fitted without intercept on data with a non-zero mean.
+    pub fn r_squared(&self, data: &[f64]) -> Result<f64, NefeleError> {
+        let residuals = self.residuals(data)?;
+        Ok(r_squared(data, &residuals, warm_up(self.phi.len(), self.theta.len()), self.phi.len() + self.theta.len()).0)
+    }
+
+    /// Computes the R^2 of `r_squared` adjusted for the number of estimated coefficients.
+    pub fn adjusted_r_squared(&self, data: &[f64]) -> Result<f64, NefeleError> {
+        let residuals = self.residuals(data)?;
+        Ok(r_squared(data, &residuals, warm_up(self.phi.len(), self.theta.len()), self.phi.len() + self.theta.len()).1)
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(forecast(data, self.intercept, &self.phi, &self.theta, horizon))
+    }
+
+    /// Forecasts `horizon` steps ahead of each of the `series` with this one fitted model,
+    /// applied to each series' own history, as for a model pooled over a panel. The series
+    /// are forecast in parallel when the `rayon` feature is enabled, and the forecasts are
+    /// returned in the order of the input.
+    pub fn forecast_panel(&self, series: &[Vec<f64>], horizon: usize) -> Result<Vec<Vec<f64>>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let forecast_one = |data: &Vec<f64>| forecast(data, self.intercept, &self.phi, &self.theta, horizon);
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            Ok(series.par_iter().map(forecast_one).collect())
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            Ok(series.iter().map(forecast_one).collect())
+        }
+    }
+
+    /// Predicts over the historical range of the provided data and `horizon` steps beyond it.
+    /// The in-sample values are the one-step-ahead fitted values `x_t - e_t`, starting after
+    /// the `max(p, q)` warm-up observations; the out-of-sample forecasts start at index `data.len()`.
+    pub fn predict(&self, data: &[f64], horizon: usize) -> Result<Prediction, NefeleError> {
+        let residuals = self.residuals(data)?;
+        let forecasts = self.forecast(data, horizon)?;
+        let start = warm_up(self.phi.len(), self.theta.len()).min(data.len());
+
+        let mut values: Vec<f64> = (start..data.len()).map(|t| data[t] - residuals[t]).collect();
+        values.extend(forecasts);
+
+        Ok(Prediction {
+            index: (start..data.len() + horizon).collect(),
+            in_sample: (start..data.len() + horizon).map(|t| t < data.len()).collect(),
+            values
+        })
+    }
+
+    /// Builds the state of the recursion at the end of the provided data, from which
+    /// `forecast_step` continues.
+    pub fn forecast_state(&self, data: &[f64]) -> Result<ForecastState, NefeleError> {
+        let residuals = self.residuals(data)?;
+        let (p, q) = (self.phi.len(), self.theta.len());
+        if data.len() < warm_up(p, q) {
+            return Err(NefeleError::InsufficientData(format!(
+                "{} observations, at least {} needed", data.len(), warm_up(p, q)
+            )));
+        }
+
+        Ok(ForecastState {
+            observations: data[data.len() - p..].to_vec(),
+            residuals: residuals[residuals.len() - q..].to_vec()
+        })
+    }
+
+    /// Advances the state by one time point and returns the one-step-ahead forecast of it.
+    /// With `Some(obs)` the observation and its forecast error enter the state; with `None`
+    /// the forecast itself does, with a zero error, so that chained `None` steps reproduce
+    /// `forecast` over the same horizon.
+    pub fn forecast_step(&self, state: &mut ForecastState, new_obs: Option<f64>) -> f64 {
+        let mut forecast = self.intercept;
+        for (phi, obs) in self.phi.iter().zip(state.observations.iter().rev()) {
+            forecast += phi * obs;
+        }
+        for (theta, res) in self.theta.iter().zip(state.residuals.iter().rev()) {
+            forecast += theta * res;
+        }
+
+        let value = new_obs.unwrap_or(forecast);
+        if !state.observations.is_empty() {
+            state.observations.remove(0);
+            state.observations.push(value);
+        }
+        if !state.residuals.is_empty() {
+            state.residuals.remove(0);
+            state.residuals.push(value - forecast);
+        }
+        forecast
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data around a known `mean` instead
+    /// of the estimated intercept: the data are centered on `mean`, forecast with a zero
+    /// intercept and shifted back. The two forecasts agree when
+    /// `mean = intercept / (1 - phi_1 - ... - phi_p)`.
+    pub fn forecast_with_mean(&self, data: &[f64], horizon: usize, mean: f64) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let centered: Vec<f64> = data.iter().map(|x| x - mean).collect();
+        let forecast = forecast(&centered, 0.0, &self.phi, &self.theta, horizon);
+        Ok(forecast.iter().map(|f| f + mean).collect())
+    }
+
+    /// Computes the autocorrelations implied by the model from lag 0 to `max_lag`.
+    pub fn theoretical_acf(&self, max_lag: usize) -> Vec<f64> {
+        arma_acf(&self.phi, &self.theta, max_lag)
+    }
+
+    /// Computes the partial autocorrelations implied by the model from lag 1 to `max_lag`,
+    /// to overlay on the sample PACF: for a pure AR(p) they vanish beyond lag `p`.
+    pub fn theoretical_pacf(&self, max_lag: usize) -> Vec<f64> {
+        arma_pacf(&self.phi, &self.theta, max_lag)
     }
 
     /// Simulates an ARMA process.
@@ -47,150 +317,318 @@ impl ARMA {
         error_mean: f64,
         error_variance: f64,
     ) -> Vec<f64> {
-        let mut output: Vec<f64> = Vec::with_capacity(length);
-
-        let ar_order = ar_param.len();
-        let ma_order = ma_param.len();
         let normal: Normal<f64> = Normal::new(error_mean, error_variance.sqrt()).unwrap();
+        let mut rng = rand::thread_rng();
 
         // Initialization
+        let init = ar_param.len() + ma_param.len();
+        let shocks: Vec<f64> = (0..(init + length)).map(|_| normal.sample(&mut rng)).collect();
+
+        self.simulate_with_shocks(&ar_param, &ma_param, &shocks)
+    }
+
+    /// Simulates an ARMA process driven by the supplied innovations instead of random ones,
+    /// so that the output is deterministic. The first `p + q` shocks only warm the recursion
+    /// up, so the output has `shocks.len() - p - q` values: zero shocks give a zero series and
+    /// a single unit shock right after the warm-up gives the impulse response (psi weights).
+    pub fn simulate_with_shocks(&self, ar_param: &[f64], ma_param: &[f64], shocks: &[f64]) -> Vec<f64> {
+        let ar_order = ar_param.len();
+        let ma_order = ma_param.len();
         let init = ar_order + ma_order;
-        for _ in 0..(init + length) {
-            let mut rng = rand::thread_rng();
-            let err = normal.sample(&mut rng);
-            output.push(err);
-        }
+        let mut output: Vec<f64> = shocks.to_vec();
 
         // MA(theta)
-        if ma_order > 0 {
-            let ma = ma_param;
-            let err = output.clone();
-
-            for i in (ma_order)..(init + length) {
-                for j in 0..ma_order {
-                    output[i] += ma[j] * err[i - j - 1];
-                }
+        for i in ma_order..output.len() {
+            for j in 0..ma_order {
+                output[i] += ma_param[j] * shocks[i - j - 1];
             }
         }
 
         // AR(phi)
-        if ar_order > 0 {
-            let ar = ar_param;
+        for i in init..output.len() {
+            for j in 0..ar_order {
+                output[i] += ar_param[j] * output[i - j - 1];
+            }
+        }
 
-            for i in (ma_order + ar_order)..(init + length) {
-                for j in 0..ar_order {
-                    output[i] += ar[j] * output[i - j - 1];
-                }
+        output.split_off(init.min(output.len()))
+    }
+
+    /// Simulates an ARMA process specified by its lag-operator polynomials,
+    /// `ar_poly(L) x_t = ma_poly(L) e_t` with `ar_poly = [1, -phi_1, ..., -phi_p]` and
+    /// `ma_poly = [1, theta_1, ..., theta_q]`, both including the leading 1. This is the same
+    /// process as `simulate` with `ar_param = [phi_1, ..., phi_p]` and `ma_param = [theta_1, ..., theta_q]`:
+    /// e.g. the AR(1) `x_t = 0.5 x_{t-1} + e_t` is `ar_poly = [1.0, -0.5]`.
+    pub fn simulate_from_polynomial(
+        &self,
+        length: usize,
+        ar_poly: &[f64],
+        ma_poly: &[f64],
+        error_mean: f64,
+        error_variance: f64,
+    ) -> Result<Vec<f64>, NefeleError> {
+        for poly in [ar_poly, ma_poly] {
+            if poly.first() != Some(&1.0) {
+                return Err(NefeleError::InvalidValue(format!(
+                    "lag polynomials must start with 1, found {:?}", poly
+                )));
             }
         }
+        let ar_param: Vec<f64> = ar_poly[1..].iter().map(|c| -c).collect();
+        let ma_param: Vec<f64> = ma_poly[1..].to_vec();
 
-        output
+        Ok(self.simulate(length, ar_param, ma_param, error_mean, error_variance))
+    }
+
+    /// Simulates an ARMA process starting from a draw of the stationary distribution of its
+    /// initial observations and innovations instead of a burn-in, from a generator seeded with
+    /// `seed`: every value, the first included, has the stationary distribution. Returns an
+    /// `InvalidValue` error for non-stationary AR coefficients.
+    pub fn simulate_stationary_init(
+        &self,
+        length: usize,
+        ar_param: Vec<f64>,
+        ma_param: Vec<f64>,
+        error_mean: f64,
+        error_variance: f64,
+        seed: u64,
+    ) -> Result<Vec<f64>, NefeleError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        simulate_stationary(length, &ar_param, &ma_param, error_mean, error_variance, &mut rng)
+    }
+
+    /// Simulates an ARMA process as `simulate` does and adds the deterministic linear trend
+    /// `trend_intercept + trend_slope * t`, for `t = 0, ..., length - 1`, on top of it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn simulate_with_trend(
+        &self,
+        length: usize,
+        ar_param: Vec<f64>,
+        ma_param: Vec<f64>,
+        error_mean: f64,
+        error_variance: f64,
+        trend_slope: f64,
+        trend_intercept: f64,
+    ) -> Vec<f64> {
+        self.simulate(length, ar_param, ma_param, error_mean, error_variance)
+            .into_iter()
+            .enumerate()
+            .map(|(t, x)| x + trend_intercept + trend_slope * t as f64)
+            .collect()
     }
 
     /// Fits the ARMA model to the provided data according to the selected method.
     pub fn fit(&mut self, data: &Vec<f64>, ar_order: usize, ma_order: usize, method: ARMAMethod) {
+        if self.prepare_fit(data).is_err() {
+            return;
+        }
         match method {
-            ARMAMethod::CSS => Self::fit_css(self, data, ar_order, ma_order),
+            ARMAMethod::CSS => Self::fit_css(self, data, ar_order, ma_order, None),
             ARMAMethod::ML => Self::fit_ml(self, data, ar_order, ma_order),
+            ARMAMethod::WHITTLE => Self::fit_whittle(self, data, ar_order, ma_order),
         }
 
-        
-        self.sigma_squared = compute_variance(&data, &self.phi);
-        self.aic = compute_aic(data.len(), self.sigma_squared, ar_order + ma_order);
-        self.bic = compute_bic(data.len(), self.sigma_squared, ar_order + ma_order);
+        // coefficients, intercept and innovation variance
+        self.set_fit_statistics(data, ar_order + ma_order + 1 + usize::from(self.include_intercept));
     }
 
-    /// Automatically fits the ARMA model by selecting the order based on a criterion.
-    pub fn autofit(&mut self, data: &Vec<f64>, max_ar_order: usize, max_ma_order: usize, criterion: ARMACriterion) {     
-        match criterion {
-            ARMACriterion::AIC => Self::autofit_aic(self, data, max_ar_order, max_ma_order),
-            ARMACriterion::BIC => Self::autofit_bic(self, data, max_ar_order, max_ma_order),
+    /// Fits the model by CSS starting the optimizer from `initial` instead of the default
+    /// guesses. `initial` holds the intercept followed by the AR and MA coefficients, so it must
+    /// have length `1 + ar_order + ma_order`. Trying several starting points helps escaping bad local minima.
+    /// The intercept is ignored, and held at zero, when `include_intercept` is false.
+    /// The data are checked as in `fit`, and a degenerate series returns its problem as an error.
+    pub fn fit_css_with_initial(&mut self, data: &Vec<f64>, ar_order: usize, ma_order: usize, initial: Vec<f64>) -> Result<(), NefeleError> {
+        if initial.len() != 1 + ar_order + ma_order {
+            return Err(NefeleError::InvalidLength(format!(
+                "initial guess has {} values, expected {}", initial.len(), 1 + ar_order + ma_order
+            )));
         }
+        self.prepare_fit(data)?;
+        Self::fit_css(self, data, ar_order, ma_order, Some(initial));
+        self.set_fit_statistics(data, ar_order + ma_order + 1 + usize::from(self.include_intercept));
+        Ok(())
     }
 
-    fn fit_ml(&mut self, data: &Vec<f64>, ar: usize, ma: usize) {
-        // Initial guess for parameters
-        let initial_guess: Vec<f64> = vec![0.0; ar + ma + 1];
-
-        // Objective function for MLE estimation
-        let f = |params: &[f64]| -> f64 {
-            let phi = &params[1..ar + 1];
-            let theta = &params[ar + 1..];
-            let mut log_likelihood = 0.0;
-
-            for t in (ar + ma)..data.len() {
-                let mut prediction = params[0];
-                for i in 0..ar {
-                    prediction += phi[i] * data[t - i - 1];
-                }
-                for j in 0..ma {
-                    prediction += theta[j] * data[t - j - 1];
+    /// Fits the model by CSS from `n_restarts` starting points and keeps the fit with the
+    /// lowest conditional sum of squares. The first start is the default initial guess, the
+    /// others perturb its AR and MA coefficients with normal noise of standard deviation 0.5
+    /// drawn from a generator seeded with `seed`. Returns the number of restarts tried and the
+    /// best objective.
+    pub fn fit_best_of(&mut self, data: &Vec<f64>, ar_order: usize, ma_order: usize, n_restarts: usize, seed: u64) -> (usize, f64) {
+        let normal: Normal<f64> = Normal::new(0.0, 0.5).unwrap();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let default = Self::initial_guess(data, ar_order, ma_order);
+
+        let mut best: Option<(f64, ARMA)> = None;
+        for restart in 0..n_restarts.max(1) {
+            let mut initial = default.clone();
+            if restart > 0 {
+                for c in initial.iter_mut().skip(1) {
+                    *c += normal.sample(&mut rng);
                 }
-                let residual = data[t] - prediction;
-                log_likelihood -= 0.5 * (residual * residual).ln();
             }
 
-            // println!("LL: {:?}, {:?}, {}",phi, theta, -log_likelihood);
-            -log_likelihood // negative log likelihood
+            let mut model = self.clone();
+            Self::fit_css(&mut model, data, ar_order, ma_order, Some(initial));
+            let objective = css_objective(data, model.intercept, &model.phi, &model.theta);
+
+            if best.as_ref().is_none_or(|(best_objective, _)| objective < *best_objective) {
+                best = Some((objective, model));
+            }
+        }
+
+        let (objective, model) = best.unwrap();
+        *self = model;
+        self.set_fit_statistics(data, ar_order + ma_order + 1 + usize::from(self.include_intercept));
+        (n_restarts.max(1), objective)
+    }
+
+    /// Checks the data before a fit. On a degenerate series the model is left unfitted with
+    /// NaN statistics and the problem returned as an error.
+    fn prepare_fit(&mut self, data: &[f64]) -> Result<(), NefeleError> {
+        let quality = input_quality(data);
+        quality.warn();
+        if quality.is_degenerate() {
+            self.fitted = false;
+            self.sigma_squared = f64::NAN;
+            self.aic = f64::NAN;
+            self.bic = f64::NAN;
+            return quality.check();
+        }
+        Ok(())
+    }
+
+    /// Stores the variance and the information criteria of a fit with `k` free parameters.
+    fn set_fit_statistics(&mut self, data: &Vec<f64>, k: usize) {
+        let (sigma_squared, rss, n) = innovation_variance(data, self.intercept, &self.phi, &self.theta, self.variance_estimator);
+        self.sigma_squared = sigma_squared;
+        self.aic = aic(n, rss, k);
+        self.bic = bic(n, rss, k);
+        self.fitted = true;
+    }
+
+    /// Automatically fits the ARMA model by selecting the order based on a criterion.
+    pub fn autofit(&mut self, data: &Vec<f64>, max_ar_order: usize, max_ma_order: usize, criterion: &dyn OrderCriterion) {
+        Self::autofit_grid(self, data, max_ar_order, max_ma_order, criterion)
+    }
+
+    fn fit_ml(&mut self, data: &Vec<f64>, ar: usize, ma: usize) {
+        let enforce = self.enforce_stationarity;
+        let total_size = 1 + ar + ma;
+
+        // The objective is the negative Gaussian log-likelihood, with the
+        // innovation variance profiled out
+        let f = |coef: &Vec<f64>| {
+            assert_eq!(coef.len(), total_size);
+
+            let intercept = coef[0];
+            let phi = &ar_from_optimizer(&coef[1..ar + 1], enforce);
+            let theta = &coef[ar + 1..];
+
+            -gaussian_loglik(data, intercept, phi, theta)
         };
+        let g = |coef: &Vec<f64>| coef.forward_diff(&f);
+
+        // Initial coefficients
+        let mut coef: Vec<f64> = Vec::new();
+
+        // Initial guess for the intercept: Mean of data
+        coef.push(mean(data));
 
-        // Compute gradient using finite differences
-        let mut gradient = vec![0.0; ar + ma + 1];
-        let epsilon = 1e-6;
-        for i in 0..(ar + ma + 1) {
-            let mut params_plus = initial_guess.clone();
-            params_plus[i] += epsilon;
-            let fx_plus = f(&params_plus);
+        // Initial guess for the AR coefficients: Values of the PACF
+        if ar > 0 {
+            coef.extend(initial_ar_from_pacf(data, ar));
+        }
 
-            let mut params_minus = initial_guess.clone();
-            params_minus[i] -= epsilon;
-            let fx_minus = f(&params_minus);
+        // Initial guess for the MA coefficients: 0.0
+        coef.resize(total_size, 0.0);
 
-            gradient[i] = (fx_plus - fx_minus) / (2.0 * epsilon);
+        // With enforced stationarity the AR part is searched in the unconstrained space
+        let start = ar_to_optimizer(&coef[1..=ar], enforce);
+        coef[1..=ar].copy_from_slice(&start);
+
+        // Without intercept it is held at zero and only the coefficients are searched
+        let skip = usize::from(!self.include_intercept);
+        if skip == 1 {
+            coef[0] = 0.0;
         }
 
-        let mut optimized_params = initial_guess.clone();
-        
         let evaluate = |x: &[f64], gx: &mut [f64]| {
-            let fx = f(x);
-            gx.copy_from_slice(&gradient);
+            let x: Vec<f64> = std::iter::repeat_n(0.0, skip).chain(x.iter().cloned()).collect();
+            let fx = f(&x);
+            let gx_eval = g(&x);
+            // copy values from gx_eval into gx
+            gx.copy_from_slice(&gx_eval[skip..]);
             Ok(fx)
         };
 
-        let fmin = lbfgs().with_max_iterations(200);
-        if let Err(e) = fmin.minimize(&mut optimized_params, evaluate, |_prng| { false }) {
+        if let Err(e) = self.optimizer.minimize(&mut coef[skip..], evaluate, |_prng| { false }) {
             tracing::warn!("{}", e);
         }
 
         // Extract estimated parameters
-        self.phi = optimized_params[1..=ar].to_vec();
-        self.theta = optimized_params[ar + 1..].to_vec();
+        self.intercept = coef[0];
+        self.phi = ar_from_optimizer(&coef[1..=ar], enforce);
+        self.theta = coef[ar + 1..].to_vec();
     }
     
-    fn fit_css(&mut self, data: &Vec<f64>, ar: usize, ma: usize) {
+    /// Fits the model by minimizing the Whittle likelihood, with the innovation variance
+    /// profiled out: `ln(mean_j I(w_j) / g(w_j)) + mean_j ln g(w_j)`, where `I` is the
+    /// periodogram of the data at the Fourier frequencies and `g` the ARMA spectral density
+    /// with unit `sigma^2 / (2 pi)`. The periodogram is computed once, before the optimization,
+    /// by a direct Fourier sum: its O(n^2) cost dominates the fit on long series, where CSS is
+    /// cheaper.
+    /// The intercept is set from the sample mean, `mean (1 - phi_1 - ... - phi_p)`.
+    fn fit_whittle(&mut self, data: &Vec<f64>, ar: usize, ma: usize) {
+        let enforce = self.enforce_stationarity;
+        let total_size = ar + ma;
+        let (frequencies, ordinates) = periodogram(data);
+        let m = frequencies.len() as f64;
 
-        let total_size = 1 + ar + ma;
-
-        // The objective is to minimize the conditional sum of squares (CSS),
-        // i.e. the sum of the squared residuals
         let f = |coef: &Vec<f64>| {
             assert_eq!(coef.len(), total_size);
 
-            let intercept = coef[0];
-            let phi = &coef[1..ar + 1];
-            let theta = &coef[ar + 1..];
-
-            let residuals = residuals(&data, intercept, &phi.to_vec(), &theta.to_vec());
+            let phi = &ar_from_optimizer(&coef[..ar], enforce);
+            let theta = &coef[ar..];
 
-            let mut css: f64 = 0.0;
-            for residual in &residuals {
-                css += residual * residual;
-            }
-            css
+            let density: Vec<f64> = frequencies.iter()
+                .map(|&omega| spectral_density(phi, theta, 2.0 * std::f64::consts::PI, omega))
+                .collect();
+            let scale = ordinates.iter().zip(&density).map(|(i, g)| i / g).sum::<f64>() / m;
+            scale.ln() + density.iter().map(|g| g.ln()).sum::<f64>() / m
         };
         let g = |coef: &Vec<f64>| coef.forward_diff(&f);
 
-        // Initial coefficients
+        // Initial coefficients: those of the CSS fit, without the intercept
+        let mut coef: Vec<f64> = Self::initial_guess(data, ar, ma).split_off(1);
+
+        // With enforced stationarity the AR part is searched in the unconstrained space
+        let start = ar_to_optimizer(&coef[..ar], enforce);
+        coef[..ar].copy_from_slice(&start);
+
+        let evaluate = |x: &[f64], gx: &mut [f64]| {
+            let x = x.to_vec();
+            let fx = f(&x);
+            let gx_eval = g(&x);
+            // copy values from gx_eval into gx
+            gx[..gx_eval.len()].copy_from_slice(&gx_eval[..]);
+            Ok(fx)
+        };
+
+        if let Err(e) = self.optimizer.minimize(&mut coef, evaluate, |_prng| { false }) {
+            tracing::warn!("{}", e);
+        }
+
+        // Extract estimated parameters
+        self.phi = ar_from_optimizer(&coef[..ar], enforce);
+        self.theta = coef[ar..].to_vec();
+        self.intercept = if self.include_intercept { mean(data) * (1.0 - self.phi.iter().sum::<f64>()) } else { 0.0 };
+    }
+
+    /// Default initial guesses of the CSS fit: the mean of the data for the intercept, the
+    /// PACF for the AR coefficients and, for the MA coefficients, the match of the ACF of the
+    /// AR-filtered data given by `initial_ma_from_acf`.
+    fn initial_guess(data: &Vec<f64>, ar: usize, ma: usize) -> Vec<f64> {
         let mut coef: Vec<f64> = Vec::new();
 
         // Initial guess for the intercept: First value of data
@@ -198,29 +636,63 @@ impl ARMA {
 
         // Initial guess for the AR coefficients: Values of the PACF
         if ar > 0 {
-            let pacf = pacf(&data, Some(ar));
-            for p in pacf {
-                coef.push(p);
-            }
+            coef.extend(initial_ar_from_pacf(data, ar));
         }
 
-        // Initial guess for the MA coefficients: 1.0
+        // Initial guess for the MA coefficients: matched to the ACF of the AR-filtered data
         if ma > 0 {
-            coef.resize(coef.len() + ma, 1.0);
+            let filtered = residuals(data, 0.0, &coef[1..], &[]);
+            coef.extend(initial_ma_from_acf(&filtered[ar.min(filtered.len())..], ma));
+        }
+
+        coef
+    }
+
+    fn fit_css(&mut self, data: &Vec<f64>, ar: usize, ma: usize, initial: Option<Vec<f64>>) {
+        let enforce = self.enforce_stationarity;
+
+        let total_size = 1 + ar + ma;
+
+        // The objective is to minimize the conditional sum of squares (CSS),
+        // i.e. the sum of the squared residuals
+        let f = |coef: &Vec<f64>| {
+            assert_eq!(coef.len(), total_size);
+
+            let intercept = coef[0];
+            let phi = &ar_from_optimizer(&coef[1..ar + 1], enforce);
+            let theta = &coef[ar + 1..];
+
+            css_objective(data, intercept, phi, theta)
+        };
+        let g = |coef: &Vec<f64>| coef.forward_diff(&f);
+
+        // Initial coefficients, unless supplied by the caller
+        let mut coef: Vec<f64> = match initial {
+            Some(initial) => initial,
+            None => Self::initial_guess(data, ar, ma)
+        };
+
+        // With enforced stationarity the AR part is searched in the unconstrained space
+        let start = ar_to_optimizer(&coef[1..=ar], enforce);
+        coef[1..=ar].copy_from_slice(&start);
+
+        // Without intercept it is held at zero and only the coefficients are searched
+        let skip = usize::from(!self.include_intercept);
+        if skip == 1 {
+            coef[0] = 0.0;
         }
 
         let evaluate = |x: &[f64], gx: &mut [f64]| {
-            let x = x.to_vec();
+            let x: Vec<f64> = std::iter::repeat_n(0.0, skip).chain(x.iter().cloned()).collect();
             let fx = f(&x);
             let gx_eval = g(&x);
             // copy values from gx_eval into gx
-            gx[..gx_eval.len()].copy_from_slice(&gx_eval[..]);
+            gx.copy_from_slice(&gx_eval[skip..]);
             Ok(fx)
         };
 
-        let fmin = lbfgs().with_max_iterations(200);
-        if let Err(e) = fmin.minimize(
-            &mut coef, // input variables
+        if let Err(e) = self.optimizer.minimize(
+            &mut coef[skip..], // input variables
             evaluate,  // define how to evaluate function
             |_prng| {
                 false 
@@ -229,44 +701,393 @@ impl ARMA {
             tracing::warn!("{}", e);
         }
         
-        self.phi = coef[1..=ar].to_vec();
+        self.intercept = coef[0];
+        self.phi = ar_from_optimizer(&coef[1..=ar], enforce);
         self.theta = coef[ar+1..].to_vec();
     }
 
-    fn autofit_aic(&mut self, data: &Vec<f64>, max_ar_order: usize, max_ma_order: usize) {
-        let mut aic: Vec<f64> = Vec::with_capacity((max_ar_order + 1) * (max_ma_order + 1));
-    
+    /// Fits every order of the grid by CSS, keeping a copy of the model with the lowest
+    /// criterion as the grid is scanned, so that the winner needs no final refit.
+    fn autofit_grid(&mut self, data: &Vec<f64>, max_ar_order: usize, max_ma_order: usize, criterion: &dyn OrderCriterion) {
+        let mut best: Option<(f64, ARMA)> = None;
+
         for ar_order in 0..=max_ar_order {
             for ma_order in 0..=max_ma_order {
-                Self::fit(self, data, ar_order, ma_order, ARMAMethod::CSS);
-                aic.push(self.aic);
+                let mut model = ARMA::new();
+                model.enforce_stationarity = self.enforce_stationarity;
+                model.optimizer = self.optimizer;
+                model.variance_estimator = self.variance_estimator;
+                model.include_intercept = self.include_intercept;
+                model.fit(data, ar_order, ma_order, ARMAMethod::CSS);
+                let (_, rss, n) = innovation_variance(data, model.intercept, &model.phi, &model.theta, model.variance_estimator);
+                let value = criterion.evaluate(n, rss, model.n_params());
+
+                if best.as_ref().is_none_or(|(best_value, _)| value < *best_value) {
+                    best = Some((value, model));
+                }
             }
         }
-    
-        let min_order = aic
-            .iter()
-            .enumerate()
-            .min_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(index, _)| index)
-            .unwrap_or(0);
-    
-        let ar_order = min_order / (max_ma_order + 1); // Integer division for ar_order
-        let ma_order = min_order % (max_ma_order + 1); // Using modulo for ma_order
-    
-        Self::fit(self, data, ar_order, ma_order, ARMAMethod::CSS);
-    }  
 
-    fn autofit_bic(&mut self, data: &Vec<f64>, max_ar_order: usize, max_ma_order: usize){
-        let mut bic:Vec<f64> = Vec::with_capacity(max_ar_order * max_ma_order);
-            for ar_order in 1..(max_ar_order+1){
-                for ma_order in 1..(max_ma_order+1){
-                Self::fit(self, data, ar_order,ma_order, ARMAMethod::CSS);
-                bic.push(self.bic);}
+        if let Some((_, model)) = best {
+            *self = model;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ARMA(1, 1) path with `phi = 0.6`, `theta = 0.3` and unit innovation variance.
+    fn arma11(length: usize, seed: u64) -> Vec<f64> {
+        ARMA::new().simulate_stationary_init(length, vec![0.6], vec![0.3], 0.0, 1.0, seed).unwrap()
+    }
+
+    #[test]
+    fn ml_recovers_arma11() {
+        let data = arma11(2000, 1);
+        let mut model = ARMA::new();
+        model.variance_estimator = VarianceEstimator::ML;
+        model.fit(&data, 1, 1, ARMAMethod::ML);
+
+        assert!((model.phi[0] - 0.6).abs() < 0.1, "phi {:?}", model.phi);
+        assert!((model.theta[0] - 0.3).abs() < 0.1, "theta {:?}", model.theta);
+        assert!((model.sigma_squared - 1.0).abs() < 0.1, "sigma^2 {}", model.sigma_squared);
+    }
+
+    #[test]
+    fn unfitted_model_reports_not_fitted() {
+        let model = ARMA::new();
+        let data = vec![1.0, 2.0, 1.5, 0.5, 1.0];
+
+        assert_eq!(model.forecast(&data, 3), Err(NefeleError::NotFitted));
+        assert_eq!(model.residuals(&data), Err(NefeleError::NotFitted));
+        assert!(model.to_summary().to_string().contains("not yet fitted"));
+    }
+
+    #[test]
+    fn criteria_count_intercept_and_variance() {
+        let data = arma11(1000, 2);
+        let mut model = ARMA::new();
+        model.fit(&data, 1, 1, ARMAMethod::CSS);
+        assert_eq!(model.n_params(), 4);
+
+        let (_, rss, n) = innovation_variance(&data, model.intercept, &model.phi, &model.theta, model.variance_estimator);
+        assert!((model.aic - aic(n, rss, 4)).abs() < 1e-9);
+        assert!((model.bic - bic(n, rss, 4)).abs() < 1e-9);
+
+        // with the full penalty BIC selects the true orders of an AR(1)
+        let ar1 = ARMA::new().simulate_stationary_init(1000, vec![0.7], vec![], 0.0, 1.0, 3).unwrap();
+        let mut selected = ARMA::new();
+        selected.autofit(&ar1, 2, 2, &ARMACriterion::BIC);
+        assert_eq!((selected.phi.len(), selected.theta.len()), (1, 0));
+    }
+
+    #[test]
+    fn forecast_with_mean_shifts_by_the_mean() {
+        let data = arma11(300, 6);
+        let mut model = ARMA::new();
+        model.fit(&data, 1, 1, ARMAMethod::CSS);
+        let shifted: Vec<f64> = data.iter().map(|x| x - 3.0).collect();
 
-            let ar_order =1;
-            let ma_order =1;
+        let base = model.forecast_with_mean(&data, 5, 0.0).unwrap();
+        let moved = model.forecast_with_mean(&shifted, 5, -3.0).unwrap();
+        for (b, m) in base.iter().zip(&moved) {
+            assert!((m - b + 3.0).abs() < 1e-12, "{} vs {}", m, b);
+        }
+    }
+
+    #[test]
+    fn prediction_aligns_fitted_values_and_forecasts() {
+        let data = arma11(200, 4);
+        let mut model = ARMA::new();
+        model.fit(&data, 1, 1, ARMAMethod::CSS);
+
+        let prediction = model.predict(&data, 3).unwrap();
+        let forecasts = prediction.forecasts();
+        assert_eq!(forecasts.iter().map(|&(i, _)| i).collect::<Vec<_>>(), [200, 201, 202]);
+        assert_eq!(forecasts.iter().map(|&(_, v)| v).collect::<Vec<_>>(), model.forecast(&data, 3).unwrap());
+
+        let fitted = prediction.fitted();
+        let residuals = model.residuals(&data).unwrap();
+        assert_eq!((fitted[0].0, fitted.len()), (1, 199));
+        for &(t, value) in &fitted {
+            assert!((value + residuals[t] - data[t]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn lag_polynomial_follows_the_sign_convention() {
+        use crate::utils::acf;
+
+        // (1 - 0.5 L) x_t = e_t is the recursion x_t = 0.5 x_{t-1} + e_t, with rho_1 = 0.5
+        let from_polynomial = ARMA::new().simulate_from_polynomial(5000, &[1.0, -0.5], &[1.0], 0.0, 1.0).unwrap();
+        let from_recursion = ARMA::new().simulate(5000, vec![0.5], vec![], 0.0, 1.0);
+        for path in [from_polynomial, from_recursion] {
+            let rho = acf(&path, Some(1), false)[1];
+            assert!((rho - 0.5).abs() < 0.05, "{}", rho);
+        }
+
+        assert!(matches!(
+            ARMA::new().simulate_from_polynomial(10, &[0.5], &[1.0], 0.0, 1.0),
+            Err(NefeleError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn autofit_returns_the_grid_minimum() {
+        let data = arma11(400, 6);
+        let mut model = ARMA::new();
+        model.autofit(&data, 2, 2, &ARMACriterion::AIC);
+
+        let mut grid_minimum = f64::INFINITY;
+        for ar_order in 0..=2 {
+            for ma_order in 0..=2 {
+                let mut candidate = ARMA::new();
+                candidate.fit(&data, ar_order, ma_order, ARMAMethod::CSS);
+                grid_minimum = grid_minimum.min(candidate.aic);
+            }
+        }
+        assert_eq!(model.aic, grid_minimum);
+    }
+
+    #[test]
+    fn restarts_escape_a_bad_local_minimum() {
+        // near-cancelling AR and MA roots make the CSS surface multimodal
+        let data = ARMA::new().simulate_stationary_init(60, vec![0.9], vec![-0.85], 0.0, 1.0, 1).unwrap();
+        let mut cold = ARMA::new();
+        cold.fit(&data, 1, 1, ARMAMethod::CSS);
+        let cold_objective = css_objective(&data, cold.intercept, &cold.phi, &cold.theta);
+
+        let mut model = ARMA::new();
+        let (restarts, objective) = model.fit_best_of(&data, 1, 1, 10, 1);
+        assert_eq!(restarts, 10);
+        assert_eq!(objective, css_objective(&data, model.intercept, &model.phi, &model.theta));
+        assert!(objective < cold_objective - 1.0, "{} vs {}", objective, cold_objective);
+    }
+
+    #[test]
+    fn theoretical_pacf_of_an_ar2_cuts_off() {
+        let model = ARMA::try_from_coefficients(vec![0.5, -0.3], vec![], 1.0).unwrap();
+        let partial = model.theoretical_pacf(6);
+
+        assert_eq!(partial.len(), 6);
+        // the last partial autocorrelation of an AR(p) at lag p is phi_p
+        assert!((partial[1] + 0.3).abs() < 1e-10, "{:?}", partial);
+        assert!(partial[2..].iter().all(|r| r.abs() < 1e-10), "{:?}", partial);
+
+        // an MA(1) has no cutoff
+        let ma = ARMA::try_from_coefficients(vec![], vec![0.6], 1.0).unwrap();
+        assert!(ma.theoretical_pacf(6).iter().all(|r| r.abs() > 1e-3));
+    }
+
+    #[test]
+    fn chained_steps_match_the_batch_forecast() {
+        let data = arma11(300, 7);
+        let mut model = ARMA::new();
+        model.fit(&data, 1, 1, ARMAMethod::CSS);
+
+        // feeding the last observations one by one reaches the state of the whole history
+        let mut state = model.forecast_state(&data[..250]).unwrap();
+        for &obs in &data[250..] {
+            model.forecast_step(&mut state, Some(obs));
+        }
+        assert_eq!(state, model.forecast_state(&data).unwrap());
+
+        let stepped: Vec<f64> = (0..5).map(|_| model.forecast_step(&mut state, None)).collect();
+        let batch = model.forecast(&data, 5).unwrap();
+        for (s, b) in stepped.iter().zip(&batch) {
+            assert!((s - b).abs() < 1e-10, "{:?} vs {:?}", stepped, batch);
+        }
+    }
+
+    #[test]
+    fn unit_shock_gives_the_impulse_response() {
+        let model = ARMA::new();
+        assert_eq!(model.simulate_with_shocks(&[0.5], &[0.4], &[0.0; 6]), [0.0; 4]);
+
+        // psi_0 = 1, psi_1 = phi + theta and psi_j = phi psi_{j-1} afterwards
+        let response = model.simulate_with_shocks(&[0.5], &[0.4], &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        let expected = [1.0, 0.9, 0.45, 0.225];
+        assert_eq!(response.len(), expected.len());
+        for (r, e) in response.iter().zip(&expected) {
+            assert!((r - e).abs() < 1e-12, "{:?}", response);
+        }
+        assert!(model.simulate_with_shocks(&[0.5], &[0.4], &[1.0]).is_empty());
+    }
+
+    #[test]
+    fn tuned_optimizer_converges_on_a_hard_arma22() {
+        // near-cancelling roots: the default line search wanders off on this path
+        let data = ARMA::new().simulate_stationary_init(200, vec![1.2, -0.5], vec![-0.9, 0.3], 0.0, 1.0, 11).unwrap();
+        let objective = |optimizer: OptimizerConfig| {
+            let mut model = ARMA::new();
+            model.optimizer = optimizer;
+            model.fit(&data, 2, 2, ARMAMethod::CSS);
+            css_objective(&data, model.intercept, &model.phi, &model.theta)
+        };
+
+        let tuned = OptimizerConfig { max_iterations: 2000, epsilon: 1e-9, linesearch_gtol: 0.1, max_linesearch: 100, ..OptimizerConfig::default() };
+        let default = objective(OptimizerConfig::default());
+        let tuned = objective(tuned);
+        assert!(tuned < 200.0 && tuned < default, "{} vs {}", tuned, default);
+    }
+
+    #[test]
+    fn ml_variance_is_consistent_with_the_likelihood() {
+        let data = arma11(2000, 2);
+        let mut plug_in = ARMA::new();
+        plug_in.fit(&data, 1, 1, ARMAMethod::CSS);
+        let mut ml = ARMA::new();
+        ml.variance_estimator = VarianceEstimator::ML;
+        ml.fit(&data, 1, 1, ARMAMethod::CSS);
+
+        // same coefficients and criteria: the estimator changes only the reported variance
+        assert_eq!(plug_in.phi, ml.phi);
+        assert_eq!((plug_in.aic, plug_in.bic), (ml.aic, ml.bic));
+        assert!((ml.sigma_squared - 1.0).abs() < 0.1, "{}", ml.sigma_squared);
+
+        // with the ML variance, AIC = -2 ln L + 2k up to the constant n (1 + ln 2 pi)
+        let n = 1999.0;
+        let log_likelihood = gaussian_loglik(&data, ml.intercept, &ml.phi, &ml.theta);
+        let constant = n * (1.0 + (2.0 * std::f64::consts::PI).ln());
+        assert!((ml.aic - (-2.0 * log_likelihood + 2.0 * 4.0 - constant)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn whittle_estimates_agree_with_css() {
+        let data = ARMA::new().simulate_stationary_init(2000, vec![0.6], vec![0.3], 0.0, 1.0, 29).unwrap();
+        let mut whittle = ARMA::new();
+        whittle.fit(&data, 1, 1, ARMAMethod::WHITTLE);
+        let mut css = ARMA::new();
+        css.fit(&data, 1, 1, ARMAMethod::CSS);
+
+        assert!((whittle.phi[0] - css.phi[0]).abs() < 0.05, "{:?} vs {:?}", whittle.phi, css.phi);
+        assert!((whittle.theta[0] - css.theta[0]).abs() < 0.05, "{:?} vs {:?}", whittle.theta, css.theta);
+        assert!((whittle.phi[0] - 0.6).abs() < 0.1 && (whittle.theta[0] - 0.3).abs() < 0.1);
+
+        // the spectrum of white noise is flat at sigma^2 / (2 pi)
+        let flat = 2.0 / (2.0 * std::f64::consts::PI);
+        assert!((spectral_density(&[], &[], 2.0, 0.7) - flat).abs() < 1e-12);
+    }
+
+    #[test]
+    fn coefficients_beyond_the_order_are_zero() {
+        let model = ARMA::try_from_coefficients(vec![0.5, -0.2], vec![0.4], 1.0).unwrap();
+        assert_eq!([model.phi_at(1), model.phi_at(2)], [0.5, -0.2]);
+        assert_eq!(model.theta_at(1), 0.4);
+        assert_eq!([model.phi_at(0), model.phi_at(3), model.theta_at(0), model.theta_at(2)], [0.0; 4]);
+
+        let ar = crate::ar::AutoRegressive::try_from_coefficients(vec![0.5, -0.2], 1.0).unwrap();
+        assert_eq!([ar.phi_at(0), ar.phi_at(2), ar.phi_at(7)], [0.0, -0.2, 0.0]);
+    }
+
+    #[test]
+    fn stationary_start_has_the_stationary_variance() {
+        // ARMA(1,1) close to the unit root: gamma_0 = (1 + 2 phi theta + theta^2) / (1 - phi^2)
+        let (phi, theta) = (0.95, 0.4);
+        let gamma0 = crate::utils::arma_autocovariance(&[phi], &[theta], 1.0, 0)[0];
+        assert!((gamma0 - (1.0 + 2.0 * phi * theta + theta * theta) / (1.0 - phi * phi)).abs() < 1e-10);
+
+        // the first value of many independent paths
+        let first: Vec<f64> = (0..4000)
+            .map(|seed| ARMA::new().simulate_stationary_init(2, vec![phi], vec![theta], 0.0, 1.0, seed).unwrap()[0])
+            .collect();
+        let variance = first.iter().map(|x| x * x).sum::<f64>() / first.len() as f64;
+        assert!((variance / gamma0 - 1.0).abs() < 0.1, "{} vs {}", variance, gamma0);
+    }
+
+    #[test]
+    fn panel_forecasts_follow_each_history() {
+        let first = ARMA::new().simulate_stationary_init(200, vec![0.6], vec![0.3], 0.0, 1.0, 30).unwrap();
+        let second = ARMA::new().simulate_stationary_init(150, vec![0.6], vec![0.3], 0.0, 1.0, 31).unwrap();
+        let mut model = ARMA::new();
+        assert_eq!(model.forecast_panel(std::slice::from_ref(&first), 3), Err(NefeleError::NotFitted));
+        model.fit(&first, 1, 1, ARMAMethod::CSS);
+
+        let panel = model.forecast_panel(&[first.clone(), second.clone(), first.clone()], 4).unwrap();
+        assert_eq!(panel.len(), 3);
+        assert_eq!(panel[0], panel[2]);
+        assert_eq!(panel[0], model.forecast(&first, 4).unwrap());
+        assert_eq!(panel[1], model.forecast(&second, 4).unwrap());
+    }
+
+    #[test]
+    fn robust_errors_widen_under_heteroskedasticity() {
+        let noise = ARMA::new().simulate_stationary_init(2000, vec![], vec![], 0.0, 1.0, 38).unwrap();
+        let ratio = |data: &Vec<f64>| {
+            let mut model = ARMA::new();
+            model.fit(data, 1, 0, ARMAMethod::CSS);
+            let naive = model.std_errors(data).unwrap();
+            let robust = model.robust_std_errors(data).unwrap();
+            robust[1] / naive[1]
+        };
+
+        // AR(1) whose innovation variance grows with the lagged value
+        let mut heteroskedastic: Vec<f64> = vec![0.0; noise.len()];
+        for t in 1..noise.len() {
+            let previous = heteroskedastic[t - 1];
+            heteroskedastic[t] = 0.5 * previous + noise[t] * (0.2 + previous * previous).sqrt();
+        }
+        assert!(ratio(&heteroskedastic) > 1.3, "{}", ratio(&heteroskedastic));
+
+        let mut homoskedastic = vec![0.0; noise.len()];
+        for t in 1..noise.len() {
+            homoskedastic[t] = 0.5 * homoskedastic[t - 1] + noise[t];
+        }
+        assert!((ratio(&homoskedastic) - 1.0).abs() < 0.15, "{}", ratio(&homoskedastic));
+    }
+
+    #[test]
+    fn degenerate_input_leaves_the_model_unfitted() {
+        let mut model = ARMA::new();
+        model.fit(&vec![3.0; 50], 1, 1, ARMAMethod::CSS);
+        assert_eq!(model.forecast(&[3.0; 50], 2), Err(NefeleError::NotFitted));
+        assert!(model.aic.is_nan());
+    }
+
+    #[test]
+    fn coefficients_are_validated_on_construction() {
+        let model = ARMA::try_from_coefficients(vec![0.5], vec![0.4], 1.0).unwrap();
+        assert!(model.forecast(&[1.0, 2.0, 3.0], 2).is_ok());
+        // the zero intercept is given, not estimated
+        assert_eq!(model.n_params(), 3);
+
+        // explosive AR part, non-invertible MA part, non-positive variance
+        for (phi, theta, sigma2) in [(vec![1.5], vec![], 1.0), (vec![0.5], vec![1.2], 1.0), (vec![0.5], vec![0.4], -1.0)] {
+            assert!(matches!(ARMA::try_from_coefficients(phi, theta, sigma2), Err(NefeleError::InvalidValue(_))));
+        }
+    }
+
+    #[test]
+    fn intercept_can_be_held_at_zero() {
+        let data = ARMA::new().simulate_stationary_init(1000, vec![0.6], vec![0.3], 0.0, 1.0, 41).unwrap();
+        let fit = |include_intercept: bool, method: ARMAMethod| {
+            let mut model = ARMA::new();
+            model.include_intercept = include_intercept;
+            model.fit(&data, 1, 1, method);
+            model
+        };
+
+        for (with, without) in [
+            (fit(true, ARMAMethod::CSS), fit(false, ARMAMethod::CSS)),
+            (fit(true, ARMAMethod::ML), fit(false, ARMAMethod::ML)),
+        ] {
+            assert_eq!(without.intercept, 0.0);
+            assert!((with.phi[0] - without.phi[0]).abs() < 0.02, "{:?} vs {:?}", with.phi, without.phi);
+            assert!((with.theta[0] - without.theta[0]).abs() < 0.02, "{:?} vs {:?}", with.theta, without.theta);
+            assert_eq!(with.n_params(), without.n_params() + 1);
+        }
+    }
 
-            Self::fit(self, data, ar_order, ma_order, ARMAMethod::CSS);
+    #[test]
+    fn autofit_selects_ma_terms_under_either_estimator() {
+        let data = ARMA::new().simulate_stationary_init(1000, vec![], vec![0.8], 0.0, 1.0, 42).unwrap();
+        for estimator in [VarianceEstimator::PlugIn, VarianceEstimator::ML] {
+            let mut model = ARMA::new();
+            model.variance_estimator = estimator;
+            model.autofit(&data, 2, 2, &ARMACriterion::AIC);
+            assert!(!model.theta.is_empty(), "{:?}: ARMA({}, 0)", estimator, model.phi.len());
         }
     }
 }
@@ -0,0 +1,86 @@
+use rand::{rngs::StdRng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+
+/// Monthly totals of international airline passengers, in thousands, from January 1949
+/// to December 1960 (Box and Jenkins): 144 observations with trend and multiplicative seasonality.
+pub fn airline_passengers() -> Vec<f64> {
+    AIRLINE_PASSENGERS.to_vec()
+}
+
+/// Annual numbers of lynx trappings in the Mackenzie River district of Canada from 1821
+/// to 1934: 114 observations with a cycle of about ten years.
+pub fn lynx() -> Vec<f64> {
+    LYNX.to_vec()
+}
+
+/// Generates `n` independent standard normal observations from a generator seeded with `seed`.
+pub fn white_noise(n: usize, seed: u64) -> Vec<f64> {
+    let normal: Normal<f64> = Normal::new(0.0, 1.0).unwrap();
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n).map(|_| normal.sample(&mut rng)).collect()
+}
+
+/// Generates `n` observations of a Gaussian random walk, the cumulative sum of
+/// `white_noise(n, seed)`.
+pub fn random_walk(n: usize, seed: u64) -> Vec<f64> {
+    let mut level = 0.0;
+    white_noise(n, seed)
+        .into_iter()
+        .map(|e| {
+            level += e;
+            level
+        })
+        .collect()
+}
+
+const AIRLINE_PASSENGERS: [f64; 144] = [
+    112.0, 118.0, 132.0, 129.0, 121.0, 135.0, 148.0, 148.0, 136.0, 119.0, 104.0, 118.0,
+    115.0, 126.0, 141.0, 135.0, 125.0, 149.0, 170.0, 170.0, 158.0, 133.0, 114.0, 140.0,
+    145.0, 150.0, 178.0, 163.0, 172.0, 178.0, 199.0, 199.0, 184.0, 162.0, 146.0, 166.0,
+    171.0, 180.0, 193.0, 181.0, 183.0, 218.0, 230.0, 242.0, 209.0, 191.0, 172.0, 194.0,
+    196.0, 196.0, 236.0, 235.0, 229.0, 243.0, 264.0, 272.0, 237.0, 211.0, 180.0, 201.0,
+    204.0, 188.0, 235.0, 227.0, 234.0, 264.0, 302.0, 293.0, 259.0, 229.0, 203.0, 229.0,
+    242.0, 233.0, 267.0, 269.0, 270.0, 315.0, 364.0, 347.0, 312.0, 274.0, 237.0, 278.0,
+    284.0, 277.0, 317.0, 313.0, 318.0, 374.0, 413.0, 405.0, 355.0, 306.0, 271.0, 306.0,
+    315.0, 301.0, 356.0, 348.0, 355.0, 422.0, 465.0, 467.0, 404.0, 347.0, 305.0, 336.0,
+    340.0, 318.0, 362.0, 348.0, 363.0, 435.0, 491.0, 505.0, 404.0, 359.0, 310.0, 337.0,
+    360.0, 342.0, 406.0, 396.0, 420.0, 472.0, 548.0, 559.0, 463.0, 407.0, 362.0, 405.0,
+    417.0, 391.0, 419.0, 461.0, 472.0, 535.0, 622.0, 606.0, 508.0, 461.0, 390.0, 432.0,
+];
+
+const LYNX: [f64; 114] = [
+    269.0, 321.0, 585.0, 871.0, 1475.0, 2821.0, 3928.0, 5943.0, 4950.0, 2577.0, 523.0, 98.0,
+    184.0, 279.0, 409.0, 2285.0, 2685.0, 3409.0, 1824.0, 409.0, 151.0, 45.0, 68.0, 213.0,
+    546.0, 1033.0, 2129.0, 2536.0, 957.0, 361.0, 377.0, 225.0, 360.0, 731.0, 1638.0, 2725.0,
+    2871.0, 2119.0, 684.0, 299.0, 236.0, 245.0, 552.0, 1623.0, 3311.0, 6721.0, 4254.0, 687.0,
+    255.0, 473.0, 358.0, 784.0, 1594.0, 1676.0, 2251.0, 1426.0, 756.0, 299.0, 201.0, 229.0,
+    469.0, 736.0, 2042.0, 2811.0, 4431.0, 2511.0, 389.0, 73.0, 39.0, 49.0, 59.0, 188.0,
+    377.0, 1292.0, 4031.0, 3495.0, 587.0, 105.0, 153.0, 387.0, 758.0, 1307.0, 3465.0, 6991.0,
+    6313.0, 3794.0, 1836.0, 345.0, 382.0, 808.0, 1388.0, 2713.0, 3800.0, 3091.0, 2985.0, 3790.0,
+    674.0, 81.0, 80.0, 108.0, 229.0, 399.0, 1132.0, 2432.0, 3574.0, 2935.0, 1537.0, 529.0,
+    485.0, 662.0, 1000.0, 1590.0, 2657.0, 3396.0,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datasets_have_the_documented_lengths() {
+        let airline = airline_passengers();
+        assert_eq!(airline.len(), 144);
+        assert_eq!((airline[0], airline[143]), (112.0, 432.0));
+
+        let lynx = lynx();
+        assert_eq!(lynx.len(), 114);
+        assert_eq!((lynx[0], lynx[113]), (269.0, 3396.0));
+
+        assert_eq!(white_noise(50, 1).len(), 50);
+        assert_eq!(white_noise(50, 1), white_noise(50, 1));
+        let steps = white_noise(50, 1);
+        let walk = random_walk(50, 1);
+        for t in 1..50 {
+            assert!((walk[t] - walk[t - 1] - steps[t]).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+/// Decomposition struct holds the components of a classical seasonal decomposition.
+#[derive(Debug, Clone)]
+pub struct Decomposition {
+    pub trend: Vec<f64>,        // Centered moving average trend
+    pub seasonal: Vec<f64>,     // Seasonal component, repeating with the period
+    pub remainder: Vec<f64>,    // What is left once trend and seasonal are removed
+    pub multiplicative: bool    // Whether the components multiply rather than add up
+}
+
+impl Decomposition {
+    /// Recombines the components into the original series: `trend + seasonal + remainder`,
+    /// or `trend * seasonal * remainder` for a multiplicative decomposition.
+    pub fn reconstruct(&self) -> Vec<f64> {
+        self.trend.iter()
+            .zip(&self.seasonal)
+            .zip(&self.remainder)
+            .map(|((t, s), r)| if self.multiplicative { t * s * r } else { t + s + r })
+            .collect()
+    }
+}
+
+/// Additive classical decomposition of the data into trend, seasonal and remainder
+/// components, a simple moving average stand-in for STL. See [`decompose`].
+pub fn stl_like(data: &[f64], period: usize) -> Decomposition {
+    decompose(data, period, false)
+}
+
+/// Classical decomposition of the data with seasonal `period`. The trend is the centered
+/// moving average over one period (a 2 x `period` average for even periods), carried flat
+/// over the `period / 2` observations at each end where the average is not defined. The
+/// seasonal component is the mean detrended value of each season, normalized to sum to zero
+/// over a period (to average one when `multiplicative`), and the remainder is what is left,
+/// so the components always reconstruct the data. Multiplicative decompositions need
+/// positive data.
+///
+/// # Panics
+///
+/// Panics if `period < 2` or the data cover less than two full periods.
+pub fn decompose(data: &[f64], period: usize, multiplicative: bool) -> Decomposition {
+    assert!(period >= 2, "the seasonal period must be at least 2");
+    assert!(data.len() >= 2 * period, "the data must cover at least two full periods");
+    let n = data.len();
+    let half = period / 2;
+
+    // Centered moving average: for even periods the two end points get half weight
+    let weights: Vec<f64> = if period.is_multiple_of(2) {
+        (0..=period)
+            .map(|j| if j == 0 || j == period { 0.5 } else { 1.0 } / period as f64)
+            .collect()
+    } else {
+        vec![1.0 / period as f64; period]
+    };
+    let mut trend = vec![0.0; n];
+    for t in half..n - half {
+        trend[t] = weights.iter().enumerate().map(|(j, w)| w * data[t + j - half]).sum();
+    }
+    for t in 0..half {
+        trend[t] = trend[half];
+        trend[n - 1 - t] = trend[n - 1 - half];
+    }
+
+    // Average detrended value of each season, over the observations with a centered trend
+    let mut season_sums = vec![0.0; period];
+    let mut season_counts = vec![0usize; period];
+    for t in half..n - half {
+        let detrended = if multiplicative { data[t] / trend[t] } else { data[t] - trend[t] };
+        season_sums[t % period] += detrended;
+        season_counts[t % period] += 1;
+    }
+    let mut factors: Vec<f64> = season_sums.iter()
+        .zip(&season_counts)
+        .map(|(sum, &count)| sum / count as f64)
+        .collect();
+    let average = factors.iter().sum::<f64>() / period as f64;
+    for factor in factors.iter_mut() {
+        if multiplicative { *factor /= average } else { *factor -= average }
+    }
+
+    let seasonal: Vec<f64> = (0..n).map(|t| factors[t % period]).collect();
+    let remainder: Vec<f64> = (0..n)
+        .map(|t| {
+            if multiplicative {
+                data[t] / (trend[t] * seasonal[t])
+            } else {
+                data[t] - trend[t] - seasonal[t]
+            }
+        })
+        .collect();
+
+    Decomposition { trend, seasonal, remainder, multiplicative }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components_reconstruct_the_series() {
+        let airline = crate::datasets::airline_passengers();
+
+        for multiplicative in [false, true] {
+            let parts = decompose(&airline, 12, multiplicative);
+            assert_eq!(parts.multiplicative, multiplicative);
+            for (x, y) in parts.reconstruct().iter().zip(&airline) {
+                assert!((x - y).abs() < 1e-9 * y, "{} vs {}", x, y);
+            }
+
+            // the seasonal component repeats and is normalized over a period
+            let season: f64 = parts.seasonal[..12].iter().sum();
+            let neutral = if multiplicative { 12.0 } else { 0.0 };
+            assert!((season - neutral).abs() < 1e-9, "{}", season);
+            assert!((parts.seasonal[3] - parts.seasonal[15]).abs() < 1e-12);
+        }
+
+        let additive = stl_like(&airline, 12);
+        assert!(!additive.multiplicative);
+        // July peaks above the trend
+        assert!(additive.seasonal[6] > 0.0);
+    }
+}
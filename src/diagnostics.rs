@@ -0,0 +1,271 @@
+use std::fmt;
+use nalgebra::{DMatrix, DVector};
+use super::utils::{acf, chi_squared_sf};
+
+/// ResidualReport struct collects the adequacy tests of a model's residuals.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResidualReport {
+    pub mean: f64,                  // Mean of the residuals
+    pub variance: f64,              // Variance of the residuals
+    pub ljung_box: (f64, f64),      // Ljung-Box statistic and p-value
+    pub durbin_watson: f64,         // Durbin-Watson statistic
+    pub jarque_bera: (f64, f64),    // Jarque-Bera statistic and p-value
+    pub arch_lm: (f64, f64)         // ARCH-LM statistic and p-value
+}
+
+impl ResidualReport {
+    /// Single adequacy verdict at family-wise error rate `alpha` over the three tests with a
+    /// p-value (Ljung-Box, Jarque-Bera, ARCH-LM), so that running several tests does not
+    /// inflate the rejection rate. The residuals pass when Holm's step-down procedure rejects
+    /// none of the tests, which happens exactly when every p-value is at least `alpha / 3`,
+    /// the Bonferroni bound. A NaN p-value, from a test which could not be computed, fails the
+    /// verdict. Durbin-Watson, which has no p-value, does not enter the verdict.
+    pub fn passes(&self, alpha: f64) -> bool {
+        let p_values = [self.ljung_box.1, self.jarque_bera.1, self.arch_lm.1];
+        let threshold = alpha / p_values.len() as f64;
+        p_values.iter().all(|&p| p >= threshold)
+    }
+}
+
+impl fmt::Display for ResidualReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mean: {:.6}", self.mean)?;
+        writeln!(f, "variance: {:.6}", self.variance)?;
+        writeln!(f, "Ljung-Box: {:.4} (p-value {:.4})", self.ljung_box.0, self.ljung_box.1)?;
+        writeln!(f, "Durbin-Watson: {:.4}", self.durbin_watson)?;
+        writeln!(f, "Jarque-Bera: {:.4} (p-value {:.4})", self.jarque_bera.0, self.jarque_bera.1)?;
+        write!(f, "ARCH-LM: {:.4} (p-value {:.4})", self.arch_lm.0, self.arch_lm.1)
+    }
+}
+
+/// Runs the residual adequacy tests in one call: Ljung-Box and ARCH-LM up to `lags`,
+/// Durbin-Watson and Jarque-Bera, together with the residual mean and variance.
+/// `fitted_params` is the number of ARMA coefficients, removed from the Ljung-Box degrees of freedom.
+pub fn residual_diagnostics(residuals: &[f64], fitted_params: usize, lags: usize) -> ResidualReport {
+    let n = residuals.len() as f64;
+    let mean = residuals.iter().sum::<f64>() / n;
+    let variance = residuals.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / n;
+
+    ResidualReport {
+        mean,
+        variance,
+        ljung_box: ljung_box(residuals, lags, fitted_params),
+        durbin_watson: durbin_watson(residuals),
+        jarque_bera: jarque_bera(residuals),
+        arch_lm: arch_lm(residuals, lags),
+    }
+}
+
+/// Ljung-Box test for residual autocorrelation up to `lags`, `Q = n (n + 2) sum_k rho_k^2 / (n - k)`,
+/// with a chi-squared p-value on `lags - fitted_params` degrees of freedom.
+pub fn ljung_box(residuals: &[f64], lags: usize, fitted_params: usize) -> (f64, f64) {
+    portmanteau(residuals, lags, fitted_params, true)
+}
+
+/// Box-Pierce test for residual autocorrelation up to `lags`, `Q = n sum_k rho_k^2`, with a
+/// chi-squared p-value on `lags - fitted_params` degrees of freedom. It lacks the small-sample
+/// correction of the Ljung-Box statistic, which it approaches for large `n`.
+pub fn box_pierce(residuals: &[f64], lags: usize, fitted_params: usize) -> (f64, f64) {
+    portmanteau(residuals, lags, fitted_params, false)
+}
+
+/// Portmanteau statistic on the squared autocorrelations of the residuals, with the
+/// Ljung-Box weights `(n + 2) / (n - k)` if `small_sample` is true, unweighted otherwise.
+fn portmanteau(residuals: &[f64], lags: usize, fitted_params: usize, small_sample: bool) -> (f64, f64) {
+    let n = residuals.len() as f64;
+    let rho = acf(residuals, Some(lags), false);
+
+    let stat = n * rho.iter()
+        .enumerate()
+        .skip(1)
+        .map(|(k, r)| if small_sample { r * r * (n + 2.0) / (n - k as f64) } else { r * r })
+        .sum::<f64>();
+
+    let dof = lags.saturating_sub(fitted_params).max(1);
+    (stat, chi_squared_sf(stat, dof))
+}
+
+/// Durbin-Watson statistic, `sum (e_t - e_{t-1})^2 / sum e_t^2`: close to 2 without
+/// first-order autocorrelation, towards 0 (4) with positive (negative) autocorrelation.
+pub fn durbin_watson(residuals: &[f64]) -> f64 {
+    let num: f64 = residuals.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+    let den: f64 = residuals.iter().map(|e| e * e).sum();
+    num / den
+}
+
+/// Jarque-Bera normality test, `n / 6 (S^2 + (K - 3)^2 / 4)`, with a chi-squared p-value
+/// on 2 degrees of freedom.
+pub fn jarque_bera(residuals: &[f64]) -> (f64, f64) {
+    let n = residuals.len() as f64;
+    let mean = residuals.iter().sum::<f64>() / n;
+    let m2 = residuals.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / n;
+    let m3 = residuals.iter().map(|e| (e - mean).powi(3)).sum::<f64>() / n;
+    let m4 = residuals.iter().map(|e| (e - mean).powi(4)).sum::<f64>() / n;
+
+    let skewness = m3 / m2.powf(1.5);
+    let kurtosis = m4 / (m2 * m2);
+
+    let stat = n / 6.0 * (skewness.powi(2) + (kurtosis - 3.0).powi(2) / 4.0);
+    (stat, chi_squared_sf(stat, 2))
+}
+
+/// Engle's ARCH-LM test for conditional heteroskedasticity: regresses the squared residuals
+/// on a constant and their first `lags` lags, `LM = n R^2` with a chi-squared p-value on
+/// `lags` degrees of freedom. Both are NaN when `lags + 1 >= residuals.len()`, which leaves
+/// too few observations for the regression, and when the regressors are collinear, e.g. for
+/// residuals of constant magnitude.
+pub fn arch_lm(residuals: &[f64], lags: usize) -> (f64, f64) {
+    if lags + 1 >= residuals.len() {
+        return (f64::NAN, f64::NAN);
+    }
+    let squared: Vec<f64> = residuals.iter().map(|e| e * e).collect();
+    let n = squared.len() - lags;
+
+    // Construct the matrix of regressors
+    let mut x = DMatrix::from_element(n, lags + 1, 1.0);
+    for t in 0..n {
+        for j in 0..lags {
+            x[(t, j + 1)] = squared[t + lags - j - 1];
+        }
+    }
+    let y = DVector::from_column_slice(&squared[lags..]);
+
+    // OLS
+    let coefficients = match (x.transpose() * &x).cholesky() {
+        Some(chol) => chol.solve(&(x.transpose() * &y)),
+        None => return (f64::NAN, f64::NAN),
+    };
+    let fitted = &x * coefficients;
+
+    let y_mean = y.mean();
+    let ss_res: f64 = y.iter().zip(fitted.iter()).map(|(yi, fi)| (yi - fi).powi(2)).sum();
+    let ss_tot: f64 = y.iter().map(|yi| (yi - y_mean).powi(2)).sum();
+    let r_squared = 1.0 - ss_res / ss_tot;
+
+    let stat = n as f64 * r_squared;
+    (stat, chi_squared_sf(stat, lags))
+}
+
+/// Cumulative sum of the standardized residuals, `S_t = sum_{i <= t} e_i / sigma`, with
+/// `sigma^2` the mean square of the residuals. Under a correct model `S_t` wanders like a
+/// random walk around zero, while a drift in the residual mean makes it trend away.
+pub fn residual_cusum(residuals: &[f64]) -> Vec<f64> {
+    let n = residuals.len() as f64;
+    let sigma = (residuals.iter().map(|e| e * e).sum::<f64>() / n).sqrt();
+    if sigma == 0.0 {
+        return vec![0.0; residuals.len()];
+    }
+
+    residuals.iter()
+        .scan(0.0, |sum, e| {
+            *sum += e / sigma;
+            Some(*sum)
+        })
+        .collect()
+}
+
+/// Indices where the residual CUSUM crosses the control limit `threshold`, i.e. where
+/// `|S_t|` first exceeds it after being within it. Under a correct model `|S_t|` grows like
+/// `sqrt(t)`, so a limit of a few times `sqrt(n)` keeps false alarms rare; a crossing lags a
+/// mean shift of `delta` standard deviations by about `threshold / delta` observations.
+pub fn cusum_breakpoints(residuals: &[f64], threshold: f64) -> Vec<usize> {
+    let cusum = residual_cusum(residuals);
+    (0..cusum.len())
+        .filter(|&t| cusum[t].abs() > threshold && (t == 0 || cusum[t - 1].abs() <= threshold))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rand_distr::StandardNormal;
+
+    fn white_noise(length: usize, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..length).map(|_| rng.sample(StandardNormal)).collect()
+    }
+
+    #[test]
+    fn white_noise_passes_and_autocorrelated_residuals_fail() {
+        let noise = white_noise(500, 1);
+        let report = residual_diagnostics(&noise, 0, 10);
+        assert!(report.passes(0.05), "{}", report);
+        assert!((report.durbin_watson - 2.0).abs() < 0.3);
+
+        let mut structured = noise.clone();
+        for t in 1..structured.len() {
+            structured[t] += 0.8 * structured[t - 1];
+        }
+        let report = residual_diagnostics(&structured, 0, 10);
+        assert!(report.ljung_box.1 < 1e-6, "{}", report);
+        assert!(!report.passes(0.05));
+        assert!(report.to_string().contains("Ljung-Box"));
+    }
+
+    #[test]
+    fn arch_lm_is_nan_on_too_few_observations() {
+        let (stat, p_value) = arch_lm(&[1.0, 2.0, 3.0], 5);
+        assert!(stat.is_nan() && p_value.is_nan());
+    }
+
+    #[test]
+    fn arch_lm_is_nan_on_collinear_regressors() {
+        // squared residuals all equal one, so every lag duplicates the constant
+        let residuals: Vec<f64> = (0..50).map(|t| if t % 2 == 0 { 1.0 } else { -1.0 }).collect();
+        let (stat, p_value) = arch_lm(&residuals, 2);
+        assert!(stat.is_nan() && p_value.is_nan());
+
+        let mut report = residual_diagnostics(&residuals, 0, 2);
+        assert!(report.arch_lm.1.is_nan());
+        report.ljung_box.1 = 0.5;
+        report.jarque_bera.1 = 0.5;
+        assert!(!report.passes(0.05));
+    }
+
+    #[test]
+    fn box_pierce_approaches_ljung_box_in_large_samples() {
+        let relative_gap = |x: &[f64]| {
+            let (q_lb, _) = ljung_box(x, 10, 0);
+            let (q_bp, _) = box_pierce(x, 10, 0);
+            assert!(q_bp < q_lb);
+            (q_lb - q_bp) / q_lb
+        };
+
+        let small = relative_gap(&white_noise(30, 2));
+        let large = relative_gap(&white_noise(20000, 2));
+        assert!(small > 0.1, "{}", small);
+        assert!(large < 1e-3, "{}", large);
+    }
+
+    #[test]
+    fn cusum_flags_a_mean_shift() {
+        let mut residuals = white_noise(1000, 9);
+        for e in residuals.iter_mut().skip(500) {
+            *e += 1.0;
+        }
+
+        let cusum = residual_cusum(&residuals);
+        assert_eq!(cusum.len(), residuals.len());
+        let breakpoints = cusum_breakpoints(&residuals, 3.0 * 500.0_f64.sqrt());
+        assert!(!breakpoints.is_empty());
+        assert!(breakpoints[0] > 500 && breakpoints[0] < 650, "{:?}", breakpoints);
+
+        assert!(cusum_breakpoints(&white_noise(1000, 10), 3.0 * 1000.0_f64.sqrt()).is_empty());
+    }
+
+    #[test]
+    fn verdict_corrects_for_the_three_tests() {
+        let mut report = residual_diagnostics(&white_noise(500, 11), 0, 10);
+        report.ljung_box.1 = 0.5;
+        report.jarque_bera.1 = 0.5;
+
+        // a single p-value of 0.03 rejects on its own at 5%, but not after the correction
+        report.arch_lm.1 = 0.03;
+        assert!(report.passes(0.05));
+        assert!(!report.passes(0.1));
+        report.arch_lm.1 = 0.01;
+        assert!(!report.passes(0.05));
+    }
+}
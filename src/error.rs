@@ -0,0 +1,26 @@
+use core::fmt;
+use alloc::string::String;
+
+/// NefeleError represents the errors returned by the models.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NefeleError {
+    NotFitted,                  // The model has been used before calling `fit`
+    InvalidLength(String),      // An input has the wrong length
+    InvalidValue(String),       // An input holds a value outside its domain
+    InsufficientData(String),   // The series is too short for the requested computation
+    DegenerateInput(String)     // The series is empty or constant, so the statistic is undefined
+}
+
+impl fmt::Display for NefeleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NefeleError::NotFitted => write!(f, "model not yet fitted, call `fit` first"),
+            NefeleError::InvalidLength(msg) => write!(f, "invalid length: {}", msg),
+            NefeleError::InvalidValue(msg) => write!(f, "invalid value: {}", msg),
+            NefeleError::InsufficientData(msg) => write!(f, "insufficient data: {}", msg),
+            NefeleError::DegenerateInput(msg) => write!(f, "degenerate input: {}", msg),
+        }
+    }
+}
+
+impl core::error::Error for NefeleError {}
@@ -0,0 +1,322 @@
+use rand_distr::{Distribution, Normal};
+use finitediff::FiniteDiff;
+use super::error::NefeleError;
+use super::forecaster::Forecaster;
+use super::utils::OptimizerConfig;
+
+/// SimpleExponentialSmoothing struct represents a simple exponential smoothing model,
+/// `l_t = alpha y_t + (1 - alpha) l_{t-1}`, whose forecasts are the last level.
+#[derive(Debug, Clone)]
+pub struct SimpleExponentialSmoothing {
+    pub alpha: f64,             // Smoothing parameter of the level, in [0, 1]
+    pub sigma_squared: f64,     // Variance of the one-step-ahead errors
+    pub optimizer: OptimizerConfig, // Settings of the L-BFGS minimizer of the fit
+    fitted: bool                // Whether `fit` has been called
+}
+
+/// Holt struct represents Holt's linear trend method,
+/// `l_t = alpha y_t + (1 - alpha) (l_{t-1} + b_{t-1})` and
+/// `b_t = beta (l_t - l_{t-1}) + (1 - beta) b_{t-1}`, forecasting `l_t + h b_t`.
+#[derive(Debug, Clone)]
+pub struct Holt {
+    pub alpha: f64,             // Smoothing parameter of the level, in [0, 1]
+    pub beta: f64,              // Smoothing parameter of the trend, in [0, 1]
+    pub sigma_squared: f64,     // Variance of the one-step-ahead errors
+    pub optimizer: OptimizerConfig, // Settings of the L-BFGS minimizer of the fit
+    fitted: bool                // Whether `fit` has been called
+}
+
+impl SimpleExponentialSmoothing {
+    /// Creates a new SimpleExponentialSmoothing struct with default values.
+    pub fn new() -> SimpleExponentialSmoothing {
+        SimpleExponentialSmoothing { alpha: 0.5, sigma_squared: 0.0, optimizer: OptimizerConfig::default(), fitted: false }
+    }
+
+    /// Fits the smoothing parameter by minimizing the sum of squared one-step-ahead errors,
+    /// starting from the first observation as initial level.
+    pub fn fit(&mut self, data: &[f64]) {
+        let f = |params: &Vec<f64>| sse(&ses_filter(data, logistic(params[0])).1);
+        let params = minimize(&self.optimizer, f, vec![0.0]);
+
+        self.alpha = logistic(params[0]);
+        self.sigma_squared = error_variance(&ses_filter(data, self.alpha).1, 1);
+        self.fitted = true;
+    }
+
+    /// Computes the one-step-ahead errors of the fitted model on the provided data; the
+    /// error of the first observation, used as initial level, is set to 0.
+    pub fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(ses_filter(data, self.alpha).1)
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data: all forecasts equal the last level.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let (level, _) = ses_filter(data, self.alpha);
+        Ok(vec![level; horizon])
+    }
+
+    /// Simulates a simple exponential smoothing process from the initial level `level`,
+    /// `y_t = l_{t-1} + e_t` and `l_t = l_{t-1} + alpha e_t`.
+    pub fn simulate(
+        &self,
+        length: usize,
+        alpha: f64,
+        level: f64,
+        error_mean: f64,
+        error_variance: f64,
+    ) -> Vec<f64> {
+        let normal: Normal<f64> = Normal::new(error_mean, error_variance.sqrt()).unwrap();
+        let mut rng = rand::thread_rng();
+
+        let mut level = level;
+        (0..length)
+            .map(|_| {
+                let err = normal.sample(&mut rng);
+                let y = level + err;
+                level += alpha * err;
+                y
+            })
+            .collect()
+    }
+}
+
+impl Holt {
+    /// Creates a new Holt struct with default values.
+    pub fn new() -> Holt {
+        Holt { alpha: 0.5, beta: 0.1, sigma_squared: 0.0, optimizer: OptimizerConfig::default(), fitted: false }
+    }
+
+    /// Fits the smoothing parameters by minimizing the sum of squared one-step-ahead errors,
+    /// starting from the second observation as initial level and the first difference as
+    /// initial trend.
+    pub fn fit(&mut self, data: &[f64]) {
+        let f = |params: &Vec<f64>| sse(&holt_filter(data, logistic(params[0]), logistic(params[1])).2);
+        let params = minimize(&self.optimizer, f, vec![0.0, -2.0]);
+
+        self.alpha = logistic(params[0]);
+        self.beta = logistic(params[1]);
+        self.sigma_squared = error_variance(&holt_filter(data, self.alpha, self.beta).2, 2);
+        self.fitted = true;
+    }
+
+    /// Computes the one-step-ahead errors of the fitted model on the provided data; the
+    /// errors of the first two observations, used as initial states, are set to 0.
+    pub fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(holt_filter(data, self.alpha, self.beta).2)
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data along the last level and trend.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let (level, trend, _) = holt_filter(data, self.alpha, self.beta);
+        Ok((1..=horizon).map(|h| level + h as f64 * trend).collect())
+    }
+
+    /// Simulates a Holt linear trend process from the initial `level` and `trend`,
+    /// `y_t = l_{t-1} + b_{t-1} + e_t`, `l_t = l_{t-1} + b_{t-1} + alpha e_t` and
+    /// `b_t = b_{t-1} + alpha beta e_t`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn simulate(
+        &self,
+        length: usize,
+        alpha: f64,
+        beta: f64,
+        level: f64,
+        trend: f64,
+        error_mean: f64,
+        error_variance: f64,
+    ) -> Vec<f64> {
+        let normal: Normal<f64> = Normal::new(error_mean, error_variance.sqrt()).unwrap();
+        let mut rng = rand::thread_rng();
+
+        let (mut level, mut trend) = (level, trend);
+        (0..length)
+            .map(|_| {
+                let err = normal.sample(&mut rng);
+                let y = level + trend + err;
+                level += trend + alpha * err;
+                trend += alpha * beta * err;
+                y
+            })
+            .collect()
+    }
+}
+
+impl Default for SimpleExponentialSmoothing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for Holt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Forecaster for SimpleExponentialSmoothing {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        SimpleExponentialSmoothing::residuals(self, data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        SimpleExponentialSmoothing::forecast(self, data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        // alpha, initial level and error variance
+        3
+    }
+
+    fn warm_up(&self) -> usize {
+        // the first observation sets the initial level
+        1
+    }
+}
+
+impl Forecaster for Holt {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        Holt::residuals(self, data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        Holt::forecast(self, data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        // alpha, beta, initial level and trend and error variance
+        5
+    }
+
+    fn warm_up(&self) -> usize {
+        // the first two observations set the initial level and trend
+        2
+    }
+}
+
+/// Runs the simple exponential smoothing recursion, returning the last level and the
+/// one-step-ahead errors.
+fn ses_filter(data: &[f64], alpha: f64) -> (f64, Vec<f64>) {
+    let mut errors: Vec<f64> = vec![0.0; data.len().min(1)];
+    let mut level = data.first().copied().unwrap_or(0.0);
+    for &y in data.iter().skip(1) {
+        let err = y - level;
+        level += alpha * err;
+        errors.push(err);
+    }
+    (level, errors)
+}
+
+/// Runs Holt's recursion, returning the last level and trend and the one-step-ahead errors.
+fn holt_filter(data: &[f64], alpha: f64, beta: f64) -> (f64, f64, Vec<f64>) {
+    if data.len() < 2 {
+        return (data.first().copied().unwrap_or(0.0), 0.0, vec![0.0; data.len()]);
+    }
+    let mut errors: Vec<f64> = vec![0.0; 2];
+    let mut level = data[1];
+    let mut trend = data[1] - data[0];
+    for &y in data.iter().skip(2) {
+        let err = y - (level + trend);
+        let previous = level;
+        level = level + trend + alpha * err;
+        trend = beta * (level - previous) + (1.0 - beta) * trend;
+        errors.push(err);
+    }
+    (level, trend, errors)
+}
+
+/// Maps an unconstrained real to (0, 1), so that the optimizer searches smoothing parameters freely.
+fn logistic(u: f64) -> f64 {
+    1.0 / (1.0 + (-u).exp())
+}
+
+fn sse(errors: &[f64]) -> f64 {
+    errors.iter().map(|e| e * e).sum()
+}
+
+/// Variance of the one-step-ahead errors, skipping the `warm_up` initial ones.
+fn error_variance(errors: &[f64], warm_up: usize) -> f64 {
+    let errors = &errors[warm_up.min(errors.len())..];
+    sse(errors) / errors.len() as f64
+}
+
+/// Minimizes `f` with the L-BFGS minimizer configured by `optimizer` from `start`, with
+/// forward-difference gradients.
+fn minimize<F: Fn(&Vec<f64>) -> f64>(optimizer: &OptimizerConfig, f: F, start: Vec<f64>) -> Vec<f64> {
+    let g = |params: &Vec<f64>| params.forward_diff(&f);
+
+    let evaluate = |x: &[f64], gx: &mut [f64]| {
+        let x = x.to_vec();
+        let fx = f(&x);
+        let gx_eval = g(&x);
+        // copy values from gx_eval into gx
+        gx[..gx_eval.len()].copy_from_slice(&gx_eval[..]);
+        Ok(fx)
+    };
+
+    let mut params = start;
+    if let Err(e) = optimizer.minimize(&mut params, evaluate, |_prng| { false }) {
+        tracing::warn!("{}", e);
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ses_forecasts_the_last_level() {
+        let model = SimpleExponentialSmoothing::new();
+        let data = model.simulate(2000, 0.3, 10.0, 0.0, 1.0);
+        let mut model = SimpleExponentialSmoothing::new();
+        model.fit(&data);
+        assert!(model.alpha > 0.0 && model.alpha < 1.0);
+        assert!((model.alpha - 0.3).abs() < 0.1, "{}", model.alpha);
+
+        let mut level = data[0];
+        for y in &data[1..] {
+            level += model.alpha * (y - level);
+        }
+        let forecast = model.forecast(&data, 3).unwrap();
+        assert!(forecast.iter().all(|f| (f - level).abs() < 1e-9), "{:?} vs {}", forecast, level);
+    }
+
+    #[test]
+    fn holt_extends_a_straight_line() {
+        let data: Vec<f64> = (0..30).map(|t| 5.0 + 2.0 * t as f64).collect();
+        let mut model = Holt::new();
+        model.fit(&data);
+        assert!((0.0..=1.0).contains(&model.alpha) && (0.0..=1.0).contains(&model.beta));
+
+        let forecast = model.forecast(&data, 3).unwrap();
+        for (h, f) in forecast.iter().enumerate() {
+            assert!((f - (65.0 + 2.0 * h as f64)).abs() < 1e-9, "{:?}", forecast);
+        }
+        assert_eq!(Holt::new().forecast(&data, 3), Err(NefeleError::NotFitted));
+    }
+
+    #[test]
+    fn fit_uses_the_optimizer_settings() {
+        let data = SimpleExponentialSmoothing::new().simulate(1000, 0.8, 0.0, 0.0, 1.0);
+        let mut converged = SimpleExponentialSmoothing::new();
+        converged.fit(&data);
+
+        let mut stopped = SimpleExponentialSmoothing::new();
+        stopped.optimizer.max_iterations = 1;
+        stopped.fit(&data);
+        assert!((stopped.alpha - converged.alpha).abs() > 1e-3, "{} {}", stopped.alpha, converged.alpha);
+        assert!(sse(&stopped.residuals(&data).unwrap()) > sse(&converged.residuals(&data).unwrap()));
+    }
+}
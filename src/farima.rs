@@ -1,5 +1,6 @@
-use super::utils::{mean, diff, pacf, diffseries, residuals, closest_integer, compute_variance};
-use liblbfgs::lbfgs;
+use super::utils::{mean, input_quality, diff, initial_ar_from_pacf, diffseries_full, forecast, undiff_forecast, undiff_fractional_forecast, residuals, css_objective, closest_integer, compute_variance, normal_quantile, OptimizerConfig, initial_ma_from_acf};
+use super::error::NefeleError;
+use super::summary::Summary;
 use finitediff::FiniteDiff;
 
 /// FARIMA struct represents a fractional autoregressive integrated moving average model.
@@ -8,7 +9,10 @@ pub struct FARIMA {
     pub phi: Vec<f64>,          // AR coefficients
     pub diff: f64,              // Fractional differencing parameter
     pub theta: Vec<f64>,        // MA coefficients
-    pub sigma_squared: f64      // Variance of the model
+    pub intercept: f64,         // Intercept of the differenced series
+    pub sigma_squared: f64,     // Variance of the model
+    pub optimizer: OptimizerConfig, // Settings of the L-BFGS minimizer of the CSS fit
+    fitted: bool                // Whether `fit` has been called
 }
 
 impl FARIMA {
@@ -16,29 +20,163 @@ impl FARIMA {
     pub fn new() -> FARIMA {
         let phi: Vec<f64> = vec![0.0; 1];
         let theta: Vec<f64> = vec![0.0; 1];
-        FARIMA { phi, diff: 0.0, theta, sigma_squared: 0.0 }
+        FARIMA { phi, diff: 0.0, theta, intercept: 0.0, sigma_squared: 0.0, optimizer: OptimizerConfig::default(), fitted: false }
     }
 
     /// Prints a summary of the FARIMA model.
     pub fn summary(&self) {
-        println!("phi: {:?}\nd: {}\ntheta: {:?} \nsigma_squared {}", self.phi, self.diff, self.theta, self.sigma_squared);
+        println!("{}", self.to_summary());
+    }
+
+    /// Collects the estimates of the FARIMA model in a Summary struct.
+    pub fn to_summary(&self) -> Summary {
+        Summary {
+            model: format!("FARIMA({}, {}, {})", self.phi.len(), self.diff, self.theta.len()),
+            fitted: self.fitted,
+            phi: self.phi.clone(),
+            phi_std_errors: None,
+            diff: Some(self.diff),
+            theta: self.theta.clone(),
+            intercept: self.intercept,
+            sigma_squared: self.sigma_squared,
+            aic: None,
+            bic: None
+        }
+    }
+
+    /// Returns the number of free parameters: coefficients, intercept, differencing parameter
+    /// and innovation variance.
+    pub fn n_params(&self) -> usize {
+        self.phi.len() + self.theta.len() + 3
+    }
+
+    /// Computes the in-sample residuals of the fitted model on the differenced data.
+    pub fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(residuals(&self.differenced(data), self.intercept, &self.phi, &self.theta))
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data, on the original scale: the ARMA
+    /// forecast of the differenced series is integrated back and the fractional differencing undone.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let int_d = closest_integer(self.diff);
+        let frac_data = diffseries_full(data, self.diff - int_d as f64);
+
+        let diff_forecast = forecast(&diff(&frac_data, int_d), self.intercept, &self.phi, &self.theta, horizon);
+        let frac_forecast = undiff_forecast(&frac_data, &diff_forecast, int_d);
+        Ok(undiff_fractional_forecast(data, &frac_forecast, self.diff - int_d as f64))
     }
 
     /// Fits the FARIMA model to the provided data.
     pub fn fit(&mut self, data: &Vec<f64>, p: usize, d: f64, q: usize) {
+        if self.prepare_fit(data).is_err() {
+            return;
+        }
+        self.diff = d;
+        let diff_data = self.differenced(data);
 
-        let int_d = closest_integer(d);
-
-        // Fractional integration
-        let mut diff_data = diffseries(data, d - int_d as f64);
-        diff_data = diff(&diff_data, int_d);
+        Self::fit_css(self, &diff_data, p, q, None);
+        self.sigma_squared = compute_variance(&diff_data, &self.phi);
+        self.fitted = true;
+    }
 
+    /// Fits the model by CSS starting the optimizer from `initial` instead of the default
+    /// guesses. `initial` holds the intercept followed by the AR and MA coefficients, so it must
+    /// have length `1 + p + q`. Trying several starting points helps escaping bad local minima.
+    /// The data are checked as in `fit`, and a degenerate series returns its problem as an error.
+    pub fn fit_css_with_initial(&mut self, data: &Vec<f64>, p: usize, d: f64, q: usize, initial: Vec<f64>) -> Result<(), NefeleError> {
+        if initial.len() != 1 + p + q {
+            return Err(NefeleError::InvalidLength(format!(
+                "initial guess has {} values, expected {}", initial.len(), 1 + p + q
+            )));
+        }
+        self.prepare_fit(data)?;
         self.diff = d;
-        Self::fit_css(self, &diff_data, p, q);
+        let diff_data = self.differenced(data);
+
+        Self::fit_css(self, &diff_data, p, q, Some(initial));
         self.sigma_squared = compute_variance(&diff_data, &self.phi);
+        self.fitted = true;
+        Ok(())
+    }
+
+    /// Computes a profile-likelihood confidence interval for the differencing parameter `d`.
+    /// At each trial `d` the ARMA part is re-fitted by CSS, warm-started from the current
+    /// estimates, and the interval is the set of `d` whose likelihood-ratio statistic
+    /// `n ln(s^2(d) / s^2(d_hat))` stays below the chi-squared(1) quantile at `confidence`.
+    /// Each bound is located within `1e-4` by bisection, searching at most 1 away from `d_hat`.
+    /// An error of any of the re-fits is returned.
+    pub fn d_confidence_interval(&self, data: &Vec<f64>, confidence: f64) -> Result<(f64, f64), NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let diff_data = self.differenced(data);
+        let n = diff_data.len() as f64;
+        let s2_hat = css_objective(&diff_data, self.intercept, &self.phi, &self.theta) / n;
+        let critical = normal_quantile(0.5 + confidence / 2.0).powi(2);
+
+        let outside = |d: f64| -> Result<bool, NefeleError> {
+            Ok(n * (self.profile_mean_square(data, d)? / s2_hat).ln() > critical)
+        };
+        let bound = |direction: f64| -> Result<f64, NefeleError> {
+            // Step away from the estimate until the profile crosses the critical value, then bisect
+            let (mut inside, mut step) = (self.diff, 0.05);
+            while step <= 1.0 && !outside(self.diff + direction * step)? {
+                inside = self.diff + direction * step;
+                step += 0.05;
+            }
+            let mut beyond = self.diff + direction * step.min(1.0);
+            while (beyond - inside).abs() > 1e-4 {
+                let middle = 0.5 * (inside + beyond);
+                if outside(middle)? { beyond = middle } else { inside = middle }
+            }
+            Ok(inside)
+        };
+
+        Ok((bound(-1.0)?, bound(1.0)?))
     }
 
-    fn fit_css(&mut self, data: &Vec<f64>, p: usize, q: usize) {
+    /// Re-fits the ARMA part with the differencing parameter fixed at `d`, starting from the
+    /// current estimates, and returns the mean squared residual of the differenced series.
+    fn profile_mean_square(&self, data: &Vec<f64>, d: f64) -> Result<f64, NefeleError> {
+        let mut initial = vec![self.intercept];
+        initial.extend(&self.phi);
+        initial.extend(&self.theta);
+
+        let mut model = self.clone();
+        model.fit_css_with_initial(data, self.phi.len(), d, self.theta.len(), initial)?;
+        let diff_data = model.differenced(data);
+        Ok(css_objective(&diff_data, model.intercept, &model.phi, &model.theta) / diff_data.len() as f64)
+    }
+
+    /// Checks the data before a fit. On a degenerate series the model is left unfitted with
+    /// NaN statistics and the problem returned as an error.
+    fn prepare_fit(&mut self, data: &[f64]) -> Result<(), NefeleError> {
+        let quality = input_quality(data);
+        quality.warn();
+        if quality.is_degenerate() {
+            self.fitted = false;
+            self.sigma_squared = f64::NAN;
+            return quality.check();
+        }
+        Ok(())
+    }
+
+    /// Applies the fractional and integer differencing of order `self.diff` to the data.
+    fn differenced(&self, data: &[f64]) -> Vec<f64> {
+        let int_d = closest_integer(self.diff);
+
+        // Fractional integration
+        let diff_data = diffseries_full(data, self.diff - int_d as f64);
+        diff(&diff_data, int_d)
+    }
+
+    fn fit_css(&mut self, data: &Vec<f64>, p: usize, q: usize, initial: Option<Vec<f64>>) {
 
         let total_size = 1 + p + q;
 
@@ -51,34 +189,33 @@ impl FARIMA {
             let phi = &coef[1..p + 1];
             let theta = &coef[p + 1..];
 
-            let residuals = residuals(&data, intercept, &phi.to_vec(), &theta.to_vec());
-
-            let mut css: f64 = 0.0;
-            for residual in &residuals {
-                css += residual * residual;
-            }
-            css
+            css_objective(data, intercept, phi, theta)
         };
         let g = |coef: &Vec<f64>| coef.forward_diff(&f);
 
-        // Initial coefficients
-        let mut coef: Vec<f64> = Vec::new();
+        // Initial coefficients, unless supplied by the caller
+        let mut coef: Vec<f64> = match initial {
+            Some(initial) => initial,
+            None => {
+                let mut coef: Vec<f64> = Vec::new();
 
-        // Initial guess for the intercept: First value of data
-        coef.push(mean(&data));
+                // Initial guess for the intercept: First value of data
+                coef.push(mean(&data));
 
-        // Initial guess for the p coefficients: Values of the PACF
-        if p > 0 {
-            let pacf = pacf(&data, Some(p));
-            for p in pacf {
-                coef.push(p);
-            }
-        }
+                // Initial guess for the p coefficients: Values of the PACF
+                if p > 0 {
+                    coef.extend(initial_ar_from_pacf(data, p));
+                }
 
-        // Initial guess for the MA coefficients: 1.0
-        if q > 0 {
-            coef.resize(coef.len() + q, 1.0);
-        }
+                // Initial guess for the MA coefficients: matched to the ACF of the AR-filtered data
+                if q > 0 {
+                    let filtered = residuals(data, 0.0, &coef[1..], &[]);
+                    coef.extend(initial_ma_from_acf(&filtered[p.min(filtered.len())..], q));
+                }
+
+                coef
+            }
+        };
 
         let evaluate = |x: &[f64], gx: &mut [f64]| {
             let x = x.to_vec();
@@ -89,8 +226,7 @@ impl FARIMA {
             Ok(fx)
         };
 
-        let fmin = lbfgs().with_max_iterations(200);
-        if let Err(_e) = fmin.minimize(
+        if let Err(_e) = self.optimizer.minimize(
             &mut coef, // input variables
             evaluate,  // define how to evaluate function
             |_prng| {
@@ -98,7 +234,47 @@ impl FARIMA {
             },
         ) {}
 
+        self.intercept = coef[0];
         self.phi = coef[1..=p].to_vec();
         self.theta = coef[p+1..].to_vec();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unfitted_model_reports_not_fitted() {
+        let model = FARIMA::new();
+        let data = vec![1.0, 2.0, 1.5, 0.5, 1.0];
+
+        assert_eq!(model.forecast(&data, 3), Err(NefeleError::NotFitted));
+        assert_eq!(model.residuals(&data), Err(NefeleError::NotFitted));
+        assert!(model.to_summary().to_string().contains("not yet fitted"));
+    }
+
+    #[test]
+    fn d_interval_covers_the_true_d() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        use rand_distr::StandardNormal;
+
+        // x = (1 - L)^-0.3 e, with MA(infinity) weights psi_k = psi_{k-1} (k - 1 + d) / k
+        let (d, n) = (0.3, 2000);
+        let mut rng = StdRng::seed_from_u64(5);
+        let shocks: Vec<f64> = (0..n).map(|_| rng.sample(StandardNormal)).collect();
+        let mut psi = vec![1.0; n];
+        for k in 1..n {
+            psi[k] = psi[k - 1] * (k as f64 - 1.0 + d) / k as f64;
+        }
+        let data: Vec<f64> = (0..n).map(|t| (0..=t).map(|k| psi[k] * shocks[t - k]).sum()).collect();
+
+        let mut model = FARIMA::new();
+        model.fit(&data, 0, d, 0);
+        let (lower, upper) = model.d_confidence_interval(&data, 0.95).unwrap();
+        assert!(lower < d && d < upper, "({}, {})", lower, upper);
+        assert!(upper - lower < 0.2 && lower > 0.0, "({}, {})", lower, upper);
+
+        assert_eq!(FARIMA::new().d_confidence_interval(&data, 0.95), Err(NefeleError::NotFitted));
+    }
+}
@@ -0,0 +1,636 @@
+use super::ar::{ARMethod, AutoRegressive};
+use super::arima::{ARIMAMethod, ARIMA};
+use super::arma::{ARMAMethod, ARMA};
+use super::error::NefeleError;
+use super::farima::FARIMA;
+use super::ma::{MAMethod, MovingAverage};
+use super::utils::{aic, aicc, bic, hqic, gamma_q, log_returns, newey_west_variance, prices_from_returns, warm_up, OrderCriterion};
+
+/// Point forecasts together with the lower and upper bounds of their prediction intervals.
+pub type ForecastInterval = (Vec<f64>, Vec<f64>, Vec<f64>);
+
+/// Prediction struct holds in-sample fitted values and out-of-sample forecasts together with
+/// their time indices, relative to the start of the input series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prediction {
+    pub values: Vec<f64>,           // Fitted values followed by the forecasts
+    pub index: Vec<usize>,          // Time index of each value
+    pub in_sample: Vec<bool>        // Whether each value is in-sample (fitted) or out-of-sample
+}
+
+impl Prediction {
+    /// Returns the index and value pairs of the in-sample fitted values.
+    pub fn fitted(&self) -> Vec<(usize, f64)> {
+        self.select(true)
+    }
+
+    /// Returns the index and value pairs of the out-of-sample forecasts.
+    pub fn forecasts(&self) -> Vec<(usize, f64)> {
+        self.select(false)
+    }
+
+    fn select(&self, in_sample: bool) -> Vec<(usize, f64)> {
+        self.index.iter()
+            .zip(&self.values)
+            .zip(&self.in_sample)
+            .filter(|(_, &is)| is == in_sample)
+            .map(|((&i, &v), _)| (i, v))
+            .collect()
+    }
+}
+
+/// ForecastState struct carries the recent observations and residuals an ARMA recursion
+/// needs to forecast the next value, so that streaming forecasts avoid recomputing the
+/// residuals over the whole history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForecastState {
+    pub observations: Vec<f64>,     // Last `p` observations, most recent last
+    pub residuals: Vec<f64>         // Last `q` residuals, most recent last
+}
+
+/// Forecaster trait represents a fitted model which can compute residuals and forecasts.
+pub trait Forecaster {
+    /// Computes the in-sample residuals of the fitted model on the provided data.
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError>;
+
+    /// Forecasts `horizon` steps ahead of the provided data.
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError>;
+
+    /// Number of free parameters, intercept and innovation variance included.
+    fn n_params(&self) -> usize;
+
+    /// Number of leading residuals of the warm-up, which are set to zero instead of estimated.
+    fn warm_up(&self) -> usize {
+        0
+    }
+
+    /// Forecasts `horizon` price levels ahead of a positive price series, for a model fitted
+    /// on its log-returns: the returns are forecast and converted back to prices.
+    fn forecast_prices(&self, prices: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        let last_price = match prices.last() {
+            Some(&price) => price,
+            None => return Err(NefeleError::InvalidLength("no prices provided".to_string())),
+        };
+        let returns = log_returns(prices)?;
+        let forecast = self.forecast(&returns, horizon)?;
+        Ok(prices_from_returns(&forecast, last_price))
+    }
+}
+
+/// Model enum wraps any of the models, so that they can be held uniformly, e.g. in a
+/// collection keyed by series.
+#[derive(Debug, Clone)]
+pub enum Model {
+    AR(AutoRegressive),
+    MA(MovingAverage),
+    ARMA(ARMA),
+    ARIMA(ARIMA),
+    FARIMA(FARIMA)
+}
+
+impl Model {
+    /// Fits the wrapped model to the provided data by CSS, keeping its current orders
+    /// (and differencing parameter).
+    pub fn fit(&mut self, data: &Vec<f64>) {
+        match self {
+            Model::AR(model) => {
+                let order = model.phi.len();
+                model.fit(data, order, ARMethod::CSS)
+            }
+            Model::MA(model) => {
+                let order = model.theta.len();
+                model.fit(data, order, MAMethod::CSS)
+            }
+            Model::ARMA(model) => {
+                let (p, q) = (model.phi.len(), model.theta.len());
+                model.fit(data, p, q, ARMAMethod::CSS)
+            }
+            Model::ARIMA(model) => {
+                let (p, d, q) = (model.phi.len(), model.diff, model.theta.len());
+                model.fit(data, p, d, q, ARIMAMethod::CSS)
+            }
+            Model::FARIMA(model) => {
+                let (p, d, q) = (model.phi.len(), model.diff, model.theta.len());
+                model.fit(data, p, d, q)
+            }
+        }
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data with the wrapped model.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        self.as_forecaster().forecast(data, horizon)
+    }
+
+    /// Boxes the wrapped model as a Forecaster trait object.
+    pub fn into_forecaster(self) -> Box<dyn Forecaster> {
+        match self {
+            Model::AR(model) => Box::new(model),
+            Model::MA(model) => Box::new(model),
+            Model::ARMA(model) => Box::new(model),
+            Model::ARIMA(model) => Box::new(model),
+            Model::FARIMA(model) => Box::new(model)
+        }
+    }
+
+    fn as_forecaster(&self) -> &dyn Forecaster {
+        match self {
+            Model::AR(model) => model,
+            Model::MA(model) => model,
+            Model::ARMA(model) => model,
+            Model::ARIMA(model) => model,
+            Model::FARIMA(model) => model
+        }
+    }
+}
+
+impl From<AutoRegressive> for Model {
+    fn from(model: AutoRegressive) -> Model {
+        Model::AR(model)
+    }
+}
+
+impl From<MovingAverage> for Model {
+    fn from(model: MovingAverage) -> Model {
+        Model::MA(model)
+    }
+}
+
+impl From<ARMA> for Model {
+    fn from(model: ARMA) -> Model {
+        Model::ARMA(model)
+    }
+}
+
+impl From<ARIMA> for Model {
+    fn from(model: ARIMA) -> Model {
+        Model::ARIMA(model)
+    }
+}
+
+impl From<FARIMA> for Model {
+    fn from(model: FARIMA) -> Model {
+        Model::FARIMA(model)
+    }
+}
+
+/// Criterion represents criteria for comparing fitted models.
+#[derive(Debug, Clone, Copy)]
+pub enum Criterion {
+    AIC,    // Akaike Information Criterion
+    BIC,    // Bayesian Information Criterion
+    AICc,   // Small-sample corrected Akaike Information Criterion
+    HQIC    // Hannan-Quinn Information Criterion
+}
+
+impl OrderCriterion for Criterion {
+    fn evaluate(&self, n: usize, rss: f64, k: usize) -> f64 {
+        match self {
+            Criterion::AIC => aic(n, rss, k),
+            Criterion::BIC => bic(n, rss, k),
+            Criterion::AICc => aicc(n, rss, k),
+            Criterion::HQIC => hqic(n, rss, k),
+        }
+    }
+}
+
+/// Computes the information criterion of a fitted model from its residuals on the provided data.
+pub fn criterion_value<F: Forecaster + ?Sized>(model: &F, data: &[f64], criterion: Criterion) -> Result<f64, NefeleError> {
+    let residuals = model.residuals(data)?;
+
+    // the residuals of the warm-up observations are set to zero and left out
+    let residuals = &residuals[model.warm_up().min(residuals.len())..];
+    let n = residuals.len();
+    let rss: f64 = residuals.iter().map(|e| e * e).sum();
+
+    Ok(criterion.evaluate(n, rss, model.n_params()))
+}
+
+/// Returns whichever of the two fitted models has the lower criterion on the provided data.
+/// A model which cannot be evaluated, e.g. because it is not fitted, always loses.
+pub fn better_of<F: Forecaster>(a: F, b: F, data: &[f64], criterion: Criterion) -> F {
+    let value_a = criterion_value(&a, data, criterion).unwrap_or(f64::INFINITY);
+    let value_b = criterion_value(&b, data, criterion).unwrap_or(f64::INFINITY);
+
+    if value_b < value_a { b } else { a }
+}
+
+/// EnsembleWeights represents the ways the members of a forecast ensemble are weighted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnsembleWeights {
+    Equal,  // Every member weighs the same
+    AIC     // Akaike weights exp(-(AIC - min AIC) / 2), normalized to sum to one
+}
+
+/// ForecastEnsemble struct combines the forecasts of several fitted models into their
+/// weighted average.
+pub struct ForecastEnsemble {
+    members: Vec<Box<dyn Forecaster>>,  // Fitted member models
+    pub weighting: EnsembleWeights,     // How the members are weighted
+    weights: Vec<f64>,                  // Weight of each member, summing to one
+    fitted: bool                        // Whether `fit` has been called
+}
+
+impl ForecastEnsemble {
+    /// Creates a new ForecastEnsemble of already fitted models.
+    pub fn new(members: Vec<Box<dyn Forecaster>>, weighting: EnsembleWeights) -> ForecastEnsemble {
+        ForecastEnsemble { members, weighting, weights: Vec::new(), fitted: false }
+    }
+
+    /// Computes the weights of the members on the provided data. The members are not refitted,
+    /// since the Forecaster trait has no `fit`: fit each one, e.g. with `Model::fit`, before
+    /// building the ensemble. With AIC weights a lower AIC gives a larger weight, the model
+    /// averaging weights of Burnham and Anderson.
+    pub fn fit(&mut self, data: &[f64]) -> Result<(), NefeleError> {
+        if self.members.is_empty() {
+            return Err(NefeleError::InvalidLength("the ensemble has no members".to_string()));
+        }
+
+        let weights = match self.weighting {
+            EnsembleWeights::Equal => vec![1.0; self.members.len()],
+            EnsembleWeights::AIC => {
+                let aics = self.members.iter()
+                    .map(|member| criterion_value(member.as_ref(), data, Criterion::AIC))
+                    .collect::<Result<Vec<f64>, NefeleError>>()?;
+                let best = aics.iter().cloned().fold(f64::INFINITY, f64::min);
+                aics.iter().map(|aic| (-(aic - best) / 2.0).exp()).collect()
+            }
+        };
+        let total: f64 = weights.iter().sum();
+        self.weights = weights.iter().map(|w| w / total).collect();
+        self.fitted = true;
+        Ok(())
+    }
+
+    /// Returns the weight of each member, in the order they were given.
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data as the weighted average of the
+    /// forecasts of the members.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        self.combine(|member| member.forecast(data, horizon))
+    }
+
+    /// Computes the residuals of the weighted average of the one-step predictions of the
+    /// members, i.e. the weighted average of their residuals.
+    pub fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        self.combine(|member| member.residuals(data))
+    }
+
+    fn combine<M>(&self, output: M) -> Result<Vec<f64>, NefeleError>
+    where
+        M: Fn(&dyn Forecaster) -> Result<Vec<f64>, NefeleError>,
+    {
+        let mut combined: Vec<f64> = Vec::new();
+        for (member, weight) in self.members.iter().zip(&self.weights) {
+            let values = output(member.as_ref())?;
+            combined.resize(values.len(), 0.0);
+            for (c, v) in combined.iter_mut().zip(&values) {
+                *c += weight * v;
+            }
+        }
+        Ok(combined)
+    }
+}
+
+impl Forecaster for ForecastEnsemble {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        ForecastEnsemble::residuals(self, data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        ForecastEnsemble::forecast(self, data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        self.members.iter().map(|member| member.n_params()).sum()
+    }
+
+    fn warm_up(&self) -> usize {
+        self.members.iter().map(|member| member.warm_up()).max().unwrap_or(0)
+    }
+}
+
+/// Backtests a fitted model over the last `test_size` observations with a rolling forecast
+/// origin, and returns the mean squared forecast error at each horizon from 1 to
+/// `max_horizon`. From every origin `t` in the test window the model forecasts from
+/// `data[..t]`, and the error at horizon `h` is taken against `data[t + h - 1]` when it is
+/// available, so horizon `h` averages over `test_size - h + 1` origins. The model is not
+/// refitted, so fit it on the observations before the test window for an honest backtest.
+pub fn horizon_accuracy<F: Forecaster + ?Sized>(model: &F, data: &[f64], test_size: usize, max_horizon: usize) -> Result<Vec<(usize, f64)>, NefeleError> {
+    if test_size == 0 || test_size >= data.len() {
+        return Err(NefeleError::InvalidLength(format!(
+            "test size {} on {} observations", test_size, data.len()
+        )));
+    }
+    if max_horizon > test_size {
+        return Err(NefeleError::InvalidLength(format!(
+            "horizon {} longer than the test size {}", max_horizon, test_size
+        )));
+    }
+
+    let mut squared_errors = vec![0.0; max_horizon];
+    let mut counts = vec![0usize; max_horizon];
+    for origin in (data.len() - test_size)..data.len() {
+        let horizon = max_horizon.min(data.len() - origin);
+        let forecast = model.forecast(&data[..origin], horizon)?;
+        for (h, value) in forecast.iter().enumerate() {
+            squared_errors[h] += (data[origin + h] - value).powi(2);
+            counts[h] += 1;
+        }
+    }
+
+    Ok(squared_errors.iter()
+        .zip(&counts)
+        .enumerate()
+        .map(|(h, (sse, &count))| (h + 1, sse / count as f64))
+        .collect())
+}
+
+/// Diebold-Mariano test of equal forecast accuracy of two models, from their out-of-sample
+/// forecast errors at the same `horizon`, under squared-error loss. The loss differential
+/// `d_t = e_a,t^2 - e_b,t^2` has its mean standardized with a Newey-West variance over
+/// `horizon - 1` lags. Returns the statistic, negative when model `a` is more accurate, and
+/// its two-sided standard normal p-value.
+pub fn diebold_mariano(errors_a: &[f64], errors_b: &[f64], horizon: usize) -> (f64, f64) {
+    let differential: Vec<f64> = errors_a.iter()
+        .zip(errors_b)
+        .map(|(a, b)| a * a - b * b)
+        .collect();
+    let n = differential.len() as f64;
+    let mean = differential.iter().sum::<f64>() / n;
+
+    let variance = newey_west_variance(&differential, horizon.saturating_sub(1));
+    let stat = mean / (variance / n).sqrt();
+
+    // P(|Z| > z) = Q(1/2, z^2 / 2)
+    (stat, gamma_q(0.5, stat * stat / 2.0))
+}
+
+impl<F: Forecaster + ?Sized> Forecaster for Box<F> {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        (**self).residuals(data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        (**self).forecast(data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        (**self).n_params()
+    }
+
+    fn warm_up(&self) -> usize {
+        (**self).warm_up()
+    }
+}
+
+impl Forecaster for AutoRegressive {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        AutoRegressive::residuals(self, data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        AutoRegressive::forecast(self, data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        AutoRegressive::n_params(self)
+    }
+
+    fn warm_up(&self) -> usize {
+        warm_up(self.phi.len(), 0)
+    }
+}
+
+impl Forecaster for MovingAverage {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        MovingAverage::residuals(self, data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        MovingAverage::forecast(self, data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        MovingAverage::n_params(self)
+    }
+
+    fn warm_up(&self) -> usize {
+        warm_up(0, self.theta.len())
+    }
+}
+
+impl Forecaster for ARMA {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        ARMA::residuals(self, data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        ARMA::forecast(self, data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        ARMA::n_params(self)
+    }
+
+    fn warm_up(&self) -> usize {
+        warm_up(self.phi.len(), self.theta.len())
+    }
+}
+
+impl Forecaster for ARIMA {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        ARIMA::residuals(self, data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        ARIMA::forecast(self, data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        ARIMA::n_params(self)
+    }
+
+    fn warm_up(&self) -> usize {
+        warm_up(self.phi.len(), self.theta.len())
+    }
+}
+
+impl Forecaster for FARIMA {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        FARIMA::residuals(self, data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        FARIMA::forecast(self, data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        FARIMA::n_params(self)
+    }
+
+    fn warm_up(&self) -> usize {
+        warm_up(self.phi.len(), self.theta.len())
+    }
+}
+
+impl Forecaster for Model {
+    fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        self.as_forecaster().residuals(data)
+    }
+
+    fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        self.as_forecaster().forecast(data, horizon)
+    }
+
+    fn n_params(&self) -> usize {
+        self.as_forecaster().n_params()
+    }
+
+    fn warm_up(&self) -> usize {
+        self.as_forecaster().warm_up()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arma_beats_ar_on_arma_data() {
+        let data = ARMA::new().simulate_stationary_init(1000, vec![0.6], vec![0.8], 0.0, 1.0, 1).unwrap();
+        let mut ar = AutoRegressive::new();
+        ar.fit(&data, 1, ARMethod::OLS);
+        let mut arma = ARMA::new();
+        arma.fit(&data, 1, 1, ARMAMethod::CSS);
+
+        for criterion in [Criterion::AIC, Criterion::BIC, Criterion::AICc, Criterion::HQIC] {
+            let best = better_of(Model::from(ar.clone()), Model::from(arma.clone()), &data, criterion);
+            assert!(matches!(best, Model::ARMA(_)), "{:?}", criterion);
+        }
+        // an unfitted model always loses
+        let best = better_of(Model::from(ARMA::new()), Model::from(ar), &data, Criterion::AIC);
+        assert!(matches!(best, Model::AR(_)));
+    }
+
+    #[test]
+    fn every_model_forecasts_the_same_through_the_enum() {
+        let data = ARMA::new().simulate_stationary_init(300, vec![0.5], vec![0.3], 0.0, 1.0, 2).unwrap();
+        let mut ar = AutoRegressive::new();
+        ar.fit(&data, 2, ARMethod::CSS);
+        let mut ma = MovingAverage::new();
+        ma.fit(&data, 1, MAMethod::CSS);
+        let mut arma = ARMA::new();
+        arma.fit(&data, 1, 1, ARMAMethod::CSS);
+        let mut arima = ARIMA::new();
+        arima.fit(&data, 1, 0, 1, ARIMAMethod::CSS);
+        let mut farima = FARIMA::new();
+        farima.fit(&data, 1, 0.2, 0);
+
+        let expected = [
+            Forecaster::forecast(&ar, &data, 5).unwrap(),
+            Forecaster::forecast(&ma, &data, 5).unwrap(),
+            Forecaster::forecast(&arma, &data, 5).unwrap(),
+            Forecaster::forecast(&arima, &data, 5).unwrap(),
+            Forecaster::forecast(&farima, &data, 5).unwrap(),
+        ];
+        let models = [
+            Model::from(ar), Model::from(ma), Model::from(arma), Model::from(arima), Model::from(farima)
+        ];
+        for (model, expected) in models.into_iter().zip(expected) {
+            assert_eq!(model.forecast(&data, 5).unwrap(), expected);
+            assert_eq!(model.into_forecaster().forecast(&data, 5).unwrap(), expected);
+        }
+
+        // refitting through the enum keeps the orders
+        let mut model = Model::from(ARMA::new());
+        if let Model::ARMA(arma) = &mut model {
+            arma.phi = vec![0.0];
+            arma.theta = vec![0.0];
+        }
+        model.fit(&data);
+        assert!(matches!(&model, Model::ARMA(arma) if arma.phi.len() == 1 && arma.theta.len() == 1));
+        assert_eq!(model.forecast(&data, 5).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn price_forecasts_compound_the_return_forecasts() {
+        let returns = ARMA::new().simulate_stationary_init(300, vec![0.3], vec![], 0.001, 1e-4, 3).unwrap();
+        let mut prices = vec![50.0];
+        prices.extend(prices_from_returns(&returns, 50.0));
+        let mut model = AutoRegressive::new();
+        model.fit(&returns, 1, ARMethod::OLS);
+
+        let forecast = model.forecast_prices(&prices, 4).unwrap();
+        let expected = prices_from_returns(&Forecaster::forecast(&model, &returns, 4).unwrap(), prices[300]);
+        for (f, e) in forecast.iter().zip(&expected) {
+            assert!((f - e).abs() < 1e-9, "{} vs {}", f, e);
+        }
+        assert!(matches!(model.forecast_prices(&[], 4), Err(NefeleError::InvalidLength(_))));
+    }
+
+    #[test]
+    fn diebold_mariano_detects_the_more_accurate_model() {
+        let noise = |seed| ARMA::new().simulate_stationary_init(400, vec![], vec![], 0.0, 1.0, seed).unwrap();
+        let errors_b = noise(1);
+        let errors_a: Vec<f64> = errors_b.iter().map(|e| 0.5 * e).collect();
+
+        let (stat, p_value) = diebold_mariano(&errors_a, &errors_b, 1);
+        assert!(stat < 0.0 && p_value < 0.01, "{} {}", stat, p_value);
+        let (stat, _) = diebold_mariano(&errors_b, &errors_a, 3);
+        assert!(stat > 0.0);
+
+        // equally accurate models
+        let (_, p_value) = diebold_mariano(&noise(2), &noise(3), 1);
+        assert!(p_value > 0.05, "{}", p_value);
+    }
+
+    #[test]
+    fn forecast_error_grows_with_the_horizon() {
+        let data = AutoRegressive::new().simulate_stationary_init(1400, vec![0.8], 0.0, 1.0, 4).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data[..1000].to_vec(), 1, ARMethod::OLS);
+
+        let accuracy = horizon_accuracy(&model, &data, 400, 5).unwrap();
+        assert_eq!(accuracy.iter().map(|(h, _)| *h).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        for pair in accuracy.windows(2) {
+            assert!(pair[1].1 >= pair[0].1 - 0.05, "{:?}", accuracy);
+        }
+        // from the one-step innovation variance towards the variance of the process
+        assert!((accuracy[0].1 - 1.0).abs() < 0.2 && accuracy[4].1 > 1.5, "{:?}", accuracy);
+
+        assert!(matches!(horizon_accuracy(&model, &data, 3, 5), Err(NefeleError::InvalidLength(_))));
+    }
+
+    #[test]
+    fn ensemble_of_identical_models_forecasts_like_each() {
+        let data = ARMA::new().simulate_stationary_init(500, vec![0.6], vec![0.8], 0.0, 1.0, 5).unwrap();
+        let mut ar = AutoRegressive::new();
+        ar.fit(&data, 1, ARMethod::OLS);
+
+        let mut ensemble = ForecastEnsemble::new(vec![Box::new(ar.clone()), Box::new(ar.clone())], EnsembleWeights::Equal);
+        assert_eq!(ensemble.forecast(&data, 3), Err(NefeleError::NotFitted));
+        ensemble.fit(&data).unwrap();
+        assert_eq!(ensemble.weights(), [0.5, 0.5]);
+        let single = ar.forecast(&data, 5).unwrap();
+        for (e, s) in ensemble.forecast(&data, 5).unwrap().iter().zip(&single) {
+            assert!((e - s).abs() < 1e-12);
+        }
+
+        // Akaike weights favour the correctly specified model
+        let mut arma = ARMA::new();
+        arma.fit(&data, 1, 1, ARMAMethod::CSS);
+        let mut weighted = ForecastEnsemble::new(vec![Box::new(ar), Box::new(arma)], EnsembleWeights::AIC);
+        weighted.fit(&data).unwrap();
+        let weights = weighted.weights();
+        assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-12 && weights[1] > weights[0], "{:?}", weights);
+    }
+}
@@ -0,0 +1,79 @@
+use nalgebra::{DMatrix, DVector};
+use super::error::NefeleError;
+
+/// Aggregates the forecasts of the bottom-level components into the forecast of their
+/// total, summing them horizon by horizon. The result is as long as the shortest forecast.
+pub fn bottom_up(component_forecasts: &[Vec<f64>]) -> Vec<f64> {
+    let horizon = component_forecasts.iter().map(|f| f.len()).min().unwrap_or(0);
+    (0..horizon)
+        .map(|h| component_forecasts.iter().map(|f| f[h]).sum())
+        .collect()
+}
+
+/// Reconciles the base forecasts of every series of a hierarchy by OLS, projecting them on
+/// the coherent subspace: `y~ = S (S' S)^-1 S' y^`. See [`reconcile_mint`].
+pub fn reconcile_ols(base_forecasts: &[Vec<f64>], summing_matrix: &DMatrix<f64>) -> Result<Vec<Vec<f64>>, NefeleError> {
+    let weights = DMatrix::identity(summing_matrix.nrows(), summing_matrix.nrows());
+    reconcile_mint(base_forecasts, summing_matrix, &weights)
+}
+
+/// Reconciles the base forecasts of every series of a hierarchy, minimum trace (MinT)
+/// style: `y~ = S (S' W^-1 S)^-1 S' W^-1 y^`, so that the reconciled forecasts of the
+/// aggregates are the sums of those of their components.
+///
+/// `base_forecasts` holds one forecast per series, in the order of the rows of the
+/// summing matrix `S` (`n_series x n_bottom`), whose entries are 1 where the bottom-level
+/// series of the column enters the series of the row. `weights` is the `W` matrix, e.g.
+/// the covariance of the base forecast errors or its diagonal; the identity gives OLS.
+pub fn reconcile_mint(base_forecasts: &[Vec<f64>], summing_matrix: &DMatrix<f64>, weights: &DMatrix<f64>) -> Result<Vec<Vec<f64>>, NefeleError> {
+    let n_series = summing_matrix.nrows();
+    if base_forecasts.len() != n_series || weights.shape() != (n_series, n_series) {
+        return Err(NefeleError::InvalidLength(format!(
+            "{} base forecasts and a {}x{} weight matrix for a summing matrix with {} rows",
+            base_forecasts.len(), weights.nrows(), weights.ncols(), n_series
+        )));
+    }
+    let horizon = base_forecasts.iter().map(|f| f.len()).min().unwrap_or(0);
+
+    let singular = || NefeleError::InvalidValue("singular reconciliation system".to_string());
+    let weights_inv = weights.clone().try_inverse().ok_or_else(singular)?;
+    let st_w = summing_matrix.transpose() * weights_inv;
+    let information = (&st_w * summing_matrix).try_inverse().ok_or_else(singular)?;
+
+    // projection mapping the base forecasts to the reconciled ones
+    let projection = summing_matrix * information * st_w;
+
+    let mut reconciled = vec![Vec::with_capacity(horizon); n_series];
+    for h in 0..horizon {
+        let base = DVector::from_iterator(n_series, base_forecasts.iter().map(|f| f[h]));
+        for (series, value) in reconciled.iter_mut().zip((&projection * base).iter()) {
+            series.push(*value);
+        }
+    }
+    Ok(reconciled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconciled_forecasts_are_coherent() {
+        let a = vec![1.0, 2.0, 3.0];
+        let b = vec![10.0, 20.0, 30.0, 40.0];
+        assert_eq!(bottom_up(&[a.clone(), b.clone()]), [11.0, 22.0, 33.0]);
+
+        // total, a, b: the total forecasts disagree with the sums of the components
+        let summing = DMatrix::from_row_slice(3, 2, &[1.0, 1.0, 1.0, 0.0, 0.0, 1.0]);
+        let base = vec![vec![14.0, 20.0, 33.0], a, b];
+        let reconciled = reconcile_ols(&base, &summing).unwrap();
+        for ((total, a), b) in reconciled[0].iter().zip(&reconciled[1]).zip(&reconciled[2]) {
+            assert!((total - a - b).abs() < 1e-10);
+        }
+        // coherent base forecasts are left unchanged
+        assert!((reconciled[0][2] - 33.0).abs() < 1e-10 && (reconciled[1][2] - 3.0).abs() < 1e-10);
+
+        let wrong_weights = DMatrix::identity(2, 2);
+        assert!(matches!(reconcile_mint(&base, &summing, &wrong_weights), Err(NefeleError::InvalidLength(_))));
+    }
+}
@@ -0,0 +1,135 @@
+use nalgebra::{DMatrix, DVector};
+use rand::{rngs::StdRng, SeedableRng};
+use rand_distr::{Binomial, Distribution, Poisson};
+use super::error::NefeleError;
+use super::utils::{forecast, lag_matrix};
+
+/// INAR struct represents an integer-valued autoregressive model for count data,
+/// `x_t = alpha_1 o x_{t-1} + ... + alpha_p o x_{t-p} + e_t`, where `alpha o x` is the
+/// binomial thinning of `x` (the number of successes in `x` trials of probability `alpha`)
+/// and the innovations `e_t` are Poisson with mean `lambda`.
+#[derive(Debug, Clone)]
+pub struct INAR {
+    pub alpha: Vec<f64>,    // Thinning probabilities
+    pub lambda: f64,        // Mean of the Poisson innovations
+    fitted: bool            // Whether `fit` has been called
+}
+
+impl INAR {
+    /// Creates a new INAR struct with default values.
+    pub fn new() -> INAR {
+        INAR {
+            alpha: vec![0.0; 1],
+            lambda: 0.0,
+            fitted: false
+        }
+    }
+
+    /// Stationary mean of the model, `lambda / (1 - sum(alpha))`.
+    pub fn mean(&self) -> f64 {
+        self.lambda / (1.0 - self.alpha.iter().sum::<f64>())
+    }
+
+    /// Fits an INAR(`order`) by conditional least squares: the conditional mean
+    /// `lambda + alpha_1 x_{t-1} + ... + alpha_p x_{t-p}` is regressed on the data by OLS.
+    /// The thinning probabilities are clamped to [0, 1] and `lambda` to non-negative values.
+    /// The data must be non-negative integers.
+    pub fn fit(&mut self, data: &[f64], order: usize) -> Result<(), NefeleError> {
+        if let Some(x) = data.iter().find(|&&x| x < 0.0 || x.fract() != 0.0) {
+            return Err(NefeleError::InvalidValue(format!("counts must be non-negative integers, found {}", x)));
+        }
+        if data.len() <= 2 * order + 1 {
+            return Err(NefeleError::InsufficientData(format!(
+                "{} observations for an INAR({}), at least {} needed", data.len(), order, 2 * order + 2
+            )));
+        }
+
+        // Regressors: a constant followed by the first `order` lags
+        let lags: Vec<usize> = (1..=order).collect();
+        let lagged = lag_matrix(data, &lags);
+        let x = DMatrix::from_fn(lagged.nrows(), order + 1, |i, j| {
+            if j == 0 { 1.0 } else { lagged[(i, j - 1)] }
+        });
+        let y = DVector::from_iterator(data.len() - order, data.iter().skip(order).cloned());
+
+        let coefficients = (x.transpose() * &x)
+            .cholesky()
+            .ok_or_else(|| NefeleError::DegenerateInput("lagged counts are collinear".to_string()))?
+            .solve(&(x.transpose() * &y));
+
+        self.lambda = coefficients[0].max(0.0);
+        self.alpha = coefficients.iter().skip(1).map(|a| a.clamp(0.0, 1.0)).collect();
+        self.fitted = true;
+        Ok(())
+    }
+
+    /// Simulates an INAR process of the given length with thinning probabilities `alpha` and
+    /// Poisson innovations of mean `lambda`, from a generator seeded with `seed`. The
+    /// `alpha.len()` initial values are drawn from the Poisson distribution with the stationary
+    /// mean and discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thinning probability is outside [0, 1] or `lambda` is not positive.
+    pub fn simulate(&self, length: usize, alpha: Vec<f64>, lambda: f64, seed: u64) -> Vec<f64> {
+        let order = alpha.len();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let innovations = Poisson::new(lambda).expect("lambda must be positive");
+        let stationary = Poisson::new(lambda / (1.0 - alpha.iter().sum::<f64>()).max(f64::EPSILON))
+            .expect("lambda must be positive");
+
+        // Initialization
+        let mut output: Vec<u64> = (0..order).map(|_| stationary.sample(&mut rng) as u64).collect();
+
+        // Binomial thinning of the lagged counts plus the Poisson arrivals
+        for t in order..(order + length) {
+            let mut count = innovations.sample(&mut rng) as u64;
+            for j in 0..order {
+                count += Binomial::new(output[t - j - 1], alpha[j])
+                    .expect("thinning probabilities must be in [0, 1]")
+                    .sample(&mut rng);
+            }
+            output.push(count);
+        }
+
+        output.split_off(order).into_iter().map(|x| x as f64).collect()
+    }
+
+    /// Forecasts the conditional means `horizon` steps ahead of the provided data,
+    /// `lambda + alpha_1 x_{t-1} + ... + alpha_p x_{t-p}` iterated on the forecasts. The
+    /// forecasts are not integer; round them for point count predictions.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(forecast(data, self.lambda, &self.alpha, &[], horizon))
+    }
+}
+
+impl Default for INAR {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inar1_mean_and_thinning_are_recovered() {
+        let data = INAR::new().simulate(5000, vec![0.5], 2.0, 1);
+        assert!(data.iter().all(|x| *x >= 0.0 && x.fract() == 0.0));
+        // stationary mean lambda / (1 - alpha) = 4
+        let sample_mean = data.iter().sum::<f64>() / data.len() as f64;
+        assert!((sample_mean - 4.0).abs() < 0.2, "{}", sample_mean);
+
+        let mut model = INAR::new();
+        assert_eq!(model.forecast(&data, 1), Err(NefeleError::NotFitted));
+        model.fit(&data, 1).unwrap();
+        assert!((model.alpha[0] - 0.5).abs() < 0.05, "{:?}", model.alpha);
+        assert!((model.mean() - 4.0).abs() < 0.2, "{}", model.mean());
+
+        assert!(matches!(model.fit(&[1.0, 2.5, 3.0, 1.0], 1), Err(NefeleError::InvalidValue(_))));
+    }
+}
@@ -1,12 +1,63 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 /// Autoregressive module 
+#[cfg(feature = "std")]
 pub mod ar;
 /// Autoregressive Integrated Moving Average module
+#[cfg(feature = "std")]
 pub mod arima;
 /// Autoregressive Moving Average module
+#[cfg(feature = "std")]
 pub mod arma;
 /// Moving Average module
+#[cfg(feature = "std")]
 pub mod ma;
 /// Fractionally Autoregressive Integrated Moving Average module
+#[cfg(feature = "std")]
 pub mod farima;
+/// Sample datasets module
+#[cfg(feature = "std")]
+pub mod datasets;
+/// Classical seasonal decomposition module
+#[cfg(feature = "std")]
+pub mod decompose;
+/// Residual diagnostics module
+#[cfg(feature = "std")]
+pub mod diagnostics;
+/// Error module
+pub mod error;
+/// Exponential smoothing module
+#[cfg(feature = "std")]
+pub mod ets;
+/// Forecaster trait, Model enum and model comparison module
+#[cfg(feature = "std")]
+pub mod forecaster;
+/// Hierarchical forecast reconciliation module
+#[cfg(feature = "std")]
+pub mod hierarchy;
+/// Integer-valued autoregressive module for count data
+#[cfg(feature = "std")]
+pub mod inar;
+/// Pure numeric core, available without `std`
+pub mod numeric;
+/// Model summary module
+#[cfg(feature = "std")]
+pub mod summary;
+/// Utilities module, private: its public API is re-exported below
+#[cfg(feature = "std")]
+mod utils;
 
-mod utils;
\ No newline at end of file
+#[cfg(feature = "std")]
+pub use utils::{
+    acf, acf_at_lags, acf_ex, ar_from_pacf, ar_roots, ar_to_acf, arma_acf, arma_autocovariance,
+    arma_pacf, aic, aicc, bic, companion_matrix, css_objective, cumsum, deseasonalize, diff,
+    diff_into, differenced_length, diffseries, diffseries_full, dominant_frequency, gaussian_loglik,
+    hqic, input_quality, inverse_diff, lag, lag_matrix, lead, log_returns, long_run_variance,
+    moving_block_bootstrap, newey_west_variance, pacf, pacf_from_ar, periodogram,
+    prices_from_returns, process_variance, r_squared, seasonal_dummies, simulate_stationary,
+    spectral_density, spectral_entropy, suggest_differencing, try_acf, try_pacf,
+    variance_ratio_test, winsorize, DifferencingAdvice, InputQuality, Kernel, OptimizerConfig,
+    OrderCriterion, VarianceEstimator,
+};
@@ -1,25 +1,31 @@
 use std::usize;
 
 use super::ar::{ARMethod, AutoRegressive};
-use nalgebra::{DMatrix, DVector};
+use nalgebra::DVector;
 use rand_distr::{Distribution, Normal};
 use finitediff::FiniteDiff;
-use liblbfgs::lbfgs;
-use super::utils::{residuals, mean};
+use super::error::NefeleError;
+use super::summary::Summary;
+use super::utils::{aic, bic, forecast, lag_matrix, ma_exact_loglik, ma_invertible, residuals, warm_up, css_objective, mean, input_quality, OptimizerConfig, OrderCriterion, initial_ma_from_acf};
 
 /// MovingAverage struct represents a moving average model.
 #[derive(Debug, Clone)]
 pub struct MovingAverage {
     pub theta: Vec<f64>,        // MA coefficients
+    pub intercept: f64,         // Intercept, i.e. the mean of the process
     pub sigma_squared: f64,     // Variance of the model
     pub aic: f64,               // AIC (Akaike Information Criterion) value
-    pub bic: f64                // BIC (Bayesian Information Criterion) value
+    pub bic: f64,               // BIC (Bayesian Information Criterion) value
+    n_params: usize,            // Number of free parameters of the last fit
+    pub optimizer: OptimizerConfig, // Settings of the L-BFGS minimizer of the CSS fit
+    fitted: bool                // Whether `fit` has been called
 }
 
 /// MAMethod represents different methods for fitting a moving average model.
 pub enum MAMethod {
     DURBIN,    // Durbin Method
-    CSS        // Conditional Sum of Squares
+    CSS,       // Conditional Sum of Squares
+    ML         // Exact Maximum Likelihood, returning an invertible model
 }
 
 /// MACriterion represents criteria for selecting the order of the moving average model.
@@ -28,23 +34,83 @@ pub enum MACriterion {
     BIC     // Bayesian Information Criterion
 }
 
+impl OrderCriterion for MACriterion {
+    fn evaluate(&self, n: usize, rss: f64, k: usize) -> f64 {
+        match self {
+            MACriterion::AIC => aic(n, rss, k),
+            MACriterion::BIC => bic(n, rss, k),
+        }
+    }
+}
+
 impl MovingAverage {
     /// Creates a new MovingAverage struct with default values.
     pub fn new() -> MovingAverage {
         MovingAverage {
             theta: vec![0.0; 1],      // Initialize with one coefficient
+            intercept: 0.0,
             sigma_squared: 0.0,
             aic: 0.0,
-            bic: 0.0
+            bic: 0.0,
+            n_params: 0,
+            optimizer: OptimizerConfig::default(),
+            fitted: false
         }
     }
 
     /// Prints a summary of the moving average model.
     pub fn summary(&self) {
-        println!(
-            "coefficients: {:?} \nsigma^2: {}",
-            self.theta, self.sigma_squared
-        )
+        println!("{}", self.to_summary())
+    }
+
+    /// Collects the estimates of the moving average model in a Summary struct.
+    pub fn to_summary(&self) -> Summary {
+        Summary {
+            model: format!("MA({})", self.theta.len()),
+            fitted: self.fitted,
+            phi: Vec::new(),
+            phi_std_errors: None,
+            diff: None,
+            theta: self.theta.clone(),
+            intercept: self.intercept,
+            sigma_squared: self.sigma_squared,
+            aic: Some(self.aic),
+            bic: Some(self.bic)
+        }
+    }
+
+    /// Returns the number of free parameters, intercept and innovation variance included.
+    pub fn n_params(&self) -> usize {
+        self.n_params
+    }
+
+    /// Computes the in-sample residuals of the fitted model on the provided data.
+    pub fn residuals(&self, data: &[f64]) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(residuals(data, self.intercept, &[], &self.theta))
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data. Only the last `q` residuals
+    /// enter the forecast, so beyond `q` steps it equals the intercept, i.e. the process mean.
+    pub fn forecast(&self, data: &[f64], horizon: usize) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        Ok(forecast(data, self.intercept, &[], &self.theta, horizon))
+    }
+
+    /// Forecasts `horizon` steps ahead of the provided data around a known `mean` instead
+    /// of the estimated intercept: the data are centered on `mean`, forecast with a zero
+    /// intercept and shifted back. For an MA model the intercept is the mean itself.
+    pub fn forecast_with_mean(&self, data: &[f64], horizon: usize, mean: f64) -> Result<Vec<f64>, NefeleError> {
+        if !self.fitted {
+            return Err(NefeleError::NotFitted);
+        }
+        let centered: Vec<f64> = data.iter().map(|x| x - mean).collect();
+        let forecast = forecast(&centered, 0.0, &[], &self.theta, horizon);
+        Ok(forecast.iter().map(|f| f + mean).collect())
     }
 
     /// Simulates a moving average process.
@@ -85,27 +151,94 @@ impl MovingAverage {
 
     /// Fits the moving average model to the provided data according to the selected method.
     pub fn fit(&mut self, data: &Vec<f64>, order: usize, method: MAMethod) {
+        if self.prepare_fit(data).is_err() {
+            return;
+        }
+        // coefficients, innovation variance and intercept
+        let k = order + 2;
         match method {
             MAMethod::DURBIN => Self::fit_durbin(self, data, order),
-            MAMethod::CSS => Self::fit_css(self, data, order)
+            MAMethod::CSS => Self::fit_css(self, data, order, None),
+            MAMethod::ML => Self::fit_ml(self, data, order)
+        }
+
+        self.set_fit_statistics(data, k);
+    }
+
+    /// Fits the model by CSS starting the optimizer from `initial` instead of the default
+    /// guesses. `initial` holds the intercept followed by the MA coefficients, so it must
+    /// have length `1 + order`. Trying several starting points helps escaping bad local minima.
+    /// The data are checked as in `fit`, and a degenerate series returns its problem as an error.
+    pub fn fit_css_with_initial(&mut self, data: &Vec<f64>, order: usize, initial: Vec<f64>) -> Result<(), NefeleError> {
+        if initial.len() != 1 + order {
+            return Err(NefeleError::InvalidLength(format!(
+                "initial guess has {} values, expected {}", initial.len(), 1 + order
+            )));
         }
+        self.prepare_fit(data)?;
+        Self::fit_css(self, data, order, Some(initial));
+        self.set_fit_statistics(data, order + 2);
+        Ok(())
+    }
+
+    /// Checks the data before a fit. On a degenerate series the model is left unfitted with
+    /// NaN statistics and the problem returned as an error.
+    fn prepare_fit(&mut self, data: &[f64]) -> Result<(), NefeleError> {
+        let quality = input_quality(data);
+        quality.warn();
+        if quality.is_degenerate() {
+            self.fitted = false;
+            self.sigma_squared = f64::NAN;
+            self.aic = f64::NAN;
+            self.bic = f64::NAN;
+            return quality.check();
+        }
+        Ok(())
+    }
 
-        self.sigma_squared = compute_variance(&data, &self.theta);
-        self.aic = compute_aic(data.len(), self.sigma_squared, order);
-        self.bic = compute_bic(data.len(), self.sigma_squared, order);
+    /// Stores the variance and the information criteria of a fit with `k` free parameters,
+    /// from the residuals following the first `q` observations.
+    fn set_fit_statistics(&mut self, data: &Vec<f64>, k: usize) {
+        let (rss, n) = self.residual_sum_of_squares(data);
+        self.sigma_squared = rss / n as f64;
+        self.aic = aic(n, rss, k);
+        self.bic = bic(n, rss, k);
+        self.n_params = k;
+        self.fitted = true;
     }
 
     /// Automatically fits the moving average model by selecting the order based on a criterion.
-    pub fn autofit(&mut self, data: &Vec<f64>, max_order: usize, criterion: MACriterion) {
-        match criterion {
-            MACriterion::AIC => Self::autofit_aic(self, data, max_order),
-            MACriterion::BIC => Self::autofit_bic(self, data, max_order),
+    pub fn autofit(&mut self, data: &Vec<f64>, max_order: usize, criterion: &dyn OrderCriterion) {
+        let mut values: Vec<f64> = Vec::with_capacity(max_order);
+        for order in 1..(max_order + 1) {
+            Self::fit(self, data, order, MAMethod::DURBIN);
+            let (rss, n) = self.residual_sum_of_squares(data);
+            values.push(criterion.evaluate(n, rss, self.n_params));
         }
+
+        let min_order = values
+            .iter()
+            .enumerate()
+            .min_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(index, _)| index + 1) // Adding 1 to get position
+            .unwrap_or(0);
+
+        Self::fit(self, data, min_order, MAMethod::DURBIN);
+    }
+
+    /// Residual sum of squares of the fitted model on `data` and the number of residuals it
+    /// sums, the first `q` being left out as warm-up.
+    fn residual_sum_of_squares(&self, data: &[f64]) -> (f64, usize) {
+        let start = warm_up(0, self.theta.len());
+        let rss = residuals(data, self.intercept, &[], &self.theta).iter().skip(start).map(|e| e * e).sum();
+        (rss, data.len().saturating_sub(start))
     }
 
     fn fit_durbin(&mut self, data: &Vec<f64>, order: usize) {
+        // The process mean is estimated by the sample mean and the recursion run on the deviations
+        self.intercept = mean(data);
+        let data: &Vec<f64> = &data.iter().map(|x| x - self.intercept).collect();
         let m: usize= ((10*order * data.len()) as f64).ln().round() as usize;
-        let n = data.len() - m;
 
         // First step: estimate AR(m)
         let mut ar_m = AutoRegressive::new();
@@ -130,21 +263,16 @@ impl MovingAverage {
             .collect();
 
         // Second step: estimate MA parameters through least squares
-        let mut x = DMatrix::from_element(n - order, order, 0.0);
+        let lags: Vec<usize> = (1..=order).collect();
+        let x = lag_matrix(&eps, &lags);
         let y: Vec<f64> = y_[order..].iter().copied().collect();
         let y = DVector::from_vec(y);
 
-        for row in 0..(n - order) {
-            for col in 0..order {
-                x[(row, col)] = eps[order - 1 + row - col];
-            }
-        }
-
         let result = (x.transpose() * &x).try_inverse().unwrap() * x.transpose() * y;
         self.theta = result.iter().cloned().collect();
     }
 
-    fn fit_css(&mut self, data: &Vec<f64>, ma: usize) {
+    fn fit_css(&mut self, data: &Vec<f64>, ma: usize, initial: Option<Vec<f64>>) {
 
         let total_size = 1 + ma;
 
@@ -158,26 +286,27 @@ impl MovingAverage {
             let phi = &coef[1..ar + 1];
             let theta = &coef[ar + 1..];
 
-            let residuals = residuals(&data, intercept, &phi.to_vec(), &theta.to_vec());
-
-            let mut css: f64 = 0.0;
-            for residual in &residuals {
-                css += residual * residual;
-            }
-            css
+            css_objective(data, intercept, phi, theta)
         };
         let g = |coef: &Vec<f64>| coef.forward_diff(&f);
 
-        // Initial coefficients
-        let mut coef: Vec<f64> = Vec::new();
+        // Initial coefficients, unless supplied by the caller
+        let mut coef: Vec<f64> = match initial {
+            Some(initial) => initial,
+            None => {
+                let mut coef: Vec<f64> = Vec::new();
 
-        // Initial guess for the intercept: First value of data
-        coef.push(mean(&data));
+                // Initial guess for the intercept: First value of data
+                coef.push(mean(&data));
 
-        // Initial guess for the MA coefficients: 1.0
-        if ma > 0 {
-            coef.resize(coef.len() + ma, 1.0);
-        }
+                // Initial guess for the MA coefficients: matched to the ACF of the data
+                if ma > 0 {
+                    coef.extend(initial_ma_from_acf(data, ma));
+                }
+
+                coef
+            }
+        };
 
         let evaluate = |x: &[f64], gx: &mut [f64]| {
             let x = x.to_vec();
@@ -188,8 +317,7 @@ impl MovingAverage {
             Ok(fx)
         };
 
-        let fmin = lbfgs().with_max_iterations(200);
-        if let Err(e) = fmin.minimize(
+        if let Err(e) = self.optimizer.minimize(
             &mut coef, // input variables
             evaluate,  // define how to evaluate function
             |_prng| {
@@ -199,77 +327,129 @@ impl MovingAverage {
             tracing::warn!("{}", e);
         }
         
+        self.intercept = coef[0];
         self.theta = coef[1..].to_vec();
     }
 
-    fn autofit_aic(&mut self, data: &Vec<f64>, max_order: usize) {
-        let mut aic: Vec<f64> = Vec::with_capacity(max_order);
-        for order in 1..(max_order + 1) {
-            Self::fit(self, data, order, MAMethod::DURBIN);
-            aic.push(self.aic);
-        }
+    /// Maximizes the exact Gaussian likelihood, then reflects the non-invertible roots of the
+    /// estimate, which has the same likelihood, inside the unit circle.
+    fn fit_ml(&mut self, data: &Vec<f64>, ma: usize) {
+        let f = |coef: &Vec<f64>| -ma_exact_loglik(data, coef[0], &coef[1..]);
+        let g = |coef: &Vec<f64>| coef.forward_diff(&f);
 
-        let min_order = aic
-            .iter()
-            .enumerate()
-            .min_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(index, _)| index + 1) // Adding 1 to get position
-            .unwrap_or(0);
+        // Initial guess: the mean of the data and white noise
+        let mut coef: Vec<f64> = vec![mean(data)];
+        coef.resize(1 + ma, 0.0);
 
-        Self::fit(self, data, min_order, MAMethod::DURBIN);
-    }
+        let evaluate = |x: &[f64], gx: &mut [f64]| {
+            let x = x.to_vec();
+            let fx = f(&x);
+            let gx_eval = g(&x);
+            // copy values from gx_eval into gx
+            gx[..gx_eval.len()].copy_from_slice(&gx_eval[..]);
+            Ok(fx)
+        };
 
-    fn autofit_bic(&mut self, data: &Vec<f64>, max_order: usize) {
-        let mut bic: Vec<f64> = Vec::with_capacity(max_order);
-        for order in 1..(max_order + 1) {
-            Self::fit(self, data, order, MAMethod::DURBIN);
-            bic.push(self.bic);
+        if let Err(e) = self.optimizer.minimize(&mut coef, evaluate, |_prng| { false }) {
+            tracing::warn!("{}", e);
         }
 
-        let min_order = bic
-            .iter()
-            .enumerate()
-            .min_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(index, _)| index + 1) // Adding 1 to get position
-            .unwrap_or(0);
-
-        Self::fit(self, data, min_order, MAMethod::DURBIN);
+        self.intercept = coef[0];
+        self.theta = ma_invertible(&coef[1..]);
     }
 }
 
-/// Computes the variance of the residuals.
-fn compute_variance(data: &Vec<f64>, coefficients: &Vec<f64>) -> f64 {
-    let q = 0; // coefficients.len();
-    let n = data.len();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use crate::utils::simulate_stationary;
+
+    /// MA(1) path with `theta = 0.5`, unit innovation variance and mean 10.
+    fn ma1(length: usize, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let path = simulate_stationary(length, &[], &[0.5], 0.0, 1.0, &mut rng).unwrap();
+        path.iter().map(|x| x + 10.0).collect()
+    }
+
+    #[test]
+    fn unfitted_model_reports_not_fitted() {
+        let model = MovingAverage::new();
+        let data = vec![1.0, 2.0, 1.5, 0.5, 1.0];
 
-    let mut errors: Vec<f64> = vec![0.0; n];
+        assert_eq!(model.forecast(&data, 3), Err(NefeleError::NotFitted));
+        assert_eq!(model.residuals(&data), Err(NefeleError::NotFitted));
+        assert!(model.to_summary().to_string().contains("not yet fitted"));
+    }
+
+    #[test]
+    fn forecast_beyond_the_order_is_the_mean() {
+        let data = ma1(2000, 1);
+        for (name, method) in [("Durbin", MAMethod::DURBIN), ("CSS", MAMethod::CSS)] {
+            let mut model = MovingAverage::new();
+            model.fit(&data, 1, method);
+            assert!((model.intercept - 10.0).abs() < 0.1, "{}: intercept {}", name, model.intercept);
 
-    // Calculate errors using the MA model
-    for i in coefficients.len()..n {
-        let mut error = data[i];
-        for j in 0..coefficients.len() {
-            error -= coefficients[j] * data[i - j - 1];
+            let forecast = model.forecast(&data, 4).unwrap();
+            assert!(forecast[1..].iter().all(|&f| f == model.intercept), "{}: {:?}", name, forecast);
         }
-        errors[i] = error;
     }
 
-    // Compute the variance of errors
-    let sum_of_squares: f64 = errors.iter().skip(q).map(|&e| e * e).sum();
-    let variance = sum_of_squares / (n - q) as f64;
+    #[test]
+    fn different_starting_points_reach_different_css_optima() {
+        // a short MA(2) with theta_2 near -1 has a second CSS minimum in the non-invertible region
+        let mut rng = StdRng::seed_from_u64(2);
+        let data = simulate_stationary(60, &[], &[0.0, -0.9], 0.0, 1.0, &mut rng).unwrap();
+
+        let mut near = MovingAverage::new();
+        near.fit_css_with_initial(&data, 2, vec![0.0, 0.5, 0.0]).unwrap();
+        let mut far = MovingAverage::new();
+        far.fit_css_with_initial(&data, 2, vec![0.0, -1.5, 0.9]).unwrap();
+
+        let near_css = css_objective(&data, near.intercept, &[], &near.theta);
+        let far_css = css_objective(&data, far.intercept, &[], &far.theta);
+        assert!(near_css.is_finite() && far_css.is_finite());
+        assert!((near.theta[1] - far.theta[1]).abs() > 0.5, "{:?} vs {:?}", near.theta, far.theta);
+        assert!((near_css - far_css).abs() > 1.0, "{} vs {}", near_css, far_css);
+
+        assert!(matches!(
+            MovingAverage::new().fit_css_with_initial(&data, 2, vec![0.0, 0.5]),
+            Err(NefeleError::InvalidLength(_))
+        ));
+    }
 
-    variance
-}
+    #[test]
+    fn ml_returns_an_invertible_estimate_near_the_boundary() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let data = simulate_stationary(150, &[], &[-0.97], 0.0, 1.0, &mut rng).unwrap();
+        let mut model = MovingAverage::new();
+        model.fit(&data, 1, MAMethod::ML);
 
-/// Computes the Akaike Information Criterion.
-fn compute_aic(n: usize, residual_sum_of_squares: f64, p: usize) -> f64 {
-    let k = p; // Number of parameters (p autoregressive parameters)
-    let aic = 2.0 * k as f64 + n as f64 * (residual_sum_of_squares / n as f64).ln();
-    aic
-}
+        assert!(model.theta[0].abs() <= 1.0, "{:?}", model.theta);
+        assert!(model.theta[0] < -0.8, "{:?}", model.theta);
 
-/// Computes the Bayesian Information Criterion.
-fn compute_bic(n: usize, residual_sum_of_squares: f64, p: usize) -> f64 {
-    let k = p; // Number of parameters (p autoregressive parameters)
-    let bic = n as f64 * (residual_sum_of_squares / n as f64).ln() + k as f64 * (n as f64).ln();
-    bic
+        // a non-invertible MA(1) is reflected to the inverse of its coefficient
+        assert!((ma_invertible(&[2.0])[0] - 0.5).abs() < 1e-10);
+        assert!((ma_exact_loglik(&data, 0.0, &[2.0]) - ma_exact_loglik(&data, 0.0, &[0.5])).abs() < 1e-8);
+    }
+
+    #[test]
+    fn acf_matched_start_is_close_to_the_css_optimum() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let data = simulate_stationary(1000, &[], &[0.6, 0.3], 0.0, 1.0, &mut rng).unwrap();
+
+        let initial = initial_ma_from_acf(&data, 2);
+        assert!((initial[0] - 0.6).abs() < 0.15 && (initial[1] - 0.3).abs() < 0.15, "{:?}", initial);
+
+        let mut matched = MovingAverage::new();
+        matched.fit(&data, 2, MAMethod::CSS);
+        let mut flat = MovingAverage::new();
+        flat.fit_css_with_initial(&data, 2, vec![mean(&data), 1.0, 1.0]).unwrap();
+        assert!((matched.theta[0] - 0.6).abs() < 0.1 && (matched.theta[1] - 0.3).abs() < 0.1, "{:?}", matched.theta);
+        let matched_css = css_objective(&data, matched.intercept, &[], &matched.theta);
+        let flat_css = css_objective(&data, flat.intercept, &[], &flat.theta);
+        assert!(matched_css <= flat_css + 1e-6, "{} vs {}", matched_css, flat_css);
+
+        assert_eq!(initial_ma_from_acf(&[2.0; 30], 2), [0.0, 0.0]);
+    }
 }
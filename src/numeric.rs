@@ -0,0 +1,428 @@
+use core::cmp;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use super::error::NefeleError;
+
+/// Number of warm-up observations of an ARMA(`p`, `q`) residual recursion, `max(p, q)`.
+pub fn warm_up(p: usize, q: usize) -> usize {
+    cmp::max(p, q)
+}
+
+/// Computes the residuals of an ARMA model on `x`, aligned with it: `residuals[t]` is the
+/// one-step-ahead error at `x[t]`. The recursion is conditional on the first `max(p, q)`
+/// observations, the first time both the `p` lagged observations and `q` lagged residuals
+/// exist, and their residuals are set to 0. With an invertible MA part the residuals
+/// converge to the true innovations as `t` grows.
+pub fn residuals(
+    x: &[f64],
+    intercept: f64,
+    phi: &[f64],
+    theta: &[f64],
+) -> Vec<f64> {
+    let start = cmp::min(warm_up(phi.len(), theta.len()), x.len());
+
+    let mut residuals: Vec<f64> = vec![0.0; start];
+    for t in start..x.len() {
+        let mut xt: f64 = intercept;
+        for j in 0..phi.len() {
+            xt += phi[j] * x[t - j - 1];
+        }
+        for j in 0..theta.len() {
+            xt += theta[j] * residuals[t - j - 1];
+        }
+        residuals.push(x[t] - xt);
+    }
+
+    residuals
+}
+
+pub fn diff(x: &[f64], d: usize) -> Vec<f64> {
+    let mut y: Vec<f64> = Vec::with_capacity(x.len());
+    diff_into(x, d, &mut y);
+    y
+}
+
+/// Number of observations left after differencing `n` observations `d` times, `n - d`,
+/// or none when `d >= n`. The differenced series starts at the `d`-th observation: its
+/// value `i` is aligned with observation `i + d`.
+pub fn differenced_length(n: usize, d: usize) -> usize {
+    n.saturating_sub(d)
+}
+
+/// Differences `src` `d` times into `dst`, reusing its allocation: `dst` is cleared and
+/// holds `differenced_length(src.len(), d)` values afterwards.
+pub fn diff_into(src: &[f64], d: usize, dst: &mut Vec<f64>) {
+    dst.clear();
+    if differenced_length(src.len(), d) == 0 {
+        return;
+    }
+    dst.extend_from_slice(src);
+    let len = dst.len();
+    for s in 0..d {
+        for i in 1..len - s {
+            // we iterate backwards through the vector to avoid cloning
+            dst[len - i] -= dst[len - i - 1];
+        }
+    }
+    dst.drain(0..d);
+}
+
+/// Integrates `x` `d` times, starting from `d` zero initial values, with the cumulative
+/// sums computed in place.
+pub fn inverse_diff(x: &[f64], d: usize) -> Vec<f64> {
+    let mut cum: Vec<f64> = Vec::with_capacity(x.len() + d);
+    cum.resize(d, 0.0);
+    cum.extend_from_slice(x);
+
+    if d > 0 && cum.len() < 2 {
+        return vec![0.0];
+    }
+    for _ in 0..d {
+        for i in 1..cum.len() {
+            cum[i] += cum[i - 1];
+        }
+    }
+    cum
+}
+
+pub fn cumsum(x: Vec<f64>) -> Vec<f64> {
+    let mut y: Vec<f64> = Vec::new();
+    if x.len() < 2 {
+        y.push(From::from(0));
+        return y;
+    }
+    y.push(x[0]);
+    for i in 1..x.len() {
+        y.push(y[i - 1] + x[i]);
+    }
+    y
+}
+
+/// Computes the reflection (PARCOR) coefficients of the Levinson-Durbin recursion on the
+/// sample autocorrelations of `x`, one for each order from 1 to `order`. They coincide with
+/// the partial autocorrelations.
+pub fn reflection_coefficients(x: &[f64], order: usize) -> Vec<f64> {
+    let rho = acf(x, Some(order), false);
+    let cov0 = acf(x, Some(0), true)[0];
+    ar_dl_rho_cov(&rho, cov0, Some(order)).2
+}
+
+/// Computes the partial autocorrelations of `x` up to `max_lag`, returning an
+/// `InsufficientData` error when `max_lag` is not below `x.len() / 2`, past which the
+/// estimates are unreliable. [`pacf`] instead silently clamps `max_lag` to `x.len() - 1`.
+pub fn try_pacf(x: &[f64], max_lag: usize) -> Result<Vec<f64>, NefeleError> {
+    if max_lag >= x.len() / 2 {
+        return Err(NefeleError::InsufficientData(format!(
+            "{} lags requested on {} observations, at most {} allowed",
+            max_lag, x.len(), (x.len() / 2).saturating_sub(1)
+        )));
+    }
+    let rho = try_acf(x, Some(max_lag), false)?;
+    let cov0 = acf(x, Some(0), true)[0];
+    Ok(pacf_rho_cov0(&rho, cov0, Some(max_lag)))
+}
+
+pub fn pacf(
+    x: &[f64],
+    max_lag: Option<usize>,
+) -> Vec<f64> {
+    // get autocorrelations
+    let rho = acf(x, max_lag, false);
+    let cov0 = acf(x, Some(0), true)[0];
+    pacf_rho_cov0(&rho, cov0, max_lag)
+}
+
+/// Computes the autocorrelations (or autocovariances) of `x` up to `max_lag`, as [`try_acf`],
+/// but never fails: the autocorrelations of a constant series are NaN and an empty series
+/// gives an empty vector.
+pub fn acf(
+    x: &[f64],
+    max_lag: Option<usize>,
+    covariance: bool,
+) -> Vec<f64> {
+    acf_ex(x, max_lag, covariance, true)
+}
+
+/// Computes the autocorrelations (or autocovariances) of `x` up to `max_lag`, capped at
+/// `x.len() - 1`. Returns `DegenerateInput` if `x` is empty, or if correlations are requested
+/// on a constant series, whose zero variance leaves them undefined.
+pub fn try_acf(
+    x: &[f64],
+    max_lag: Option<usize>,
+    covariance: bool,
+) -> Result<Vec<f64>, NefeleError> {
+    if x.is_empty() {
+        return Err(NefeleError::DegenerateInput("empty series".to_string()));
+    }
+    if !covariance && x.iter().all(|&xi| xi == x[0]) {
+        return Err(NefeleError::DegenerateInput(
+            "constant series has no autocorrelations".to_string(),
+        ));
+    }
+    Ok(acf_ex(x, max_lag, covariance, true))
+}
+
+/// Computes the autocorrelations (or autocovariances) of `x`, removing the sample
+/// mean only if `demean` is true. Use `demean = false` on series which are zero-mean
+/// by construction, such as residuals. An empty series gives an empty vector, as in [`acf`].
+pub fn acf_ex(
+    x: &[f64],
+    max_lag: Option<usize>,
+    covariance: bool,
+    demean: bool,
+) -> Vec<f64> {
+    if x.is_empty() {
+        return Vec::new();
+    }
+    let max_lag = match max_lag {
+        // if upper bound for max_lag is n-1
+        Some(max_lag) => cmp::min(max_lag, x.len() - 1),
+        None => x.len() - 1,
+    };
+    let m = max_lag + 1;
+
+    let len_x_usize = x.len();
+    let len_x: f64 = From::from(len_x_usize as u32);
+    let sum: f64 = From::from(0.0);
+
+    let sum_x: f64 = x.iter().fold(sum, |sum, &xi| sum + xi);
+    let mean_x: f64 = if demean { sum_x / len_x } else { 0.0 };
+
+    //let mut y: Vec<f64> = Vec::with_capacity(max_lag);
+    let mut y: Vec<f64> = vec![From::from(0.0); m];
+
+    for t in 0..m {
+        y[t] = autocovariance_at(x, mean_x, t);
+        // we need y[0] to calculate the correlations, so we set it to 1.0 at the end
+        if !covariance && t > 0 {
+            y[t] = y[t] / y[0];
+        }
+    }
+    if !covariance {
+        y[0] = From::from(1.0);
+    }
+    y
+}
+
+/// Computes the autocorrelations (or autocovariances) of `x` only at the given `lags`,
+/// e.g. `[1, 12, 24]` for the seasonal lags of a monthly series. Lags not shorter than
+/// the series have no pairs and give 0.
+pub fn acf_at_lags(
+    x: &[f64],
+    lags: &[usize],
+    covariance: bool,
+) -> Vec<f64> {
+    let mean_x = x.iter().sum::<f64>() / x.len() as f64;
+    let cov0 = autocovariance_at(x, mean_x, 0);
+
+    lags.iter()
+        .map(|&lag| {
+            let cov = autocovariance_at(x, mean_x, lag);
+            if covariance { cov } else { cov / cov0 }
+        })
+        .collect()
+}
+
+/// Sample autocovariance of `x` around `mean_x` at a single `lag`, normalized by `x.len()`.
+pub(crate) fn autocovariance_at(x: &[f64], mean_x: f64, lag: usize) -> f64 {
+    let len_x = x.len() as f64;
+    (0..x.len().saturating_sub(lag))
+        .fold(0.0, |acc, i| acc + (x[i] - mean_x) * (x[i + lag] - mean_x) / len_x)
+}
+
+pub(crate) fn pacf_rho_cov0(
+    rho: &Vec<f64>,
+    cov0: f64,
+    max_lag: Option<usize>,
+) -> Vec<f64> {
+    let max_lag = match max_lag {
+        // if upper bound for max_lag is n-1
+        Some(max_lag) => cmp::min(max_lag, rho.len() - 1),
+        None => rho.len() - 1,
+    };
+    let m = max_lag + 1;
+
+    // build output vector
+    let mut y: Vec<f64> = Vec::new();
+
+    // the partial correlations are the reflection coefficients of the recursion up to max_lag
+    let (_coef, _var, reflections) = ar_dl_rho_cov(rho, cov0, Some(m - 1));
+    y.extend(reflections);
+    y
+}
+
+pub(crate) fn ar_dl_rho_cov(
+    rho: &Vec<f64>,
+    cov0: f64,
+    order: Option<usize>,
+) -> (Vec<f64>, f64, Vec<f64>) {
+    let (mut phi, var) = ar_dl_path(rho, cov0, order);
+    let order = phi.len() - 1;
+
+    // reflection coefficients phi_ii of each intermediate order
+    let reflections: Vec<f64> = (1..order + 1).map(|i| phi[i][i - 1]).collect();
+
+    (phi.swap_remove(order), var[order], reflections)
+}
+
+/// Runs the Levinson-Durbin recursion on the autocorrelations `rho` (from lag 0 on) of a
+/// process of variance `cov0` up to `order`, capped at `rho.len() - 1`, and returns the
+/// coefficients and the innovation variance of every intermediate order, from 0 on (the
+/// order 0 entry holds a single zero coefficient).
+pub(crate) fn ar_dl_path(
+    rho: &[f64],
+    cov0: f64,
+    order: Option<usize>,
+) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let order = match order {
+        Some(order) => cmp::min(order, rho.len() - 1),
+        None => rho.len() - 1,
+    };
+
+    // we need zero values more than once, so we'll use this helper var
+    let zero = 0.0;
+    let one = 1.0;
+
+    // these vectors will hold the parameter values
+    let mut phi: Vec<Vec<f64>> = vec![Vec::new(); order + 1];
+    let mut var: Vec<f64> = Vec::new();
+
+    // initialize zero-order estimates
+    phi[0].push(zero);
+    var.push(cov0);
+
+    for i in 1..order + 1 {
+        // first allocate values for the phi vector so we can use phi[i][i-1]
+        for _ in 0..i {
+            phi[i].push(zero);
+        }
+
+        // estimate phi_ii, which is stored as phi[i][i-1]
+        // phi_i,i = rho(i) - sum_{k=1}^{n-1}(phi_{n-1,k} * rho(n-k) /
+        //  (1 - sum_{k=1}^{n-1}(phi_{n-1,k} * rho(k))
+
+        let mut num_sum = zero; // numerator sum
+        let mut den_sum = one; // denominator sum
+
+        for k in 1..i {
+            let p = phi[i - 1][k - 1];
+            num_sum += p * rho[i - k];
+            den_sum += -p * rho[k];
+        }
+
+        let phi_ii = (rho[i] - num_sum) / den_sum;
+        phi[i][i - 1] = phi_ii;
+
+        var.push(var[i - 1] * (one - phi_ii * phi_ii));
+
+        for k in 1..i {
+            phi[i][k - 1] = phi[i - 1][k - 1] - phi[i][i - 1] * phi[i - 1][i - k - 1];
+        }
+    }
+
+    (phi, var)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acf_without_demeaning_matches_on_zero_mean_data() {
+        // whole periods of a sinusoid have zero sample mean
+        let x: Vec<f64> = (0..64).map(|t| (core::f64::consts::PI * t as f64 / 4.0).sin()).collect();
+
+        let demeaned = acf_ex(&x, Some(10), false, true);
+        let raw = acf_ex(&x, Some(10), false, false);
+        for (a, b) in demeaned.iter().zip(&raw) {
+            assert!((a - b).abs() < 1e-12, "{} vs {}", a, b);
+        }
+        assert_eq!(acf(&x, Some(10), false), demeaned);
+
+        for demean in [true, false] {
+            assert!(acf_ex(&[], Some(3), true, demean).is_empty());
+        }
+    }
+
+    #[test]
+    fn acf_at_lags_matches_the_full_acf() {
+        let x: Vec<f64> = (0..48).map(|t| ((t * 7) % 12) as f64 + 0.5 * ((t * 5) % 3) as f64).collect();
+
+        for covariance in [false, true] {
+            let full = acf(&x, Some(24), covariance);
+            assert_eq!(acf_at_lags(&x, &[0, 1, 2], covariance), full[..3]);
+
+            let seasonal = acf_at_lags(&x, &[12, 24], covariance);
+            assert!((seasonal[0] - full[12]).abs() < 1e-12 && (seasonal[1] - full[24]).abs() < 1e-12);
+        }
+        assert_eq!(acf_at_lags(&x, &[48, 100], true), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn try_pacf_rejects_too_many_lags() {
+        let x: Vec<f64> = (0..20).map(|t| ((t * 7) % 5) as f64).collect();
+
+        assert!(matches!(try_pacf(&x, 10), Err(NefeleError::InsufficientData(_))));
+        assert!(matches!(try_pacf(&x, 50), Err(NefeleError::InsufficientData(_))));
+        assert_eq!(pacf(&x, Some(50)).len(), pacf(&x, Some(19)).len());
+
+        assert_eq!(try_pacf(&x, 9).unwrap(), pacf(&x, Some(9)));
+    }
+
+    #[test]
+    fn diff_into_reuses_the_buffer() {
+        let x = [1.0, 4.0, 9.0, 16.0, 25.0, 36.0];
+        let mut buffer = vec![7.0; 20];
+
+        for d in 0..=7 {
+            diff_into(&x, d, &mut buffer);
+            assert_eq!(buffer, diff(&x, d), "d = {}", d);
+            assert_eq!(buffer.len(), differenced_length(x.len(), d));
+        }
+        assert_eq!(diff(&x, 2), [2.0, 2.0, 2.0, 2.0]);
+
+        let y = [1.0, -2.0, 0.5, 3.0];
+        for d in 1..=3 {
+            let integrated = inverse_diff(&y, d);
+            assert_eq!(integrated.len(), y.len() + d);
+            assert_eq!(diff(&integrated, d), y);
+        }
+    }
+
+    #[test]
+    fn ma1_residuals_converge_to_the_innovations() {
+        // x_t = 2 + e_t + 0.5 e_{t-1} with deterministic innovations
+        let shocks: Vec<f64> = (0..101).map(|t| ((t * 37) % 11) as f64 / 5.0 - 1.0).collect();
+        let x: Vec<f64> = (1..101).map(|t| 2.0 + shocks[t] + 0.5 * shocks[t - 1]).collect();
+
+        let e = residuals(&x, 2.0, &[], &[0.5]);
+        assert_eq!(e.len(), x.len());
+        assert_eq!(e[0], 0.0);
+        // the error of the zero start halves at every step
+        for t in 1..x.len() {
+            let error = (e[t] - shocks[t + 1]).abs();
+            assert!(error <= 0.5f64.powi(t as i32) * 2.0 + 1e-12, "t = {}: {}", t, error);
+        }
+        assert!((e[99] - shocks[100]).abs() < 1e-12);
+
+        // the ARMA(2, 1) recursion starts once both the lags exist
+        let e = residuals(&x, 0.0, &[0.3, 0.1], &[0.5]);
+        assert_eq!(&e[..2], &[0.0, 0.0]);
+        assert!((e[2] - (x[2] - 0.3 * x[1] - 0.1 * x[0])).abs() < 1e-12);
+    }
+
+    #[test]
+    fn try_acf_rejects_degenerate_series() {
+        assert!(matches!(try_acf(&[], Some(3), false), Err(NefeleError::DegenerateInput(_))));
+        assert!(matches!(try_acf(&[2.0; 10], Some(3), false), Err(NefeleError::DegenerateInput(_))));
+        // the autocovariances of a constant series are zero, not undefined
+        assert_eq!(try_acf(&[2.0; 10], Some(3), true).unwrap(), [0.0; 4]);
+        assert_eq!(try_acf(&[5.0], None, true).unwrap(), [0.0]);
+
+        // the lenient version never panics
+        assert!(acf(&[], Some(3), false).is_empty());
+        assert!(acf(&[2.0; 10], Some(3), false)[1].is_nan());
+    }
+}
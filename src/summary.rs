@@ -0,0 +1,222 @@
+use std::fmt;
+use super::diagnostics::ResidualReport;
+use super::utils::chi_squared_sf;
+#[cfg(feature = "serde")]
+use super::error::NefeleError;
+
+/// Summary struct collects the estimates of a model for printing.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Summary {
+    pub model: String,          // Model name
+    pub fitted: bool,           // Whether `fit` has been called
+    pub phi: Vec<f64>,          // AR coefficients
+    pub phi_std_errors: Option<Vec<f64>>, // Standard errors of the AR coefficients, if the fit method provides them
+    pub diff: Option<f64>,      // Differencing order, if any
+    pub theta: Vec<f64>,        // MA coefficients
+    pub intercept: f64,         // Intercept
+    pub sigma_squared: f64,     // Variance of the model
+    pub aic: Option<f64>,       // AIC (Akaike Information Criterion) value, if any
+    pub bic: Option<f64>        // BIC (Bayesian Information Criterion) value, if any
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.fitted {
+            return write!(f, "{}: model not yet fitted", self.model);
+        }
+
+        writeln!(f, "{}", self.model)?;
+        writeln!(f, "intercept: {}", self.intercept)?;
+        if !self.phi.is_empty() {
+            match &self.phi_std_errors {
+                Some(std_errors) => {
+                    writeln!(f, "{}", Starred("AR", &self.phi, std_errors))?;
+                    writeln!(f, "signif. codes: *** 0.001, ** 0.01, * 0.05, . 0.1")?;
+                }
+                None => {
+                    writeln!(f, "{}", Lagged("AR", &self.phi))?;
+                    writeln!(f, "(no standard errors for this fit method, significance not marked)")?;
+                }
+            }
+        }
+        if let Some(d) = self.diff {
+            writeln!(f, "d: {}", d)?;
+        }
+        if !self.theta.is_empty() {
+            writeln!(f, "{}", Lagged("MA", &self.theta))?;
+        }
+        write!(f, "sigma^2: {}", self.sigma_squared)?;
+        if let Some(aic) = self.aic {
+            write!(f, "\naic: {}", aic)?;
+        }
+        if let Some(bic) = self.bic {
+            write!(f, "\nbic: {}", bic)?;
+        }
+        Ok(())
+    }
+}
+
+/// FitResult struct is a self-contained record of a fitted model: its estimates together with
+/// the adequacy tests of its residuals.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FitResult {
+    pub model: String,                  // Model name
+    pub ar_order: usize,                // Number of AR coefficients
+    pub diff: Option<f64>,              // Differencing order, if any
+    pub ma_order: usize,                // Number of MA coefficients
+    pub phi: Vec<f64>,                  // AR coefficients
+    pub theta: Vec<f64>,                // MA coefficients
+    pub intercept: f64,                 // Intercept
+    pub sigma_squared: f64,             // Variance of the model
+    pub aic: Option<f64>,               // AIC (Akaike Information Criterion) value, if any
+    pub bic: Option<f64>,               // BIC (Bayesian Information Criterion) value, if any
+    pub diagnostics: ResidualReport     // Residual adequacy tests
+}
+
+impl FitResult {
+    /// Creates a FitResult from the summary of a fitted model and the diagnostics of its residuals.
+    pub fn new(summary: Summary, diagnostics: ResidualReport) -> FitResult {
+        FitResult {
+            model: summary.model,
+            ar_order: summary.phi.len(),
+            diff: summary.diff,
+            ma_order: summary.theta.len(),
+            phi: summary.phi,
+            theta: summary.theta,
+            intercept: summary.intercept,
+            sigma_squared: summary.sigma_squared,
+            aic: summary.aic,
+            bic: summary.bic,
+            diagnostics
+        }
+    }
+
+    /// Serializes the fit result to pretty-printed JSON. Non-finite values are written as `null`.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a fit result always serializes to JSON")
+    }
+
+    /// Deserializes a fit result from JSON, as written by `to_json`.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<FitResult, NefeleError> {
+        serde_json::from_str(json).map_err(|e| NefeleError::InvalidValue(e.to_string()))
+    }
+}
+
+/// Lagged displays a coefficient vector with the lag of each coefficient, e.g. `AR{1}: 0.52, AR{2}: -0.13`.
+pub struct Lagged<'a>(pub &'a str, pub &'a [f64]);
+
+impl fmt::Display for Lagged<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (lag, coefficient) in self.1.iter().enumerate() {
+            if lag > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}{{{}}}: {}", self.0, lag + 1, coefficient)?;
+        }
+        Ok(())
+    }
+}
+
+/// Starred displays a coefficient vector like [`Lagged`], marking each coefficient with the
+/// conventional significance stars of its two-sided p-value, from the normal approximation of
+/// its t-statistic, e.g. `AR{1}: 0.52***, AR{2}: -0.13`.
+pub struct Starred<'a>(pub &'a str, pub &'a [f64], pub &'a [f64]);
+
+impl fmt::Display for Starred<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (lag, (coefficient, std_error)) in self.1.iter().zip(self.2).enumerate() {
+            if lag > 0 {
+                write!(f, ", ")?;
+            }
+            let t_stat = coefficient / std_error;
+            let p_value = chi_squared_sf(t_stat * t_stat, 1);
+            write!(f, "{}{{{}}}: {}{}", self.0, lag + 1, coefficient, significance_stars(p_value))?;
+        }
+        Ok(())
+    }
+}
+
+/// Significance stars of a p-value: `***` below 0.001, `**` below 0.01, `*` below 0.05 and
+/// `.` below 0.1. NaN p-values, e.g. from infinite standard errors, get no mark.
+pub fn significance_stars(p_value: f64) -> &'static str {
+    match p_value {
+        p if p < 0.001 => "***",
+        p if p < 0.01 => "**",
+        p if p < 0.05 => "*",
+        p if p < 0.1 => ".",
+        _ => ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fit_result_round_trips_through_json() {
+        use crate::arma::{ARMAMethod, ARMA};
+        use crate::diagnostics::residual_diagnostics;
+
+        let data = ARMA::new().simulate_stationary_init(300, vec![0.6], vec![0.3], 0.0, 1.0, 1).unwrap();
+        let mut model = ARMA::new();
+        model.fit(&data, 1, 1, ARMAMethod::CSS);
+        let report = residual_diagnostics(&model.residuals(&data).unwrap()[1..], 2, 10);
+        let result = FitResult::new(model.to_summary(), report);
+
+        let json = result.to_json();
+        for key in ["model", "ar_order", "diff", "ma_order", "phi", "theta", "intercept", "sigma_squared",
+                    "aic", "bic", "diagnostics", "ljung_box", "durbin_watson", "jarque_bera", "arch_lm"] {
+            assert!(json.contains(&format!("\"{}\"", key)), "missing {}", key);
+        }
+
+        let restored = FitResult::from_json(&json).unwrap();
+        assert_eq!(restored.phi, result.phi);
+        assert_eq!(restored.diagnostics.ljung_box, result.diagnostics.ljung_box);
+        assert_eq!(restored.to_json(), json);
+
+        assert!(matches!(FitResult::from_json("{}"), Err(NefeleError::InvalidValue(_))));
+    }
+
+    #[test]
+    fn significant_coefficients_are_starred() {
+        use crate::ar::{ARMethod, AutoRegressive};
+
+        assert_eq!(Starred("AR", &[0.5, 0.01], &[0.05, 0.1]).to_string(), "AR{1}: 0.5***, AR{2}: 0.01");
+        assert_eq!(significance_stars(0.03), "*");
+
+        // an AR(1) fitted with a second, spurious lag
+        let data = AutoRegressive::new().simulate_stationary_init(500, vec![0.6], 0.0, 1.0, 2).unwrap();
+        let mut model = AutoRegressive::new();
+        model.fit(&data, 2, ARMethod::OLS);
+        let [first, second] = model.phi[..] else { panic!("{:?}", model.phi) };
+        let summary = model.to_summary().to_string();
+        assert!(summary.contains(&format!("AR{{1}}: {}***", first)), "{}", summary);
+        assert!(summary.contains(&format!("AR{{2}}: {}\n", second)), "{}", summary);
+        assert!(summary.contains("signif. codes"));
+
+        model.fit(&data, 2, ARMethod::YWALKER);
+        assert!(model.to_summary().to_string().contains("significance not marked"));
+    }
+
+    #[test]
+    fn arma_summary_lists_the_coefficients_by_lag() {
+        use crate::arma::{ARMAMethod, ARMA};
+
+        let data = ARMA::new().simulate_stationary_init(400, vec![0.5, -0.2], vec![0.4], 0.0, 1.0, 3).unwrap();
+        let mut model = ARMA::new();
+        model.fit(&data, 2, 1, ARMAMethod::CSS);
+        let (phi, theta) = (model.phi.clone(), model.theta.clone());
+        let summary = model.to_summary().to_string();
+        let lines: Vec<&str> = summary.lines().collect();
+
+        assert!(lines.contains(&format!("AR{{1}}: {}, AR{{2}}: {}", phi[0], phi[1]).as_str()), "{}", summary);
+        assert!(lines.contains(&format!("MA{{1}}: {}", theta[0]).as_str()), "{}", summary);
+        assert_eq!(Lagged("MA", &[0.25, -0.5]).to_string(), "MA{1}: 0.25, MA{2}: -0.5");
+        assert_eq!(Lagged("AR", &[]).to_string(), "");
+    }
+}
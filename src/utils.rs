@@ -1,28 +1,234 @@
 use std::cmp;
+use super::error::NefeleError;
+pub use super::numeric::{
+    acf, acf_at_lags, acf_ex, cumsum, diff, diff_into, differenced_length, inverse_diff, pacf,
+    residuals, try_acf, try_pacf, warm_up,
+};
+pub(crate) use super::numeric::{ar_dl_path, ar_dl_rho_cov, autocovariance_at, pacf_rho_cov0};
 extern crate nalgebra as na;
+use na::{Complex, DMatrix};
+use liblbfgs::{lbfgs, Progress, Report};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
 use unit_root::prelude::distrib::{AlphaLevel, Regression};
 use unit_root::prelude::nalgebra::DVector;
 use unit_root::prelude::*;
 
-/// Perform Augmented Dickey-Fuller test
-pub fn adf(y: Vec<f64>, lag: usize, regression: Regression) -> (f64, f64) {
+/// Performs the Augmented Dickey-Fuller test, returning its statistic and 1% critical value. Returns `InsufficientData` unless the regression on `lag` lagged
+/// differences has more observations, `y.len() - lag - 1`, than regressors, and
+/// `DegenerateInput` if it cannot be solved, e.g. on a constant series.
+pub fn adf(y: &[f64], lag: usize, regression: Regression) -> Result<(f64, f64), NefeleError> {
+    let regressors = lag + 1 + match regression {
+        Regression::NoConstantNoTrend => 0,
+        Regression::Constant => 1,
+        Regression::ConstantAndTrend => 2,
+    };
+    if y.len() <= lag + 1 + regressors {
+        return Err(NefeleError::InsufficientData(format!(
+            "{} observations for an ADF regression on {} lags, more than {} needed", y.len(), lag, lag + 1 + regressors
+        )));
+    }
     // Convert y to DVector<f64>
     let y_dvector = DVector::from_iterator(y.len(), y.iter().cloned());
 
     // compute the test statistic
-    let report = tools::adf_test(&y_dvector, lag, regression).unwrap();
+    let report = tools::adf_test(&y_dvector, lag, regression)
+        .map_err(|e| NefeleError::DegenerateInput(e.to_string()))?;
 
     // critical values for the model with a constant but no trend:
     let critical_value: f64 =
         distrib::dickeyfuller::get_critical_value(regression, report.size, AlphaLevel::OnePercent)
-            .unwrap();
+            .map_err(|e| NefeleError::DegenerateInput(e.to_string()))?;
 
     // comparison
     let stat = report.test_statistic;
-    (stat, critical_value)
+    Ok((stat, critical_value))
+}
+
+/// DifferencingAdvice struct holds a suggested differencing order together with the
+/// evidence it was chosen on.
+#[derive(Debug, Clone)]
+pub struct DifferencingAdvice {
+    pub d: usize,                       // Suggested differencing order
+    pub stages: Vec<(usize, f64, f64)>, // ADF statistic and 1% critical value of each differencing order tested
+    pub rationale: String               // Human-readable account of the choice
+}
+
+/// Suggests how many times to difference the data, testing the series differenced
+/// `0, 1, ..., max_d` times in turn with the ADF test (constant, `floor(12 (n / 100)^(1/4))`
+/// lags as in Schwert's rule, capped at the `(n - 4) / 2` the regression supports, 1% level)
+/// and stopping at the first order where the unit root is rejected. If none rejects, `max_d`
+/// is suggested and the rationale says so. Only the ADF test is run: a rejection is not
+/// confirmed by a stationarity test such as KPSS, and the rationale states it.
+///
+/// Returns `InsufficientData` if a differenced series tested has fewer than 4 observations,
+/// and `DegenerateInput` if its ADF regression cannot be solved.
+pub fn suggest_differencing(data: &[f64], max_d: usize) -> Result<DifferencingAdvice, NefeleError> {
+    let mut stages = Vec::new();
+    let mut rationale = String::from("Based on the ADF test only, not confirmed by a stationarity test. ");
+
+    for d in 0..=max_d {
+        let series = diff(data, d);
+        if series.len() < 4 {
+            return Err(NefeleError::InsufficientData(format!(
+                "{} observations leave {} after differencing {} times, at least 4 needed for the ADF test",
+                data.len(), series.len(), d
+            )));
+        }
+        let lag = ((12.0 * (series.len() as f64 / 100.0).powf(0.25)).floor() as usize).min((series.len() - 4) / 2);
+        let (stat, critical) = adf(&series, lag, Regression::Constant)?;
+        stages.push((d, stat, critical));
+
+        if stat < critical {
+            rationale.push_str(&format!(
+                "d = {}: ADF statistic {:.3} below the 1% critical value {:.3}, unit root rejected, so d = {} is suggested.",
+                d, stat, critical, d
+            ));
+            return Ok(DifferencingAdvice { d, stages, rationale });
+        }
+        rationale.push_str(&format!(
+            "d = {}: ADF statistic {:.3} above the 1% critical value {:.3}, unit root not rejected. ",
+            d, stat, critical
+        ));
+    }
+
+    rationale.push_str(&format!("No order up to {} rejects a unit root, so d = {} is suggested.", max_d, max_d));
+    Ok(DifferencingAdvice { d: max_d, stages, rationale })
+}
+
+/// InputQuality struct flags the common problems of a series which make a fit degenerate.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct InputQuality {
+    pub non_finite: bool,   // Holds NaN or infinite values
+    pub too_short: bool,    // Fewer than three observations
+    pub constant: bool,     // All the values are equal
+    pub low_variance: bool  // Standard deviation below 1e-8 times the mean absolute value
+}
+
+impl InputQuality {
+    /// Whether no problem was found.
+    pub fn is_ok(&self) -> bool {
+        *self == InputQuality::default()
+    }
+
+    /// Describes each problem found, with the likely fix.
+    pub fn messages(&self) -> Vec<&'static str> {
+        let mut messages = Vec::new();
+        if self.non_finite {
+            messages.push("series holds NaN or infinite values: drop or impute them before fitting");
+        }
+        if self.too_short {
+            messages.push("series has fewer than three observations: too short to estimate any model");
+        }
+        if self.constant {
+            messages.push("series is constant: the variance is zero and the criteria are undefined");
+        }
+        if self.low_variance {
+            messages.push("series is nearly constant: rescale or center it before fitting");
+        }
+        messages
+    }
+
+    /// Whether the series cannot be fitted at all: non-finite, too short or constant. A
+    /// nearly constant series is only warned about.
+    pub fn is_degenerate(&self) -> bool {
+        self.non_finite || self.too_short || self.constant
+    }
+
+    /// Logs each problem found as a warning.
+    pub fn warn(&self) {
+        for message in self.messages() {
+            tracing::warn!("{}", message);
+        }
+    }
+
+    /// Returns the first problem found as an error: `InvalidValue` for non-finite values,
+    /// `InsufficientData` for a too short series and `DegenerateInput` for a constant or
+    /// nearly constant one.
+    pub fn check(&self) -> Result<(), NefeleError> {
+        let message = match self.messages().first() {
+            Some(message) => message.to_string(),
+            None => return Ok(()),
+        };
+        Err(if self.non_finite {
+            NefeleError::InvalidValue(message)
+        } else if self.too_short {
+            NefeleError::InsufficientData(message)
+        } else {
+            NefeleError::DegenerateInput(message)
+        })
+    }
+}
+
+/// Checks a series for the problems which make a fit degenerate: non-finite values, fewer
+/// than three observations, constant values, or a standard deviation below `1e-8` times the
+/// mean absolute value. The `fit` methods run it and log the problems found as warnings;
+/// on a degenerate series they return early, leaving the model unfitted with NaN statistics.
+pub fn input_quality(data: &[f64]) -> InputQuality {
+    let non_finite = data.iter().any(|x| !x.is_finite());
+    let constant = !data.is_empty() && data.iter().all(|&x| x == data[0]);
+    let low_variance = !non_finite && !constant && data.len() > 1 && {
+        let n = data.len() as f64;
+        let mean = data.iter().sum::<f64>() / n;
+        let sd = (data.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+        sd <= 1e-8 * data.iter().map(|x| x.abs()).sum::<f64>() / n
+    };
+
+    InputQuality {
+        non_finite,
+        too_short: data.len() < 3,
+        constant,
+        low_variance
+    }
+}
+
+/// Lo-MacKinlay variance ratio test of the random walk hypothesis, with homoskedastic
+/// standard errors. Compares the variance of the `q`-period differences of `data` with `q`
+/// times the variance of the one-period differences: the ratio is close to 1 for a random
+/// walk, below 1 for mean reversion and above 1 for trending increments.
+/// Returns the variance ratio and the two-sided p-value of its standard normal statistic,
+/// `InvalidValue` if `q < 2` and `InsufficientData` unless there are more than `q` increments.
+pub fn variance_ratio_test(data: &[f64], q: usize) -> Result<(f64, f64), NefeleError> {
+    if q < 2 {
+        return Err(NefeleError::InvalidValue(format!("the holding period q must be at least 2, found {}", q)));
+    }
+    if data.len() <= q + 1 {
+        return Err(NefeleError::InsufficientData(format!(
+            "{} observations for a variance ratio over {} periods, at least {} needed", data.len(), q, q + 2
+        )));
+    }
+    let increments = diff(data, 1);
+    let n = increments.len() as f64;
+    let q_f = q as f64;
+    let mu = mean(&increments);
+
+    let var_1 = increments.iter().map(|r| (r - mu).powi(2)).sum::<f64>() / (n - 1.0);
+
+    // overlapping q-period differences, with the unbiased normalization of Lo and MacKinlay
+    let m = q_f * (n - q_f + 1.0) * (1.0 - q_f / n);
+    let var_q = (q..data.len())
+        .map(|t| (data[t] - data[t - q] - q_f * mu).powi(2))
+        .sum::<f64>() / m;
+
+    let ratio = var_q / var_1;
+    let z = (ratio - 1.0) / (2.0 * (2.0 * q_f - 1.0) * (q_f - 1.0) / (3.0 * q_f * n)).sqrt();
+
+    // P(|Z| > z) = Q(1/2, z^2 / 2)
+    Ok((ratio, gamma_q(0.5, z * z / 2.0)))
+}
+
+/// Fractionally differences `x` with parameter `d`, using all the available binomial weights.
+pub fn diffseries_full(x: &[f64], d: f64) -> Vec<f64> {
+    diffseries(x, d, x.len())
 }
 
-pub fn diffseries(x: &Vec<f64>, d: f64) -> Vec<f64> {
+/// Fractionally differences the centered `x` with parameter `d`, truncating the
+/// expansion of `(1 - L)^d` after `truncation` binomial weights.
+///
+/// The weights follow the recursion `w_0 = 1`, `w_k = w_{k-1} * (k - 1 - d) / k`,
+/// so that `y_t = sum_{k=0}^{min(t, truncation)} w_k * x_{t-k}`. Fewer weights trade
+/// accuracy on long-memory series for speed.
+pub fn diffseries(x: &[f64], d: f64, truncation: usize) -> Vec<f64> {
     if d == 0.{
         return x.to_owned()
     } else {
@@ -43,217 +249,584 @@ pub fn diffseries(x: &Vec<f64>, d: f64) -> Vec<f64> {
             x_centered[i] = x[i] - x_mean;
         }
         
-        let mut pi = vec![0.0; n];
-        pi[0] = -d;
-        for k in 1..n {
+        // pi[k] holds the weight w_{k+1}
+        let truncation = cmp::min(truncation, n - 1);
+        let mut pi = vec![0.0; truncation];
+        if truncation > 0 {
+            pi[0] = -d;
+        }
+        for k in 1..truncation {
             pi[k] = pi[k - 1] * (k as f64 - d) / (k as f64 + 1.0);
         }
         let mut ydiff = vec![0.0; n];
         ydiff[0] = x_centered[0];
         for i in 1..n {
             let mut sum = 0.0;
-            for j in (0..i).rev() {
+            for j in (i.saturating_sub(truncation)..i).rev() {
                 sum += pi[i - j - 1] * x_centered[j];
             }
             ydiff[i] = x_centered[i] + sum;
-            // println!("{:?}", x[i]);
         }
         ydiff
     }
 }
 
-pub fn residuals(
-    x: &Vec<f64>,
-    intercept: f64,
-    phi: &Vec<f64>,
-    theta: &Vec<f64>,
-) -> Vec<f64> {
-    let zero: f64 = From::from(0.0);
-
-    let mut residuals: Vec<f64> = Vec::new();
-    for _ in 0..phi.len() {
-        residuals.push(zero);
-    }
-    for t in phi.len()..x.len() {
-        let mut xt: f64 = intercept;
-        for j in 0..phi.len() {
-            xt += phi[j] * x[t - j - 1];
+/// Maps partial autocorrelations in (-1, 1) to the coefficients of a stationary AR model,
+/// through the Durbin-Levinson recursion `phi_kj = phi_{k-1,j} - r_k phi_{k-1,k-j}`, `phi_kk = r_k`.
+pub fn ar_from_pacf(partial: &[f64]) -> Vec<f64> {
+    let mut phi: Vec<f64> = Vec::with_capacity(partial.len());
+    for (k, &r) in partial.iter().enumerate() {
+        let previous = phi.clone();
+        for j in 0..k {
+            phi[j] = previous[j] - r * previous[k - 1 - j];
         }
-        for j in 0..cmp::min(theta.len(), t) {
-            xt += theta[j] * residuals[t - j - 1];
-        }
-        residuals.push(x[t] - xt);
+        phi.push(r);
     }
-
-    residuals
+    phi
 }
 
-pub fn diff(x: &Vec<f64>, d: usize) -> Vec<f64> {
-    let mut y: Vec<f64> = x.to_vec();
-    let len = y.len();
-    for s in 0..d {
-        for i in 1..len - s {
-            // we iterate backwards through the vector to avoid cloning
-            y[len - i] = y[len - i] - y[len - i - 1];
+/// Maps the coefficients of an AR model to its partial autocorrelations through the
+/// step-down recursion, the inverse of [`ar_from_pacf`]. The model is stationary if and
+/// only if they all lie in (-1, 1).
+pub fn pacf_from_ar(phi: &[f64]) -> Vec<f64> {
+    let mut phi = phi.to_vec();
+    let mut partial = vec![0.0; phi.len()];
+    for k in (0..phi.len()).rev() {
+        let r = phi[k];
+        partial[k] = r;
+        let previous = phi.clone();
+        for j in 0..k {
+            phi[j] = (previous[j] + r * previous[k - 1 - j]) / (1.0 - r * r);
         }
+        phi.truncate(k);
     }
-    y.drain(0..d);
-    y
+    partial
 }
 
-pub fn inverse_diff(x: &Vec<f64>, d: usize) -> Vec<f64> {
-    let y: Vec<f64> = vec![0.0; d];
-    let mut cum: Vec<f64> = vec![y, x.to_vec()].concat().to_vec();
-
-    for _ in 0..d {
-        cum = cumsum(cum);
+/// Maps the AR parameters searched by an optimizer to AR coefficients. With
+/// `enforce_stationarity` the parameters are unconstrained reals mapped to partial
+/// autocorrelations by `tanh`, and then to the coefficients of a stationary model by
+/// [`ar_from_pacf`]; otherwise they are the coefficients themselves.
+pub fn ar_from_optimizer(params: &[f64], enforce_stationarity: bool) -> Vec<f64> {
+    if enforce_stationarity {
+        let partial: Vec<f64> = params.iter().map(|u| u.tanh()).collect();
+        ar_from_pacf(&partial)
+    } else {
+        params.to_vec()
     }
-    cum
 }
 
-pub fn cumsum(x: Vec<f64>) -> Vec<f64> {
-    let mut y: Vec<f64> = Vec::new();
-    if x.len() < 2 {
-        y.push(From::from(0));
-        return y;
+/// Inverse of [`ar_from_optimizer`]. The partial autocorrelations of a non-stationary
+/// model are clamped to +-0.99, so that it is mapped to a nearby stationary one.
+pub fn ar_to_optimizer(phi: &[f64], enforce_stationarity: bool) -> Vec<f64> {
+    if enforce_stationarity {
+        pacf_from_ar(phi)
+            .iter()
+            .map(|r| if r.is_finite() { r.clamp(-0.99, 0.99).atanh() } else { 0.0 })
+            .collect()
+    } else {
+        phi.to_vec()
     }
-    y.push(x[0]);
-    for i in 1..x.len() {
-        y.push(y[i - 1] + x[i]);
+}
+
+/// OptimizerConfig struct collects the settings of the L-BFGS minimizer used by the CSS, ML
+/// and exponential smoothing fits. The defaults reproduce the plain
+/// `lbfgs().with_max_iterations(200)`.
+///
+/// ARMA objectives are often flat along a ridge of near-cancelling AR and MA roots: there,
+/// a smaller `epsilon` keeps the minimizer from stopping early, and a larger `max_linesearch`
+/// or a smaller `linesearch_gtol` (a more exact line search) helps it make progress.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OptimizerConfig {
+    pub max_iterations: usize,  // Maximum number of iterations
+    pub epsilon: f64,           // Convergence tolerance on the scaled gradient norm
+    pub linesearch_ftol: f64,   // Sufficient decrease (Armijo) tolerance of the line search
+    pub linesearch_gtol: f64,   // Curvature (Wolfe) tolerance of the line search
+    pub max_linesearch: usize   // Maximum number of trials per line search
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        OptimizerConfig {
+            max_iterations: 200,
+            epsilon: 1e-5,
+            linesearch_ftol: 1e-4,
+            linesearch_gtol: 0.9,
+            max_linesearch: 40
+        }
     }
-    y
 }
 
+impl OptimizerConfig {
+    /// Minimizes the objective from the starting point `x` with an L-BFGS minimizer built
+    /// from these settings, as `liblbfgs`'s `minimize` does.
+    pub(crate) fn minimize<E, G>(&self, x: &mut [f64], eval_fn: E, prgr_fn: G) -> anyhow::Result<Report>
+    where
+        E: FnMut(&[f64], &mut [f64]) -> anyhow::Result<f64>,
+        G: FnMut(&Progress) -> bool,
+    {
+        lbfgs()
+            .with_max_iterations(self.max_iterations)
+            .with_epsilon(self.epsilon)
+            .with_linesearch_ftol(self.linesearch_ftol)
+            .with_linesearch_gtol(self.linesearch_gtol)
+            .with_max_linesearch(self.max_linesearch)
+            .minimize(x, eval_fn, prgr_fn)
+    }
+}
 
+/// VarianceEstimator represents the ways the innovation variance of a fitted ARMA model is estimated.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VarianceEstimator {
+    /// Sum of squared AR-only residuals `x_t - sum_j phi_j x_{t-j}` over `n`, warm-up
+    /// included: ignores the intercept and the MA part, so it is only a rough plug-in.
+    /// It does not enter the AIC and BIC, which use the full ARMA residuals.
+    #[default]
+    PlugIn,
+    /// `rss / n` of the full ARMA residuals after the warm-up, the value that maximizes the
+    /// Gaussian likelihood, so that AIC and BIC agree with `-2 ln L + penalty` as reported
+    /// by standard software.
+    ML
+}
 
-pub fn pacf(
+/// Innovation variance of an ARMA model fitted to `x`, as selected by `estimator`, together
+/// with the residual sum of squares and the number of observations the information
+/// criteria are computed from. Whatever the estimator, the criteria use the full ARMA
+/// residuals after the `max(p, q)` warm-up, so that adding MA terms can lower them.
+pub fn innovation_variance(
     x: &Vec<f64>,
-    max_lag: Option<usize>,
-) -> Vec<f64> {
-    // get autocorrelations
-    let rho = acf(x, max_lag, false);
-    let cov0 = acf(x, Some(0), true)[0];
-    pacf_rho_cov0(&rho, cov0, max_lag)
+    intercept: f64,
+    phi: &[f64],
+    theta: &[f64],
+    estimator: VarianceEstimator,
+) -> (f64, f64, usize) {
+    let start = warm_up(phi.len(), theta.len()).min(x.len());
+    let rss = residuals(x, intercept, phi, theta).iter().skip(start).fold(0.0, |acc, e| acc + e * e);
+    let n = x.len() - start;
+    let sigma_squared = match estimator {
+        VarianceEstimator::PlugIn => compute_variance(x, &phi.to_vec()),
+        VarianceEstimator::ML => rss / n as f64,
+    };
+    (sigma_squared, rss, n)
 }
 
-pub fn acf(
-    x: &Vec<f64>,
-    max_lag: Option<usize>,
-    covariance: bool,
+/// Forecasts `horizon` steps ahead of an ARMA model, setting the future innovations to zero.
+///
+/// When the mean `mu = intercept / (1 - sum(phi))` exists, the recursion runs on the
+/// deviations from it, which have no constant term: for a stationary model they decay like
+/// `|r|^h`, where `r` is the inverse root of the AR polynomial with the largest modulus, and
+/// the forecasts reach `mu` exactly instead of hovering a few ulps around it.
+pub fn forecast(
+    x: &[f64],
+    intercept: f64,
+    phi: &[f64],
+    theta: &[f64],
+    horizon: usize,
 ) -> Vec<f64> {
-    println!("{}", x.len());
-    let max_lag = match max_lag {
-        // if upper bound for max_lag is n-1
-        Some(max_lag) => cmp::min(max_lag, x.len() - 1),
-        None => x.len() - 1,
+    let mut errors = residuals(x, intercept, phi, theta);
+    let denominator = 1.0 - phi.iter().sum::<f64>();
+    let (mu, constant) = if intercept != 0.0 && denominator.abs() > f64::EPSILON {
+        (intercept / denominator, 0.0)
+    } else {
+        (0.0, intercept)
     };
-    let m = max_lag + 1;
+    let mut y: Vec<f64> = x.iter().map(|xt| xt - mu).collect();
 
-    let len_x_usize = x.len();
-    let len_x: f64 = From::from(len_x_usize as u32);
-    let sum: f64 = From::from(0.0);
+    for t in x.len()..(x.len() + horizon) {
+        let mut yt: f64 = constant;
+        for j in 0..phi.len() {
+            yt += phi[j] * y[t - j - 1];
+        }
+        for j in 0..cmp::min(theta.len(), t) {
+            yt += theta[j] * errors[t - j - 1];
+        }
+        y.push(yt);
+        errors.push(0.0);
+    }
 
-    let sum_x: f64 = x.iter().fold(sum, |sum, &xi| sum + xi);
-    let mean_x: f64 = sum_x / len_x;
+    y.split_off(x.len()).into_iter().map(|yt| yt + mu).collect()
+}
 
-    //let mut y: Vec<f64> = Vec::with_capacity(max_lag);
-    let mut y: Vec<f64> = vec![From::from(0.0); m];
+/// Integrates a forecast of the `d`-times differenced series back to the scale of `x`.
+pub fn undiff_forecast(x: &[f64], forecast: &[f64], d: usize) -> Vec<f64> {
+    // last value of each differenced series, from order 0 to d - 1
+    let last: Vec<f64> = (0..d).map(|k| *diff(x, k).last().unwrap()).collect();
 
-    for t in 0..m {
-        for i in 0..len_x_usize - t {
-            let xi = x[i] - mean_x;
-            let xi_t = x[i + t] - mean_x;
-            y[t] += (xi * xi_t) / len_x;
+    let mut y: Vec<f64> = forecast.to_vec();
+    for k in (0..d).rev() {
+        let mut level = last[k];
+        for yi in y.iter_mut() {
+            level += *yi;
+            *yi = level;
         }
-        // we need y[0] to calculate the correlations, so we set it to 1.0 at the end
-        if !covariance && t > 0 {
-            y[t] = y[t] / y[0];
-        }
-    }
-    if !covariance {
-        y[0] = From::from(1.0);
     }
     y
 }
 
-fn pacf_rho_cov0(
-    rho: &Vec<f64>,
-    cov0: f64,
-    max_lag: Option<usize>,
-) -> Vec<f64> {
-    let max_lag = match max_lag {
-        // if upper bound for max_lag is n-1
-        Some(max_lag) => cmp::min(max_lag, rho.len() - 1),
-        None => rho.len() - 1,
-    };
-    let m = max_lag + 1;
+/// Undoes the fractional differencing of [`diffseries_full`] on a forecast of the fractionally
+/// differenced series, returning the forecast on the scale of `x`. Each step solves
+/// `y_t = sum_{k=0}^{t} w_k (x_t - mean)` for `x_t`, feeding the previous forecasts back in.
+pub fn undiff_fractional_forecast(x: &[f64], forecast: &[f64], d: f64) -> Vec<f64> {
+    if d == 0. {
+        return forecast.to_vec();
+    }
+    let x_mean = x.iter().sum::<f64>() / x.len() as f64;
+    let n = x.len();
+    let total = n + forecast.len();
 
-    // build output vector
-    let mut y: Vec<f64> = Vec::new();
+    // binomial weights w_0, ..., w_{total - 1} of (1 - L)^d
+    let mut w = vec![1.0; total];
+    for k in 1..total {
+        w[k] = w[k - 1] * (k as f64 - 1.0 - d) / k as f64;
+    }
 
-    // calculate AR coefficients for each solution of order 1..max_lag
-    for i in 1..m {
-        let (coef, _var) = ar_dl_rho_cov(rho, cov0, Some(i));
-        // we now have a vector with i items, the last item is our partial correlation
-        y.push(coef[i - 1]);
+    let mut centered: Vec<f64> = x.iter().map(|xi| xi - x_mean).collect();
+    for (h, yh) in forecast.iter().enumerate() {
+        let t = n + h;
+        let past: f64 = (1..=t).map(|k| w[k] * centered[t - k]).sum();
+        centered.push(yh - past);
     }
-    y
+    centered.split_off(n).into_iter().map(|c| c + x_mean).collect()
+}
+
+/// Jacobian of the one-step residuals of an ARMA model with respect to its parameters, in
+/// the order intercept, AR then MA coefficients, by central differences. Row `t` holds the
+/// derivatives of residual `t`, zero over the warm-up. The gradient of the CSS objective is
+/// `2 J' e`, and `2 J' J` is its Gauss-Newton Hessian.
+pub(crate) fn residual_jacobian(
+    x: &[f64],
+    intercept: f64,
+    phi: &[f64],
+    theta: &[f64],
+) -> DMatrix<f64> {
+    let p = phi.len();
+    let parameters: Vec<f64> = std::iter::once(intercept).chain(phi.iter().cloned()).chain(theta.iter().cloned()).collect();
+    let residuals_at = |parameters: &[f64]| residuals(x, parameters[0], &parameters[1..p + 1], &parameters[p + 1..]);
+
+    let mut jacobian = DMatrix::<f64>::zeros(x.len(), parameters.len());
+    for j in 0..parameters.len() {
+        let step = 1e-6 * parameters[j].abs().max(1.0);
+        let mut up = parameters.clone();
+        let mut down = parameters.clone();
+        up[j] += step;
+        down[j] -= step;
+        let (up, down) = (residuals_at(&up), residuals_at(&down));
+        for t in 0..x.len() {
+            jacobian[(t, j)] = (up[t] - down[t]) / (2.0 * step);
+        }
+    }
+    jacobian
 }
 
-fn ar_dl_rho_cov(
-    rho: &Vec<f64>,
-    cov0: f64,
-    order: Option<usize>,
-) -> (Vec<f64>, f64) {
-    let order = match order {
-        Some(order) => cmp::min(order, rho.len() - 1),
-        None => rho.len() - 1,
+/// Conditional sum of squares of an ARMA model, the objective minimized by the CSS fits.
+pub fn css_objective(
+    x: &[f64],
+    intercept: f64,
+    phi: &[f64],
+    theta: &[f64],
+) -> f64 {
+    residuals(x, intercept, phi, theta).iter().map(|e| e * e).sum()
+}
+
+/// Periodogram of `x` at the Fourier frequencies `w_j = 2 pi j / n`, `j = 1, ..., (n - 1) / 2`:
+/// `I(w) = |sum_t (x_t - mean) e^{-i w t}|^2 / (2 pi n)`, computed by a direct Fourier sum in
+/// O(n^2) operations rather than by an FFT: ten times the data take a hundred times as long,
+/// which takes about a second from ten thousand observations on.
+/// Returns the frequencies and the ordinates.
+pub fn periodogram(x: &[f64]) -> (Vec<f64>, Vec<f64>) {
+    let n = x.len();
+    let mean_x = x.iter().sum::<f64>() / n as f64;
+    let frequencies: Vec<f64> = (1..=n.saturating_sub(1) / 2)
+        .map(|j| 2.0 * std::f64::consts::PI * j as f64 / n as f64)
+        .collect();
+
+    let ordinates = frequencies.iter()
+        .map(|&omega| {
+            let (re, im) = x.iter().enumerate().fold((0.0, 0.0), |(re, im), (t, xt)| {
+                let angle = omega * t as f64;
+                (re + (xt - mean_x) * angle.cos(), im - (xt - mean_x) * angle.sin())
+            });
+            (re * re + im * im) / (2.0 * std::f64::consts::PI * n as f64)
+        })
+        .collect();
+
+    (frequencies, ordinates)
+}
+
+/// Spectral density of an ARMA process at frequency `omega`:
+/// `f(w) = sigma^2 / (2 pi) |1 + sum_j theta_j e^{-i j w}|^2 / |1 - sum_j phi_j e^{-i j w}|^2`.
+pub fn spectral_density(phi: &[f64], theta: &[f64], sigma_squared: f64, omega: f64) -> f64 {
+    let transfer = |coefficients: &[f64], sign: f64| {
+        let sum = coefficients.iter()
+            .enumerate()
+            .fold(Complex::new(1.0, 0.0), |sum, (j, c)| {
+                let angle = omega * (j + 1) as f64;
+                sum + Complex::new(angle.cos(), -angle.sin()) * (sign * c)
+            });
+        sum.norm_sqr()
     };
+    sigma_squared / (2.0 * std::f64::consts::PI) * transfer(theta, 1.0) / transfer(phi, -1.0)
+}
 
-    // we need zero values more than once, so we'll use this helper var
-    let zero = 0.0;
-    let one = 1.0;
+/// Spectral entropy of `x`, the Shannon entropy of its periodogram normalized to sum to one,
+/// divided by its maximum `ln(m)` over the `m` Fourier frequencies: close to 0 for a series
+/// dominated by one periodicity, close to 1 for white noise. Returns NaN for a constant
+/// series or fewer than five observations.
+pub fn spectral_entropy(x: &[f64]) -> f64 {
+    let (_, ordinates) = periodogram(x);
+    let total: f64 = ordinates.iter().sum();
+    if ordinates.len() < 2 || total <= 0.0 {
+        return f64::NAN;
+    }
+    let entropy = ordinates.iter()
+        .map(|i| i / total)
+        .filter(|&p| p > 0.0)
+        .fold(0.0, |acc, p| acc - p * p.ln());
+    entropy / (ordinates.len() as f64).ln()
+}
 
-    // these vectors will hold the parameter values
-    let mut phi: Vec<Vec<f64>> = vec![Vec::new(); order + 1];
-    let mut var: Vec<f64> = Vec::new();
+/// Fourier frequency, in radians per observation, of the largest periodogram ordinate of
+/// `x`; the corresponding period is `2 pi / w`. Returns NaN for fewer than three observations.
+pub fn dominant_frequency(x: &[f64]) -> f64 {
+    let (frequencies, ordinates) = periodogram(x);
+    ordinates.iter()
+        .zip(frequencies.iter())
+        .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal))
+        .map_or(f64::NAN, |(_, &omega)| omega)
+}
+
+/// Gaussian log-likelihood of an ARMA model conditional on the first `max(p, q)`
+/// observations, with the innovation variance profiled out as `rss / n`:
+/// `-n/2 * (ln(2 pi sigma^2) + 1)`.
+pub fn gaussian_loglik(
+    x: &[f64],
+    intercept: f64,
+    phi: &[f64],
+    theta: &[f64],
+) -> f64 {
+    let residuals = residuals(x, intercept, phi, theta);
+    let start = warm_up(phi.len(), theta.len());
+    let n = (x.len() - start) as f64;
+
+    let rss: f64 = residuals.iter().skip(start).map(|e| e * e).sum();
+    let sigma_squared = rss / n;
+
+    -0.5 * n * ((2.0 * std::f64::consts::PI * sigma_squared).ln() + 1.0)
+}
 
-    // initialize zero-order estimates
-    phi[0].push(zero);
-    var.push(cov0);
+/// Exact Gaussian log-likelihood of an MA model with the innovation variance profiled out,
+/// computed by the innovations algorithm on the autocovariances `gamma_h = sum_j theta_j
+/// theta_{j+h}` (`theta_0 = 1`), which vanish beyond lag `q`. Unlike [`gaussian_loglik`] it
+/// conditions on nothing, so it is the same for an MA model and its non-invertible twins.
+pub fn ma_exact_loglik(x: &[f64], intercept: f64, theta: &[f64]) -> f64 {
+    let n = x.len();
+    let q = theta.len();
+    let mut coefficients = vec![1.0];
+    coefficients.extend_from_slice(theta);
+    let gamma: Vec<f64> = (0..=q)
+        .map(|h| (0..=q - h).map(|j| coefficients[j] * coefficients[j + h]).sum())
+        .collect();
 
-    for i in 1..order + 1 {
-        // first allocate values for the phi vector so we can use phi[i][i-1]
-        for _ in 0..i {
-            phi[i].push(zero);
+    // theta_{t, j} of the innovations algorithm, for j = 1..=q, and the scaled one-step variances
+    let mut weights: Vec<Vec<f64>> = vec![vec![0.0; q + 1]; n];
+    let mut v: Vec<f64> = vec![0.0; n];
+    let mut innovations: Vec<f64> = vec![0.0; n];
+    v[0] = gamma[0];
+    innovations[0] = x[0] - intercept;
+    for t in 1..n {
+        let first = t.saturating_sub(q);
+        for k in first..t {
+            let mut sum = gamma[t - k];
+            for i in first..k {
+                sum -= weights[k][k - i] * weights[t][t - i] * v[i];
+            }
+            weights[t][t - k] = sum / v[k];
         }
+        v[t] = gamma[0] - (first..t).map(|i| weights[t][t - i].powi(2) * v[i]).sum::<f64>();
+
+        let prediction: f64 = (1..=t.min(q)).map(|j| weights[t][j] * innovations[t - j]).sum();
+        innovations[t] = x[t] - intercept - prediction;
+    }
+
+    let sum_of_squares: f64 = innovations.iter().zip(&v).map(|(e, v)| e * e / v).sum();
+    let log_det: f64 = v.iter().map(|v| v.ln()).sum();
+    let sigma_squared = sum_of_squares / n as f64;
 
-        // estimate phi_ii, which is stored as phi[i][i-1]
-        // phi_i,i = rho(i) - sum_{k=1}^{n-1}(phi_{n-1,k} * rho(n-k) /
-        //  (1 - sum_{k=1}^{n-1}(phi_{n-1,k} * rho(k))
+    -0.5 * (n as f64 * ((2.0 * std::f64::consts::PI * sigma_squared).ln() + 1.0) + log_det)
+}
 
-        let mut num_sum = zero; // numerator sum
-        let mut den_sum = one; // denominator sum
+/// Returns the invertible MA coefficients with the same autocorrelations as `theta`: every
+/// inverse root of `1 + theta_1 z + ... + theta_q z^q` outside the unit circle is reflected
+/// to `1 / conj(root)`. The innovation variance of the reflected model is larger by the
+/// product of the squared moduli of the reflected roots.
+pub fn ma_invertible(theta: &[f64]) -> Vec<f64> {
+    let negated: Vec<f64> = theta.iter().map(|t| -t).collect();
+    let roots = ar_roots(&negated);
+    if roots.iter().all(|root| root.norm_sqr() <= 1.0) {
+        return theta.to_vec();
+    }
 
-        for k in 1..i {
-            let p = phi[i - 1][k - 1];
-            num_sum += p * rho[i - k];
-            den_sum += -p * rho[k];
+    // Multiply out prod_i (1 - root_i z) with the reflected roots
+    let mut polynomial = vec![Complex::new(1.0, 0.0)];
+    for root in roots {
+        let root = if root.norm_sqr() > 1.0 { root.conj().inv() } else { root };
+        let mut next = vec![Complex::new(0.0, 0.0); polynomial.len() + 1];
+        for (i, c) in polynomial.iter().enumerate() {
+            next[i] += c;
+            next[i + 1] -= c * root;
         }
+        polynomial = next;
+    }
+    polynomial[1..].iter().map(|c| c.re).collect()
+}
 
-        let phi_ii = (rho[i] - num_sum) / den_sum;
-        phi[i][i - 1] = phi_ii;
+/// Initial guess of AR(`p`) coefficients: the partial autocorrelations of `data` up to lag
+/// `p`. Falls back to zeros, with a warning, on an empty or constant series, whose
+/// autocorrelations are undefined.
+pub fn initial_ar_from_pacf(data: &[f64], p: usize) -> Vec<f64> {
+    if p == 0 {
+        return Vec::new();
+    }
+    match try_acf(data, Some(p), false) {
+        Ok(rho) => {
+            let cov0 = acf(data, Some(0), true)[0];
+            let mut phi = pacf_rho_cov0(&rho, cov0, Some(p));
+            phi.resize(p, 0.0);
+            phi
+        }
+        Err(e) => {
+            tracing::warn!("{}; starting the AR coefficients at zero", e);
+            vec![0.0; p]
+        }
+    }
+}
 
-        var.push(var[i - 1] * (one - phi_ii * phi_ii));
+/// Initial guess of MA(`q`) coefficients matching the sample autocovariances of `residuals`,
+/// usually the data filtered by the AR part: the innovations algorithm is run on the sample
+/// autocovariances up to lag `m = max(q, min(20, n / 4))` and its coefficients of order `m`
+/// at lags 1 to `q`, made invertible, are returned (Brockwell and Davis, section 5.1.3).
+/// Falls back to zeros on a constant or too short series.
+pub fn initial_ma_from_acf(residuals: &[f64], q: usize) -> Vec<f64> {
+    let n = residuals.len();
+    if q == 0 || n <= q {
+        return vec![0.0; q];
+    }
+    let m = cmp::max(q, cmp::min(20, n / 4)).min(n - 1);
+    let gamma = acf(residuals, Some(m), true);
+    if gamma[0] <= 0.0 {
+        return vec![0.0; q];
+    }
 
-        for k in 1..i {
-            phi[i][k - 1] = phi[i - 1][k - 1] - phi[i][i - 1] * phi[i - 1][i - k - 1];
+    // weights[k][j] holds theta_{k, j} and v[k] the k-th one-step prediction variance
+    let mut weights: Vec<Vec<f64>> = vec![vec![0.0; m + 1]; m + 1];
+    let mut v: Vec<f64> = vec![0.0; m + 1];
+    v[0] = gamma[0];
+    for t in 1..=m {
+        for k in 0..t {
+            let mut sum = gamma[t - k];
+            for j in 0..k {
+                sum -= weights[k][k - j] * weights[t][t - j] * v[j];
+            }
+            weights[t][t - k] = sum / v[k];
         }
+        v[t] = gamma[0] - (0..t).map(|j| weights[t][t - j].powi(2) * v[j]).sum::<f64>();
+    }
+
+    let theta = &weights[m][1..=q];
+    if theta.iter().all(|t| t.is_finite()) {
+        ma_invertible(theta)
+    } else {
+        vec![0.0; q]
     }
+}
+
+/// Winsorizes the data, clamping every value below its `lower_pct` percentile and above its
+/// `upper_pct` percentile (both in `[0, 100]`, linearly interpolated) to those percentiles.
+/// This caps outliers instead of dropping them, but flattens the tails of the series, so
+/// any model fitted to the result sees a lighter-tailed distribution than the raw data.
+pub fn winsorize(data: &[f64], lower_pct: f64, upper_pct: f64) -> Vec<f64> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = data.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let percentile = |pct: f64| {
+        let position = pct.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64;
+        let below = position.floor() as usize;
+        let above = position.ceil() as usize;
+        sorted[below] + (position - below as f64) * (sorted[above] - sorted[below])
+    };
+    let (lower, upper) = (percentile(lower_pct), percentile(upper_pct));
+    data.iter().map(|&x| x.clamp(lower, upper)).collect()
+}
+
+/// Draws `n_boot` moving-block bootstrap replicates of the data from a generator seeded with
+/// `seed`. Each replicate joins blocks of `block_length` consecutive observations, starting at
+/// uniformly drawn positions, and is cut to the length of the data. Resampling whole blocks
+/// keeps the dependence within `block_length` lags, without assuming any model; refit a model
+/// on every replicate for intervals that are robust to misspecification.
+pub fn moving_block_bootstrap(data: &[f64], block_length: usize, n_boot: usize, seed: u64) -> Vec<Vec<f64>> {
+    let n = data.len();
+    let block_length = block_length.clamp(1, n.max(1));
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    (0..n_boot)
+        .map(|_| {
+            let mut replicate = Vec::with_capacity(n + block_length);
+            while replicate.len() < n {
+                let start = rng.gen_range(0..=n - block_length);
+                replicate.extend_from_slice(&data[start..start + block_length]);
+            }
+            replicate.truncate(n);
+            replicate
+        })
+        .collect()
+}
+
+/// Shifts the series `k` steps forward, so that element `t` holds `x[t - k]`; the first `k`
+/// elements, which have no lagged value, are `None`.
+pub fn lag(x: &[f64], k: usize) -> Vec<Option<f64>> {
+    (0..x.len())
+        .map(|t| t.checked_sub(k).map(|s| x[s]))
+        .collect()
+}
+
+/// Shifts the series `k` steps backward, so that element `t` holds `x[t + k]`; the last `k`
+/// elements, which have no lead value, are `None`.
+pub fn lead(x: &[f64], k: usize) -> Vec<Option<f64>> {
+    (0..x.len())
+        .map(|t| x.get(t + k).copied())
+        .collect()
+}
+
+/// Builds the design matrix of the given `lags` of the series: row `i` holds
+/// `x[t - lags[0]], x[t - lags[1]], ...` for `t = max_lag + i`, so it has one row per
+/// observation with every lag available, `x.len() - max_lag` in total.
+pub fn lag_matrix(x: &[f64], lags: &[usize]) -> DMatrix<f64> {
+    let max_lag = lags.iter().copied().max().unwrap_or(0);
+    let rows = x.len().saturating_sub(max_lag);
+    DMatrix::from_fn(rows, lags.len(), |i, j| x[max_lag + i - lags[j]])
+}
 
-    (phi[order].clone(), var[order])
+/// Computes the log-returns `ln(p_t / p_{t-1})` of a price series, which must be positive.
+pub fn log_returns(prices: &[f64]) -> Result<Vec<f64>, NefeleError> {
+    if let Some(price) = prices.iter().find(|&&p| p <= 0.0 || p.is_nan()) {
+        return Err(NefeleError::InvalidValue(format!("prices must be positive, found {}", price)));
+    }
+    Ok(prices.windows(2).map(|w| (w[1] / w[0]).ln()).collect())
 }
 
+/// Converts log-returns back to price levels starting after `last_price`, the inverse of
+/// [`log_returns`]: `p_t = last_price * exp(r_1 + ... + r_t)`.
+pub fn prices_from_returns(returns: &[f64], last_price: f64) -> Vec<f64> {
+    let mut log_price = last_price.ln();
+    returns.iter()
+        .map(|r| {
+            log_price += r;
+            log_price.exp()
+        })
+        .collect()
+}
 
 pub fn mean(x: &Vec<f64>) -> f64 {
     let zero: f64 = From::from(0_i32);
@@ -291,14 +864,742 @@ pub fn closest_integer(x: f64) -> usize {
     }
 }
 
-pub fn compute_aic(n: usize, residual_sum_of_squares: f64, p: usize) -> f64 {
-    let k = p; // Number of parameters
-    let aic = 2.0 * k as f64 + n as f64 * (residual_sum_of_squares / n as f64).ln();
-    aic
+/// Akaike Information Criterion of a Gaussian model fitted to `n` observations with
+/// residual sum of squares `rss` and `k` free parameters, intercept and innovation variance
+/// included: `AIC = n ln(rss / n) + 2k`, up to an additive constant depending only on `n`.
+pub fn aic(n: usize, rss: f64, k: usize) -> f64 {
+    n as f64 * (rss / n as f64).ln() + 2.0 * k as f64
+}
+
+/// Small-sample corrected AIC: `AICc = AIC + 2k (k + 1) / (n - k - 1)`. See [`aic`].
+/// It is infinite when `n <= k + 1`, where the correction is undefined.
+pub fn aicc(n: usize, rss: f64, k: usize) -> f64 {
+    if n <= k + 1 {
+        return f64::INFINITY;
+    }
+    aic(n, rss, k) + 2.0 * (k * (k + 1)) as f64 / (n - k - 1) as f64
+}
+
+/// Bayesian (Schwarz) Information Criterion: `BIC = n ln(rss / n) + k ln(n)`. See [`aic`].
+pub fn bic(n: usize, rss: f64, k: usize) -> f64 {
+    n as f64 * (rss / n as f64).ln() + k as f64 * (n as f64).ln()
+}
+
+/// Hannan-Quinn Information Criterion: `HQIC = n ln(rss / n) + 2k ln(ln(n))`. See [`aic`].
+pub fn hqic(n: usize, rss: f64, k: usize) -> f64 {
+    n as f64 * (rss / n as f64).ln() + 2.0 * k as f64 * (n as f64).ln().ln()
 }
 
-pub fn compute_bic(n: usize, residual_sum_of_squares: f64, p: usize) -> f64 {
-    let k = p; // Number of parameters
-    let bic = n as f64 * (residual_sum_of_squares / n as f64).ln() + k as f64 * (n as f64).ln();
-    bic
+/// OrderCriterion is an information criterion minimized by the `autofit` methods, evaluated on
+/// the `n` residuals of a candidate with residual sum of squares `rss` and `k` free parameters,
+/// the coefficients, the innovation variance and any estimated intercept, as [`aic`].
+/// The criterion enums of the models and [`Criterion`](crate::forecaster::Criterion)
+/// implement it; implement it on a custom type to select orders with another penalty.
+pub trait OrderCriterion {
+    fn evaluate(&self, n: usize, rss: f64, k: usize) -> f64;
+}
+
+/// Coefficient of determination of the one-step predictions of a model with `k` estimated
+/// coefficients: `R^2 = 1 - rss / tss`, where the total sum of squares is that of the
+/// mean-only model, together with the adjusted `1 - (1 - R^2) (n - 1) / (n - k - 1)`. The
+/// residuals are aligned with `x`, and the first `skip` warm-up observations are left out.
+/// Both are NaN when no observation is left, and the adjusted one when `n <= k + 1`, which
+/// leaves no degrees of freedom.
+pub fn r_squared(x: &[f64], residuals: &[f64], skip: usize, k: usize) -> (f64, f64) {
+    let x = &x[skip.min(x.len())..];
+    let residuals = &residuals[skip.min(residuals.len())..];
+    let n = x.len();
+
+    let mean_x = x.iter().sum::<f64>() / n as f64;
+    let tss: f64 = x.iter().map(|xi| (xi - mean_x).powi(2)).sum();
+    let rss: f64 = residuals.iter().map(|e| e * e).sum();
+
+    let r_squared = 1.0 - rss / tss;
+    let adjusted = if n > k + 1 {
+        1.0 - (1.0 - r_squared) * (n - 1) as f64 / (n - k - 1) as f64
+    } else {
+        f64::NAN
+    };
+    (r_squared, adjusted)
+}
+
+/// Builds the companion matrix of an AR process with coefficients `phi`, whose first
+/// row holds the coefficients and whose subdiagonal is one.
+pub fn companion_matrix(phi: &[f64]) -> DMatrix<f64> {
+    let p = phi.len();
+    let mut companion = DMatrix::zeros(p, p);
+    for (j, &coef) in phi.iter().enumerate() {
+        companion[(0, j)] = coef;
+    }
+    for i in 1..p {
+        companion[(i, i - 1)] = 1.0;
+    }
+    companion
+}
+
+/// Computes the eigenvalues of the companion matrix, i.e. the inverse roots of the AR
+/// polynomial `1 - phi_1 z - ... - phi_p z^p`. The process is stationary if and only if
+/// all of them lie strictly inside the unit circle.
+pub fn ar_roots(phi: &[f64]) -> Vec<Complex<f64>> {
+    if phi.is_empty() {
+        return Vec::new();
+    }
+    companion_matrix(phi).complex_eigenvalues().iter().cloned().collect()
+}
+
+/// Builds the `n x period` matrix of seasonal indicators, where row `t` has a one in
+/// column `t % period` and zeros elsewhere.
+pub fn seasonal_dummies(n: usize, period: usize) -> DMatrix<f64> {
+    DMatrix::from_fn(n, period, |t, s| if t % period == s { 1.0 } else { 0.0 })
+}
+
+/// Removes a fixed additive seasonal pattern by regressing the data on the seasonal
+/// dummies through OLS. Returns the residual series and the `period` seasonal factors,
+/// so that `data[t] = residual[t] + factors[t % period]`, or `InvalidValue` if `period` is
+/// zero and `InsufficientData` if the data do not cover a full period.
+pub fn deseasonalize(data: &[f64], period: usize) -> Result<(Vec<f64>, Vec<f64>), NefeleError> {
+    if period == 0 {
+        return Err(NefeleError::InvalidValue("seasonal period must be positive".to_string()));
+    }
+    if data.len() < period {
+        return Err(NefeleError::InsufficientData(format!(
+            "{} observations do not cover a period of {}", data.len(), period
+        )));
+    }
+    let x = seasonal_dummies(data.len(), period);
+    let y = na::DVector::from_column_slice(data);
+
+    // OLS: with every season observed, X'X is diagonal with positive entries
+    let xtx = x.transpose() * &x;
+    let xty = x.transpose() * &y;
+
+    let factors = xtx
+        .cholesky()
+        .ok_or_else(|| NefeleError::InsufficientData("a season is never observed".to_string()))?
+        .solve(&xty);
+
+    let residual: Vec<f64> = data
+        .iter()
+        .enumerate()
+        .map(|(t, &value)| value - factors[t % period])
+        .collect();
+
+    Ok((residual, factors.iter().cloned().collect()))
+}
+
+/// Natural logarithm of the gamma function, through the Lanczos approximation.
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEF: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for (j, c) in COEF.iter().enumerate() {
+        series += c / (x + 1.0 + j as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Regularized upper incomplete gamma function `Q(a, x) = 1 - P(a, x)`, computed with the
+/// series expansion of `P` for `x < a + 1` and with a continued fraction otherwise.
+pub fn gamma_q(a: f64, x: f64) -> f64 {
+    const MAX_ITER: usize = 500;
+    const EPS: f64 = 1e-14;
+
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let ln_prefactor = -x + a * x.ln() - ln_gamma(a);
+
+    if x < a + 1.0 {
+        // series for P(a, x)
+        let mut ap = a;
+        let mut del = 1.0 / a;
+        let mut sum = del;
+        for _ in 0..MAX_ITER {
+            ap += 1.0;
+            del *= x / ap;
+            sum += del;
+            if del.abs() < sum.abs() * EPS {
+                break;
+            }
+        }
+        1.0 - sum * ln_prefactor.exp()
+    } else {
+        // modified Lentz's method for the continued fraction of Q(a, x)
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..MAX_ITER {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let del = d * c;
+            h *= del;
+            if (del - 1.0).abs() < EPS {
+                break;
+            }
+        }
+        ln_prefactor.exp() * h
+    }
+}
+
+/// Newey-West (Bartlett kernel) estimate of the long-run variance of `x`,
+/// `gamma_0 + 2 sum_{k=1}^{lags} (1 - k / (lags + 1)) gamma_k`, robust to autocorrelation
+/// up to `lags`. See [`long_run_variance`].
+pub fn newey_west_variance(x: &[f64], lags: usize) -> f64 {
+    long_run_variance(x, lags, Kernel::Bartlett)
+}
+
+/// Kernel represents the lag windows of the long-run variance estimators.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kernel {
+    Bartlett,           // Triangular weights 1 - z, zero from z = 1 on (Newey-West)
+    QuadraticSpectral   // Andrews' quadratic spectral weights, over all lags
+}
+
+impl Kernel {
+    /// Weight of the autocovariance at the normalized lag `z = k / (lags + 1)`.
+    fn weight(&self, z: f64) -> f64 {
+        match self {
+            Kernel::Bartlett => (1.0 - z).max(0.0),
+            Kernel::QuadraticSpectral => {
+                let a = 6.0 * std::f64::consts::PI * z / 5.0;
+                25.0 / (12.0 * (std::f64::consts::PI * z).powi(2)) * (a.sin() / a - a.cos())
+            }
+        }
+    }
+}
+
+/// Long-run variance of `x`, `2 pi f(0)` where `f` is its spectral density, estimated as
+/// `gamma_0 + 2 sum_k w(k / (lags + 1)) gamma_k` with the lag window `w` of `kernel`: the
+/// Bartlett window stops after `lags`, the quadratic spectral one weighs every lag with
+/// `lags + 1` as bandwidth. For white noise it is close to the ordinary variance.
+pub fn long_run_variance(x: &[f64], lags: usize, kernel: Kernel) -> f64 {
+    let mean_x = x.iter().sum::<f64>() / x.len() as f64;
+    let max_lag = match kernel {
+        Kernel::Bartlett => lags.min(x.len().saturating_sub(1)),
+        Kernel::QuadraticSpectral => x.len().saturating_sub(1),
+    };
+    let bandwidth = (lags + 1) as f64;
+    let weighted: f64 = (1..=max_lag)
+        .map(|k| kernel.weight(k as f64 / bandwidth) * autocovariance_at(x, mean_x, k))
+        .sum();
+    autocovariance_at(x, mean_x, 0) + 2.0 * weighted
+}
+
+/// Survival function (upper tail probability) of the chi-squared distribution.
+pub fn chi_squared_sf(x: f64, dof: usize) -> f64 {
+    gamma_q(dof as f64 / 2.0, x / 2.0)
+}
+
+/// Quantile function of the standard normal distribution, through Acklam's rational
+/// approximation (relative error below 1.2e-9).
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02,
+        1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02,
+        6.680131188771972e+01, -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00,
+        -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    let p_low = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    if p < p_low {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - p_low {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -normal_quantile(1.0 - p)
+    }
+}
+
+/// Multiplies two polynomials given by their coefficients in increasing powers.
+pub fn poly_mul(a: &[f64], b: &[f64]) -> Vec<f64> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut c = vec![0.0; a.len() + b.len() - 1];
+    for (i, ai) in a.iter().enumerate() {
+        for (j, bj) in b.iter().enumerate() {
+            c[i + j] += ai * bj;
+        }
+    }
+    c
+}
+
+/// Computes the first `n` weights of the MA(infinity) representation of an ARMA model,
+/// `psi_0 = 1`, `psi_j = theta_j + sum_{i=1}^{min(j, p)} phi_i psi_{j-i}`.
+pub fn psi_weights(phi: &[f64], theta: &[f64], n: usize) -> Vec<f64> {
+    let mut psi: Vec<f64> = Vec::with_capacity(n);
+    for j in 0..n {
+        let mut psi_j = if j == 0 { 1.0 } else { theta.get(j - 1).cloned().unwrap_or(0.0) };
+        for i in 1..=cmp::min(j, phi.len()) {
+            psi_j += phi[i - 1] * psi[j - i];
+        }
+        psi.push(psi_j);
+    }
+    psi
+}
+
+/// Computes the theoretical autocorrelations of an ARMA model from lag 0 to `max_lag`.
+/// The autocovariances up to lag `p` solve the linear system
+/// `gamma_k - sum_i phi_i gamma_{|k-i|} = sum_{j=k}^{q} theta_j psi_{j-k}` (with `theta_0 = 1`),
+/// and the following ones continue the same recursion.
+pub fn arma_acf(phi: &[f64], theta: &[f64], max_lag: usize) -> Vec<f64> {
+    let gamma = arma_autocovariance(phi, theta, 1.0, max_lag);
+    let gamma0 = gamma[0];
+    gamma.iter().map(|g| g / gamma0).collect()
+}
+
+/// Computes the theoretical autocovariances of an ARMA model with innovation variance
+/// `sigma2` from lag 0 to `max_lag`, by the linear system and recursion of [`arma_acf`].
+pub fn arma_autocovariance(phi: &[f64], theta: &[f64], sigma2: f64, max_lag: usize) -> Vec<f64> {
+    let p = phi.len();
+    let psi = psi_weights(phi, theta, theta.len() + 1);
+    // right-hand side of the k-th equation, zero beyond lag q
+    let rhs = |k: usize| -> f64 {
+        (k..=theta.len())
+            .fold(0.0, |acc, j| acc + if j == 0 { 1.0 } else { theta[j - 1] } * psi[j - k])
+    };
+
+    let mut a = DMatrix::<f64>::identity(p + 1, p + 1);
+    for k in 0..=p {
+        for i in 1..=p {
+            a[(k, k.abs_diff(i))] -= phi[i - 1];
+        }
+    }
+    let b = na::DVector::from_iterator(p + 1, (0..=p).map(rhs));
+    let mut gamma: Vec<f64> = match a.lu().solve(&b) {
+        Some(solution) => solution.iter().cloned().collect(),
+        None => return vec![f64::NAN; max_lag + 1],
+    };
+
+    for k in p + 1..=max_lag {
+        let gamma_k = (1..=p).map(|i| phi[i - 1] * gamma[k - i]).sum::<f64>() + rhs(k);
+        gamma.push(gamma_k);
+    }
+    gamma.truncate(max_lag + 1);
+
+    gamma.iter().map(|g| g * sigma2).collect()
+}
+
+/// Stationary variance `gamma_0` of an ARMA process with innovation variance `sigma2`, from
+/// [`arma_autocovariance`]: `sigma2 / (1 - phi^2)` for an AR(1), `sigma2 (1 + sum(theta_j^2))`
+/// for a pure MA. Returns infinity for non-stationary AR coefficients, whose variance grows
+/// without bound, to compare against the sample variance of a long simulated path.
+pub fn process_variance(phi: &[f64], theta: &[f64], sigma2: f64) -> f64 {
+    if ar_roots(phi).iter().any(|root| root.norm_sqr() >= 1.0) {
+        return f64::INFINITY;
+    }
+    arma_autocovariance(phi, theta, sigma2, 0)[0]
+}
+
+/// Simulates an ARMA process whose initial state is drawn from its stationary distribution,
+/// so that the path is stationary from the first value and needs no burn-in. The state
+/// `(x_{-1}, ..., x_{-p}, e_{-1}, ..., e_{-q})` is multivariate normal with
+/// `Cov(x_{-i}, x_{-j}) = gamma_{|i-j|}`, `Cov(e_{-i}, e_{-j}) = sigma^2 delta_ij` and
+/// `Cov(x_{-i}, e_{-j}) = sigma^2 psi_{j-i}` for `j >= i` (zero otherwise), and is sampled
+/// through its Cholesky factor. The innovations have mean `error_mean`, so the process
+/// has mean `error_mean (1 + sum(theta)) / (1 - sum(phi))`. Returns an `InvalidValue` error
+/// for non-stationary AR coefficients.
+pub fn simulate_stationary<R: Rng>(
+    length: usize,
+    phi: &[f64],
+    theta: &[f64],
+    error_mean: f64,
+    error_variance: f64,
+    rng: &mut R,
+) -> Result<Vec<f64>, NefeleError> {
+    if ar_roots(phi).iter().any(|root| root.norm_sqr() >= 1.0) {
+        return Err(NefeleError::InvalidValue("the AR coefficients are not stationary".to_string()));
+    }
+    let (p, q) = (phi.len(), theta.len());
+    let sigma = error_variance.sqrt();
+    let mean = error_mean * (1.0 + theta.iter().sum::<f64>()) / (1.0 - phi.iter().sum::<f64>());
+
+    // Covariance of the initial state
+    let gamma = arma_autocovariance(phi, theta, error_variance, p);
+    let psi = psi_weights(phi, theta, q + 1);
+    let covariance = DMatrix::from_fn(p + q, p + q, |r, c| match (r < p, c < p) {
+        (true, true) => gamma[r.abs_diff(c)],
+        (false, false) => if r == c { error_variance } else { 0.0 },
+        (true, false) => if c - p >= r { error_variance * psi[c - p - r] } else { 0.0 },
+        (false, true) => if r - p >= c { error_variance * psi[r - p - c] } else { 0.0 },
+    });
+    let factor = covariance
+        .cholesky()
+        .ok_or_else(|| NefeleError::InvalidValue("degenerate stationary covariance".to_string()))?
+        .l();
+    let draws = na::DVector::from_fn(p + q, |_, _| rng.sample::<f64, _>(StandardNormal));
+    let state = factor * draws;
+
+    // centered observations and innovations, oldest first, with the initial state in front
+    let mut x: Vec<f64> = (0..p).rev().map(|i| state[i]).collect();
+    let mut e: Vec<f64> = (0..q).rev().map(|j| state[p + j]).collect();
+    for _ in 0..length {
+        let (tx, te) = (x.len(), e.len());
+        let innovation = sigma * rng.sample::<f64, _>(StandardNormal);
+        let mut xt = innovation;
+        for j in 0..p {
+            xt += phi[j] * x[tx - j - 1];
+        }
+        for j in 0..q {
+            xt += theta[j] * e[te - j - 1];
+        }
+        x.push(xt);
+        e.push(innovation);
+    }
+
+    Ok(x.split_off(p).into_iter().map(|xt| xt + mean).collect())
+}
+
+/// Computes the theoretical autocorrelations of an AR(p) model with innovation variance
+/// `sigma2` from lag 0 to `max_lag`, solving the Yule-Walker equations forward, together with
+/// the variance of the process `gamma_0 = sigma2 / (1 - sum_i phi_i rho_i)`. This inverts the
+/// Durbin-Levinson recursion: `AutoRegressive::from_acf` on the output gives back `phi`
+/// and `sigma2`.
+pub fn ar_to_acf(phi: &[f64], sigma2: f64, max_lag: usize) -> (Vec<f64>, f64) {
+    let rho = arma_acf(phi, &[], max_lag.max(phi.len()));
+    let explained = phi.iter().enumerate().fold(0.0, |acc, (i, p)| acc + p * rho[i + 1]);
+    let cov0 = sigma2 / (1.0 - explained);
+    (rho[..=max_lag].to_vec(), cov0)
+}
+
+/// Computes the theoretical partial autocorrelations of an ARMA model from lag 1 to
+/// `max_lag`, applying the Durbin-Levinson recursion to [`arma_acf`].
+pub fn arma_pacf(phi: &[f64], theta: &[f64], max_lag: usize) -> Vec<f64> {
+    let rho = arma_acf(phi, theta, max_lag);
+    pacf_rho_cov0(&rho, 1.0, Some(max_lag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_fractional_difference_is_the_first_difference() {
+        let x = vec![1.0, 3.0, 2.0, 5.0, 4.0, 7.0];
+        let mean_x = mean(&x);
+
+        let y = diffseries_full(&x, 1.0);
+        assert!((y[0] - (x[0] - mean_x)).abs() < 1e-12);
+        for (t, yt) in y.iter().enumerate().skip(1) {
+            assert!((yt - (x[t] - x[t - 1])).abs() < 1e-12, "t = {}: {}", t, yt);
+        }
+        assert_eq!(diffseries(&x, 1.0, 1), y);
+    }
+
+    #[test]
+    fn ar2_roots_match_the_factorization() {
+        // 1 - 0.75 z + 0.125 z^2 = (1 - 0.5 z) (1 - 0.25 z)
+        let companion = companion_matrix(&[0.75, -0.125]);
+        assert_eq!(companion, DMatrix::from_row_slice(2, 2, &[0.75, -0.125, 1.0, 0.0]));
+
+        let mut real: Vec<f64> = ar_roots(&[0.75, -0.125]).iter().map(|root| root.re).collect();
+        real.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!((real[0] - 0.25).abs() < 1e-12 && (real[1] - 0.5).abs() < 1e-12, "{:?}", real);
+
+        // 1 - z + 0.5 z^2 has the complex pair of inverse roots 0.5 +- 0.5i
+        for root in ar_roots(&[1.0, -0.5]) {
+            assert!((root.re - 0.5).abs() < 1e-12 && (root.im.abs() - 0.5).abs() < 1e-12, "{}", root);
+        }
+    }
+
+    #[test]
+    fn aicc_is_infinite_without_degrees_of_freedom() {
+        assert_eq!(aicc(3, 1.0, 3), f64::INFINITY);
+        assert_eq!(aicc(4, 1.0, 3), f64::INFINITY);
+        assert!((aicc(10, 10.0, 3) - (aic(10, 10.0, 3) + 24.0 / 6.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn deseasonalize_recovers_a_monthly_pattern() {
+        let pattern: Vec<f64> = (0..12).map(|s| (s as f64 - 5.5).powi(2)).collect();
+        let data: Vec<f64> = (0..120).map(|t| pattern[t % 12] + 0.1 * ((t * 7) % 5) as f64).collect();
+
+        let (residual, factors) = deseasonalize(&data, 12).unwrap();
+        for (s, factor) in factors.iter().enumerate() {
+            assert!((factor - pattern[s] - 0.2).abs() < 0.15, "season {}: {}", s, factor);
+        }
+        for (t, r) in residual.iter().enumerate() {
+            assert!((r + factors[t % 12] - data[t]).abs() < 1e-9);
+        }
+
+        assert!(matches!(deseasonalize(&data, 0), Err(NefeleError::InvalidValue(_))));
+        assert!(matches!(deseasonalize(&data[..5], 12), Err(NefeleError::InsufficientData(_))));
+    }
+
+    #[test]
+    fn css_objective_is_minimal_at_the_css_fit() {
+        use crate::arma::{ARMAMethod, ARMA};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let data = simulate_stationary(500, &[0.6], &[0.3], 0.0, 1.0, &mut rng).unwrap();
+        let mut model = ARMA::new();
+        model.fit(&data, 1, 1, ARMAMethod::CSS);
+
+        let (intercept, phi, theta) = (model.intercept, model.phi[0], model.theta[0]);
+        let fitted = css_objective(&data, intercept, &[phi], &[theta]);
+        for step in [-0.01, 0.01] {
+            assert!(css_objective(&data, intercept + step, &[phi], &[theta]) > fitted - 1e-6);
+            assert!(css_objective(&data, intercept, &[phi + step], &[theta]) > fitted - 1e-6);
+            assert!(css_objective(&data, intercept, &[phi], &[theta + step]) > fitted - 1e-6);
+        }
+        assert!(gaussian_loglik(&data, intercept, &[phi], &[theta]) > gaussian_loglik(&data, intercept, &[0.0], &[0.0]));
+    }
+
+    #[test]
+    fn variance_ratio_separates_random_walks_from_mean_reversion() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let steps = simulate_stationary(2000, &[], &[], 0.0, 1.0, &mut rng).unwrap();
+        let walk: Vec<f64> = steps.iter().scan(0.0, |level, step| { *level += step; Some(*level) }).collect();
+        let (ratio, p_value) = variance_ratio_test(&walk, 4).unwrap();
+        assert!((ratio - 1.0).abs() < 0.15 && p_value > 0.05, "{} {}", ratio, p_value);
+
+        let reverting = simulate_stationary(2000, &[0.5], &[], 0.0, 1.0, &mut rng).unwrap();
+        let (ratio, p_value) = variance_ratio_test(&reverting, 4).unwrap();
+        assert!(ratio < 0.6 && p_value < 0.01, "{} {}", ratio, p_value);
+
+        assert!(matches!(variance_ratio_test(&walk, 1), Err(NefeleError::InvalidValue(_))));
+        assert!(matches!(variance_ratio_test(&walk[..5], 4), Err(NefeleError::InsufficientData(_))));
+        assert!(variance_ratio_test(&walk[..6], 4).unwrap().0.is_finite());
+    }
+
+    #[test]
+    fn prices_round_trip_through_log_returns() {
+        let prices = vec![100.0, 101.5, 99.8, 102.3, 104.0, 103.1];
+
+        let returns = log_returns(&prices).unwrap();
+        assert_eq!(returns.len(), 5);
+        let recovered = prices_from_returns(&returns, prices[0]);
+        for (p, r) in prices[1..].iter().zip(&recovered) {
+            assert!((p - r).abs() < 1e-10, "{} vs {}", p, r);
+        }
+
+        for bad in [0.0, -1.0, f64::NAN] {
+            assert!(matches!(log_returns(&[100.0, bad, 101.0]), Err(NefeleError::InvalidValue(_))));
+        }
+    }
+
+    #[test]
+    fn criteria_match_their_formulas() {
+        // n ln(rss / n) = 100 ln 2
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-10;
+        assert!(close(aic(100, 200.0, 3), 75.31471805599453));
+        assert!(close(aicc(100, 200.0, 3), 75.56471805599453));
+        assert!(close(bic(100, 200.0, 3), 83.1302286139588));
+        assert!(close(hqic(100, 200.0, 3), 78.47779581084194));
+    }
+
+    #[test]
+    fn lags_and_leads_shift_the_series() {
+        let x = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(lag(&x, 1), [None, Some(1.0), Some(2.0), Some(3.0)]);
+        assert_eq!(lead(&x, 2), [Some(3.0), Some(4.0), None, None]);
+        assert_eq!(lag(&x, 0), x.map(Some));
+        assert!(lag(&x, 5).iter().all(|v| v.is_none()));
+
+        // rows t = 2, 3 of lags 1 and 2
+        let design = lag_matrix(&x, &[1, 2]);
+        assert_eq!(design, DMatrix::from_row_slice(2, 2, &[2.0, 1.0, 3.0, 2.0]));
+    }
+
+    #[test]
+    fn ar_to_acf_round_trips_through_durbin_levinson() {
+        // AR(1): rho_k = phi^k and gamma_0 = sigma2 / (1 - phi^2)
+        let (rho, cov0) = ar_to_acf(&[0.6], 1.0, 3);
+        assert_eq!(rho.len(), 4);
+        for (k, r) in rho.iter().enumerate() {
+            assert!((r - 0.6_f64.powi(k as i32)).abs() < 1e-10);
+        }
+        assert!((cov0 - 1.0 / 0.64).abs() < 1e-10);
+
+        let (rho, cov0) = ar_to_acf(&[0.5, -0.3], 2.0, 10);
+        let (phi, sigma2, _) = ar_dl_rho_cov(&rho, cov0, Some(2));
+        assert!((phi[0] - 0.5).abs() < 1e-10 && (phi[1] + 0.3).abs() < 1e-10);
+        assert!((sigma2 - 2.0).abs() < 1e-10);
+        let (again, _) = ar_to_acf(&phi, sigma2, 10);
+        for (a, r) in again.iter().zip(&rho) {
+            assert!((a - r).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn block_bootstrap_replicates_keep_length_and_variance() {
+        let mut rng = StdRng::seed_from_u64(24);
+        let data = simulate_stationary(500, &[0.6], &[], 0.0, 1.0, &mut rng).unwrap();
+        let variance = |x: &[f64]| {
+            let m = x.iter().sum::<f64>() / x.len() as f64;
+            x.iter().map(|v| (v - m).powi(2)).sum::<f64>() / x.len() as f64
+        };
+
+        let replicates = moving_block_bootstrap(&data, 20, 200, 7);
+        assert_eq!(replicates.len(), 200);
+        assert!(replicates.iter().all(|r| r.len() == data.len()));
+        let average = replicates.iter().map(|r| variance(r)).sum::<f64>() / 200.0;
+        assert!((average / variance(&data) - 1.0).abs() < 0.1, "{} vs {}", average, variance(&data));
+
+        // same seed, same replicates; a single block spanning the data is the data itself
+        assert_eq!(moving_block_bootstrap(&data, 20, 3, 7), replicates[..3]);
+        assert_eq!(moving_block_bootstrap(&data, 1000, 1, 7)[0], data);
+    }
+
+    #[test]
+    fn random_walk_is_differenced_once_with_the_evidence() {
+        let mut rng = StdRng::seed_from_u64(25);
+        let steps = simulate_stationary(500, &[], &[], 0.0, 1.0, &mut rng).unwrap();
+        let walk: Vec<f64> = steps.iter().scan(0.0, |level, step| { *level += step; Some(*level) }).collect();
+
+        let advice = suggest_differencing(&walk, 2).unwrap();
+        assert_eq!(advice.d, 1);
+        assert_eq!(advice.stages.len(), 2);
+        let (_, stat, critical) = advice.stages[0];
+        assert!(stat > critical, "{} {}", stat, critical);
+        let (d, stat, critical) = advice.stages[1];
+        assert!(d == 1 && stat < critical, "{} {}", stat, critical);
+        assert!(advice.rationale.contains("d = 1 is suggested"), "{}", advice.rationale);
+
+        // too few observations left to test the differenced series
+        assert!(matches!(suggest_differencing(&walk[..5], 2), Err(NefeleError::InsufficientData(_))));
+        // stages past the first rejection are never tested
+        assert_eq!(suggest_differencing(&walk, 500).unwrap().d, 1);
+        assert!(matches!(adf(&walk[..4], 1, Regression::Constant), Err(NefeleError::InsufficientData(_))));
+        assert!(suggest_differencing(&walk[..20], 1).is_ok());
+    }
+
+    #[test]
+    fn sinusoid_has_low_entropy_at_its_frequency() {
+        // period 12 on a whole number of periods, so that 2 pi / 12 is a Fourier frequency
+        let omega = 2.0 * std::f64::consts::PI / 12.0;
+        let sinusoid: Vec<f64> = (0..240).map(|t| (omega * t as f64).sin()).collect();
+        assert!(spectral_entropy(&sinusoid) < 0.05, "{}", spectral_entropy(&sinusoid));
+        assert!((dominant_frequency(&sinusoid) - omega).abs() < 1e-12);
+
+        let mut rng = StdRng::seed_from_u64(35);
+        let noise = simulate_stationary(240, &[], &[], 0.0, 1.0, &mut rng).unwrap();
+        assert!(spectral_entropy(&noise) > 0.8, "{}", spectral_entropy(&noise));
+        assert!(spectral_entropy(&[1.0; 20]).is_nan());
+    }
+
+    #[test]
+    fn input_quality_flags_each_degenerate_class() {
+        let good = input_quality(&[1.0, 3.0, 2.0, 5.0]);
+        assert!(good.is_ok() && good.check().is_ok());
+
+        let non_finite = input_quality(&[1.0, f64::NAN, 2.0, 4.0]);
+        assert!(non_finite.non_finite && non_finite.is_degenerate());
+        assert!(matches!(non_finite.check(), Err(NefeleError::InvalidValue(_))));
+
+        let too_short = input_quality(&[1.0, 2.0]);
+        assert!(too_short.too_short && too_short.is_degenerate());
+        assert!(matches!(too_short.check(), Err(NefeleError::InsufficientData(_))));
+
+        let constant = input_quality(&[4.0; 10]);
+        assert!(constant.constant && !constant.low_variance && constant.is_degenerate());
+        assert!(matches!(constant.check(), Err(NefeleError::DegenerateInput(_))));
+
+        // nearly constant: warned about, but still fitted
+        let nearly: Vec<f64> = (0..10).map(|t| 1e6 + 1e-4 * (t % 2) as f64).collect();
+        let low_variance = input_quality(&nearly);
+        assert!(low_variance.low_variance && !low_variance.is_degenerate());
+        assert!(matches!(low_variance.check(), Err(NefeleError::DegenerateInput(_))));
+        assert_eq!(low_variance.messages().len(), 1);
+    }
+
+    #[test]
+    fn simulated_variance_matches_the_process_variance() {
+        assert!((process_variance(&[0.6], &[], 2.0) - 2.0 / 0.64).abs() < 1e-10);
+        assert!((process_variance(&[], &[0.5, -0.3], 1.0) - 1.34).abs() < 1e-10);
+        assert_eq!(process_variance(&[1.0], &[], 1.0), f64::INFINITY);
+
+        let mut rng = StdRng::seed_from_u64(39);
+        let path = simulate_stationary(20000, &[0.5, -0.2], &[0.4], 0.0, 1.5, &mut rng).unwrap();
+        let m = path.iter().sum::<f64>() / path.len() as f64;
+        let sample = path.iter().map(|x| (x - m).powi(2)).sum::<f64>() / path.len() as f64;
+        let theory = process_variance(&[0.5, -0.2], &[0.4], 1.5);
+        assert!((sample / theory - 1.0).abs() < 0.05, "{} vs {}", sample, theory);
+    }
+
+    #[test]
+    fn long_horizon_forecasts_reach_the_mean() {
+        // AR(1) with intercept 2 and phi 0.9: mean 20, reached without drifting around it
+        let history = [25.0, 31.0, 12.0];
+        let mean = 2.0 / (1.0 - 0.9);
+        let path = forecast(&history, 2.0, &[0.9], &[], 10000);
+        assert_eq!(path[9999], mean);
+        assert!(path.windows(2).all(|w| (w[1] - mean).abs() <= (w[0] - mean).abs()));
+
+        // ARMA(2, 1) with oscillating roots
+        let path = forecast(&history, 1.0, &[0.5, -0.4], &[0.3], 10000);
+        assert_eq!(path[9999], 1.0 / (1.0 - (0.5 - 0.4)));
+    }
+
+    #[test]
+    fn long_run_variance_of_white_noise_is_its_variance() {
+        let mut rng = StdRng::seed_from_u64(40);
+        let noise = simulate_stationary(5000, &[], &[], 0.0, 2.0, &mut rng).unwrap();
+        let variance = long_run_variance(&noise, 0, Kernel::Bartlett);
+        for kernel in [Kernel::Bartlett, Kernel::QuadraticSpectral] {
+            let lrv = long_run_variance(&noise, 10, kernel);
+            assert!((lrv / variance - 1.0).abs() < 0.2, "{:?}: {} vs {}", kernel, lrv, variance);
+        }
+
+        // AR(1) with unit innovations: 2 pi f(0) = 1 / (1 - phi)^2
+        let ar1 = simulate_stationary(20000, &[0.5], &[], 0.0, 1.0, &mut rng).unwrap();
+        let lrv = long_run_variance(&ar1, 40, Kernel::Bartlett);
+        assert!((lrv - 4.0).abs() < 0.6, "{}", lrv);
+        assert_eq!(newey_west_variance(&ar1, 40), lrv);
+    }
+
+    #[test]
+    fn adjusted_r_squared_is_nan_without_degrees_of_freedom() {
+        let x = [1.0, 3.0, 2.0, 5.0, 4.0];
+        let residuals = [0.0, 0.5, -0.5, 0.3, -0.2];
+        let (r2, adjusted) = r_squared(&x, &residuals, 1, 1);
+        assert!(r2.is_finite() && adjusted.is_finite() && adjusted < r2);
+
+        assert!(r_squared(&x, &residuals, 1, 3).1.is_nan());
+        let (r2, adjusted) = r_squared(&x, &residuals, 10, 1);
+        assert!(r2.is_nan() && adjusted.is_nan());
+    }
 }